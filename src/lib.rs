@@ -3,12 +3,75 @@
 //! A tiny file-backed key-value bucket (binary `Vec<u8>` key/value) with simple operations.
 //!
 //! **Supported operations**
+//! - `new` / `from_file` / `from_file_single` / `open_single` / `open_with_flags` (Unix)
 //! - `set` / `get` / `delete`
-//! - `set_many`
-//! - `list` / `list_next` (pagination)
-//! - `find_next`
+//! - `set_with_retry` / `delete_with_retry` (lock contention)
+//! - `get_value` (value only) / `get_many`
+//! - `set_many` / `set_group` (shared key prefix)
+//! - `bulk_load` (fill an empty bucket)
+//! - `list` / `list_next` (pagination) / `list_sizes` (keys only)
+//! - `find_next` / `find_next_many`
 //! - `delete_to`
 //! - `list_lock_delete` (queue-like pop)
+//! - `find_suffix`
+//! - `get_prefix_first` / `get_many_prefix`
+//! - `compact` / `compacted_size` / `compact_step` / `clone_to`
+//! - `open_cached` (resident block list)
+//! - `check_overlaps`
+//! - `find_next_iter` (lazy `find_next`) / `snapshot_iter`
+//! - `list_region`
+//! - `new_with_access` (`AccessMode::SingleWriterMultiReader`)
+//! - `read_raw` / `blocks_in_range` (debugging)
+//! - `fragment_count` / `free_list_summary`
+//! - `validate_key` / `repair`
+//! - `lookup_cost` (`sum_key` collisions)
+//! - `encode_u64_key` / `decode_u64_key` (numeric keys)
+//! - `touch` (reserve a key)
+//! - `group_by_prefix`
+//! - `open_worm` (write-once, append-only)
+//! - `export_key_hashes` / `key_hash` (membership sync)
+//! - `format_version` / `needs_migration` / `migrate_v0_to_v1`
+//! - `find_value_prefix` (full scan over values)
+//! - `shard_into` (split into `n` files)
+//! - `reopen` (file replaced on disk)
+//! - `set_meta` / `get_meta` (bucket-level metadata)
+//! - `bounds` (first/last block offsets)
+//! - `open_strict` (reject duplicate keys)
+//! - `content_hash` (layout-independent)
+//! - `push` (auto-incrementing `u64` keys)
+//! - `open_bounded` (FIFO item cap)
+//! - `BucketReader` (read-only over any `Read + Seek`)
+//! - `move_to` (move a key to another bucket)
+//! - `find_duplicates`
+//! - `export_raw` / `import_raw` (portable backup stream)
+//! - `health` (all integrity checks in one report)
+//! - `prefetch_range` (warm the page cache for a key range)
+//! - `rotate` (archive the file and start empty)
+//! - `prefix_reclaimable`
+//! - `set_at` (patch part of a value in place)
+//! - `iter_physical` (file offset order)
+//! - `approx_len` (cheap entry count estimate)
+//! - `compact_key` (move one block into an earlier hole)
+//! - `freeze` (read-only snapshot for lock-free readers)
+//! - `filter_iter` (lazy predicate scan)
+//! - `open_capped` (maximum file size, `StorageFull` on overflow)
+//! - `debug_blocks` (human-readable block list dump)
+//! - `set_at_offset` (caller-chosen block offset)
+//! - `count_by_key_len`
+//! - `reserve_value` / `write_value` / `finalize_value` (stream a large value in)
+//! - `for_each_batch` (bounded-memory batches)
+//! - `rank` (position in list order)
+//! - `get_consistent` (retry a list caught mid-write)
+//! - `diff` (compare two buckets)
+//! - `trim_to_last` (keep the newest N)
+//! - `iter_decoded` (lazy value decoding)
+//! - `open_auto_reopen` (follow a file replaced on disk)
+//! - `open_lookup_cache` (remember where recently read keys are stored)
+//! - `open_size_classes` (power-of-two size-class allocator)
+//! - `open_aligned` (block starts rounded up to an alignment)
+//! - `open_with_buffers` (buffered list scans, compaction and bulk loads)
+//! - `open_with` (any combination of the open-time options)
+//! - `DeleteMode::Tombstone` / `list_tombstones` (deletes that leave a tombstone)
 //!
 //! The storage is backed by a **single file** (example: `data.db`).
 //!
@@ -51,11 +114,21 @@
 //! ## Behavior notes
 //! - `get(key)` returns `(Vec::new(), Vec::new())` if the key does not exist.
 //! - Keys and values are stored as raw bytes (`Vec<u8>`).
+//! - The header stores a checksum of the block list; write operations return
+//!   `ErrorKind::InvalidData` instead of overwriting a corrupt list.
+//! - An empty file is a new bucket; a file shorter than the header makes write
+//!   operations return `ErrorKind::UnexpectedEof`.
 //!
 
-use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Result, Seek, SeekFrom::Start, Write};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{
+    BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Result, Seek, SeekFrom,
+    SeekFrom::{End, Start},
+    Write,
+};
+use std::thread;
+use std::time::Duration;
 
 /// Public API implemented by [`Bucket`].
 ///
@@ -69,6 +142,8 @@ pub trait Trait {
         Self: Sized;
 
     /// Insert or update a key/value pair.
+    ///
+    /// If `key` already holds exactly `data`, nothing is written.
     fn set(&mut self, key: Vec<u8>, data: Vec<u8>) -> Result<()>;
 
     /// Get a value by key.
@@ -77,9 +152,13 @@ pub trait Trait {
     fn get(&mut self, key: Vec<u8>) -> (Vec<u8>, Vec<u8>);
 
     /// Delete an entry by key.
+    ///
+    /// Under [`DeleteMode::Tombstone`] the key is left in the block list as a tombstone.
     fn delete(&mut self, key: Vec<u8>) -> Result<()>;
 
     /// Insert multiple items in one call.
+    ///
+    /// A key repeated in `list_data` is written once, with its last value.
     fn set_many(&mut self, list_data: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()>;
 
     /// List up to `limit` items.
@@ -107,1397 +186,9326 @@ pub trait Trait {
 
     /// Read up to `limit` items and delete them (queue-like).
     fn list_lock_delete(&mut self, limit: u8) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
-}
 
-/// File-backed bucket storage.
-///
-/// This struct keeps **two independent file handles**:
-/// - `reader`: used for read/scan operations (list, get, find_next…)
-/// - `writer`: used for write operations (set, delete…)
-///
-/// Having separate handles helps avoid seek conflicts when reading and writing
-/// in the same process.
-///
-/// This design makes it easier to:
-/// - read sequentially while writes happen
-/// - avoid frequent seek jumps on a single handle
-/// - keep the code simple (no locking strategy inside the crate)
-pub struct Bucket {
-    /// File handle for read operations (scan / list / get).
-    pub(crate) reader: File,
+    /// Find up to `limit` items whose key ends with `suffix`.
+    ///
+    /// There is no suffix index: every key is read back from the file.
+    fn find_suffix(&mut self, suffix: Vec<u8>, limit: u8) -> Vec<(Vec<u8>, Vec<u8>)>;
 
-    /// File handle for write operations (append / update / delete).
-    pub(crate) writer: File,
-}
+    /// Get the first item (in list order) whose key starts with `prefix`.
+    ///
+    /// Keys are read until a match is found; only the matching value is read.
+    fn get_prefix_first(&mut self, prefix: Vec<u8>) -> Option<(Vec<u8>, Vec<u8>)>;
 
-const MAX_DIGIT_GROUP: u8 = 249;
-const START: u8 = 250;
-const SIZE_KEY: u8 = 251;
-const SUM_KEY: u8 = 252;
-const SUM_MD5: u8 = 253;
-const SIZE_DATA: u8 = 254;
-const END: u8 = 255;
-const FIRST_SIZE: usize = 128;
+    /// Rewrite the bucket without free space between blocks.
+    ///
+    /// The compacted bucket is written to `<path>.compact` with the original file's
+    /// permissions, synced, then renamed over the original file and the directory
+    /// synced, so a crash never leaves a half-compacted bucket behind.
+    ///
+    /// Other handles opened on the same path keep the old, unlinked file (and its lock)
+    /// until they [`Trait::reopen`]; writes made through them are lost. The writer lock
+    /// taken by `compact` is on the old file too, so it does not keep other processes
+    /// from opening and locking the new one.
+    /// Buckets built with `from_file` have no path and return `ErrorKind::Unsupported`.
+    fn compact(&mut self) -> Result<()>;
 
-#[derive(Clone)]
-struct Block {
-    pub start: usize,
-    pub size_key: usize,
-    pub sum_key: usize,
-    pub sum_md5: usize,
-    pub size_data: usize,
-}
+    /// Open a bucket at `path` and keep its parsed block list in memory.
+    ///
+    /// Reads are served from the resident list (and `get` from a `(size_key, sum_key)`
+    /// index) instead of reading the header and list on every call. Writes through this
+    /// bucket refresh it; writes made through other handles are not seen.
+    fn open_cached(path: String) -> Result<Self>
+    where
+        Self: Sized;
 
-const EMPTY_BLOCK: Block = Block {
-    start: 0,
-    size_key: 0,
-    sum_key: 0,
-    sum_md5: 0,
-    size_data: 0,
-};
+    /// Report every pair of blocks whose `[start, start + size_key + size_data)` ranges overlap.
+    ///
+    /// Overlapping blocks mean the block list is corrupt: reads of either key return mixed data.
+    fn check_overlaps(&mut self) -> Vec<(Block, Block)>;
 
-fn group_digits_to_vec(mut n: usize) -> Vec<u8> {
-    let mut digits = Vec::new();
-    while n > 0 {
-        digits.push((n % 10) as u8);
-        n /= 10;
-    }
-    digits.reverse();
+    /// Lazy version of [`Trait::find_next`] without a `limit`.
+    ///
+    /// Each call to `next()` reads one block, so callers can `take` or filter the tail
+    /// without buffering it.
+    fn find_next_iter(&mut self, key: Vec<u8>, only_after_key: bool) -> BlockIter<'_>;
 
-    let mut result = Vec::new();
-    let mut i = 0;
+    /// Insert multiple items whose keys are `prefix` + key, in one `set_many` call.
+    fn set_group(&mut self, prefix: Vec<u8>, list_data: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()>;
 
-    while i < digits.len() {
-        // nếu chữ số hiện tại là 0 -> push 0 và tiếp tục
-        if digits[i] == 0 {
-            result.push(0);
-            i += 1;
-            continue;
-        }
+    /// Byte range `(start, end)` of the block list in the file.
+    ///
+    /// `end` is exclusive and includes the trailing `END` marker.
+    fn list_region(&mut self) -> (usize, usize);
 
-        // thử gom 3 chữ số
-        if i + 2 < digits.len() {
-            let v = (digits[i] as u16) * 100 + (digits[i + 1] as u16) * 10 + (digits[i + 2] as u16);
+    /// Get only the value of `key`, or `None` if the key is not found.
+    ///
+    /// The key bytes are still compared to rule out metadata collisions, then the value
+    /// is read directly from `start + size_key`.
+    fn get_value(&mut self, key: Vec<u8>) -> Option<Vec<u8>>;
 
-            if v <= MAX_DIGIT_GROUP as u16 {
-                result.push(v as u8);
-                i += 3;
-                continue;
-            }
-        }
+    /// Get multiple keys at once; results follow the order of `list_key`.
+    ///
+    /// Repeated keys are looked up once and blocks are read in ascending file offset
+    /// order. Missing keys return `(Vec::new(), Vec::new())`, like [`Trait::get`].
+    fn get_many(&mut self, list_key: Vec<Vec<u8>>) -> Vec<(Vec<u8>, Vec<u8>)>;
 
-        // thử gom 2 chữ số
-        if i + 1 < digits.len() {
-            let v = digits[i] * 10 + digits[i + 1];
-            if v <= MAX_DIGIT_GROUP {
-                result.push(v);
-                i += 2;
-                continue;
-            }
-        }
+    /// File size the bucket would have right after [`Trait::compact`].
+    ///
+    /// Computed from the block list only; nothing is written.
+    fn compacted_size(&mut self) -> usize;
 
-        // fallback: 1 chữ số
-        result.push(digits[i]);
-        i += 1;
-    }
+    /// Open a bucket at `path` with the given [`AccessMode`].
+    fn new_with_access(path: String, access_mode: AccessMode) -> Result<Self>
+    where
+        Self: Sized;
 
-    result
-}
-#[cfg(test)]
-mod test_group_digits_to_vec {
-    use crate::group_digits_to_vec;
-    #[test]
-    fn test_group_digits_to_vec() {
-        let data = group_digits_to_vec(2502510011110001111);
-        assert_eq!(data, [25u8, 0, 25, 100, 111, 100, 0, 111, 1]);
-    }
-}
+    /// Like [`Trait::set`], but gives up with `ErrorKind::WouldBlock` if the file lock
+    /// is still held by another handle after `retries` attempts spaced by `backoff`.
+    fn set_with_retry(
+        &mut self,
+        key: Vec<u8>,
+        data: Vec<u8>,
+        retries: u32,
+        backoff: Duration,
+    ) -> Result<()>;
 
-fn digits_to_number(digits: &[u8]) -> usize {
-    let mut n: usize = 0;
-    for &x in digits {
-        let count = if x < 10 {
-            1
-        } else if x < 100 {
-            2
-        } else {
-            3
-        };
-        n = n * 10usize.pow(count) + x as usize;
-    }
-    n
-}
-#[cfg(test)]
-mod test_digits_to_number {
-    use crate::digits_to_number;
-    #[test]
-    fn test_digits_to_number() {
-        let data = digits_to_number(&[250u8, 25, 100, 111, 100, 0, 111, 1]);
-        assert_eq!(data, 2502510011110001111);
-        let data = digits_to_number(&[0u8, 250, 25, 100, 111, 100, 0, 111, 1]);
-        assert_eq!(data, 2502510011110001111);
-    }
-}
+    /// Like [`Trait::delete`], retrying the file lock like [`Trait::set_with_retry`].
+    fn delete_with_retry(&mut self, key: Vec<u8>, retries: u32, backoff: Duration) -> Result<()>;
 
-fn merge_vec(vec: &[Vec<u8>]) -> Vec<u8> {
-    let total: usize = vec.iter().map(|v| v.len()).sum();
-    let mut out = Vec::with_capacity(total);
-    for v in vec {
-        out.extend_from_slice(v);
-    }
-    out
-}
-#[cfg(test)]
-mod test_merge_vec {
-    use crate::merge_vec;
-    #[test]
-    fn test_merge_vec() {
-        let data = merge_vec(&[vec![8u8, 9, 66, 4], vec![1u8, 1, 11, 1]]);
-        assert_eq!(data, vec![8u8, 9, 66, 4, 1, 1, 11, 1]);
-    }
-}
+    /// Do part of a compaction: move at most `max_blocks` blocks down into free space.
+    ///
+    /// Returns `true` while more work remains. Once no free space is left between
+    /// blocks, the block list is moved right after the data and the file is truncated.
+    /// The bucket stays readable and writable between steps.
+    ///
+    /// A block is only copied into space that the block list on disk leaves free, so not
+    /// over the old place of a block moved earlier in the same step; one larger than the
+    /// hole below it is first moved past the block list. A crash before the list is
+    /// rewritten therefore loses no data. Regions held by
+    /// [`Trait::reserve_value`] are not moved, so pending handles stay valid.
+    fn compact_step(&mut self, max_blocks: usize) -> Result<bool>;
 
-fn pull_key(read: &mut File, info: &Block) -> Result<Vec<u8>> {
-    read.seek(Start(info.start as u64))?;
-    let mut found_key = vec![0u8; info.size_key];
-    read.read_exact(&mut found_key)?;
-    Ok(found_key)
-}
+    /// Read `len` raw bytes at `offset` of the file.
+    ///
+    /// Low-level escape hatch for inspection tools: it bypasses the block list entirely,
+    /// so the bytes may belong to a block, free space, the block list or the header.
+    fn read_raw(&mut self, offset: usize, len: usize) -> Result<Vec<u8>>;
 
-fn pull_data(read: &mut File, info: &Block) -> Result<(Vec<u8>, Vec<u8>)> {
-    read.seek(Start(info.start as u64))?;
-    let mut found_key = vec![0u8; info.size_key];
-    read.read_exact(&mut found_key)?;
-    let mut found_data = vec![0u8; info.size_data];
-    read.read_exact(&mut found_data)?;
-    Ok((found_key, found_data))
-}
+    /// Build a bucket from handles you already own.
+    ///
+    /// `reader` must be readable and `writer` writable; both must refer to the same file.
+    fn from_file(reader: File, writer: File) -> Self
+    where
+        Self: Sized;
 
-fn get_one_data(read: &mut File, list_block_data: Vec<u8>, key: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
-    let mut result: (Vec<u8>, Vec<u8>) = (Vec::new(), Vec::new());
-    {
-        let len_key = key.len();
-        let sum_key: usize = key.iter().map(|&x| x as usize).sum();
-        let sum_md5: usize = md5::compute(&key)
-            .to_vec()
-            .iter()
-            .map(|&x| x as usize)
-            .sum();
+    /// Build a bucket from a single read/write handle (duplicated with `try_clone`).
+    fn from_file_single(file: File) -> Result<Self>
+    where
+        Self: Sized;
 
-        let mut block_info = EMPTY_BLOCK;
-        let mut tmp_group: Vec<u8> = Vec::new();
-        for v in list_block_data {
-            match v {
-                START => {
-                    block_info.start = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SIZE_KEY => {
-                    block_info.size_key = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SUM_KEY => {
-                    block_info.sum_key = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SUM_MD5 => {
-                    block_info.sum_md5 = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SIZE_DATA => {
-                    block_info.size_data = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                    {
-                        if block_info.size_key == len_key
-                            && block_info.sum_key == sum_key
-                            && block_info.sum_md5 == sum_md5
-                        {
-                            let (found_key, found_data) = pull_data(read, &block_info)
-                                .unwrap_or_else(|_| (Vec::new(), Vec::new()));
-                            if found_key == key {
-                                // success
-                                result.0 = found_key;
-                                result.1 = found_data;
-                                break;
-                            }
-                        }
-                    }
-                    block_info = EMPTY_BLOCK;
-                }
-                END => {
-                    break;
-                }
-                _ => {
-                    tmp_group.push(v);
-                }
-            }
-        }
-    }
-    result
-}
+    /// Number of free-space holes between blocks (including the gap before the list).
+    ///
+    /// A hole is a whole gap between two live blocks, so neighbouring free regions, such
+    /// as two adjacent deleted blocks, count as one hole.
+    fn fragment_count(&mut self) -> usize;
 
-fn get_list_data(read: &mut File, list_block_data: Vec<u8>, limit: u8) -> Vec<(Vec<u8>, Vec<u8>)> {
-    let mut result: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
-    {
-        let mut block_info = EMPTY_BLOCK;
-        let mut tmp_group: Vec<u8> = Vec::new();
-        let mut current: u8 = 0;
-        for v in list_block_data {
-            if current >= limit {
-                break;
-            }
-            match v {
-                START => {
-                    block_info.start = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SIZE_KEY => {
-                    block_info.size_key = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SUM_KEY => {
-                    block_info.sum_key = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SUM_MD5 => {
-                    block_info.sum_md5 = digits_to_number(&tmp_group);
+    /// Fill an empty bucket with `list_data` in one pass.
+    ///
+    /// Blocks are written back to back from the start of the data region with no
+    /// free-space search, then the list and header are written once. Returns
+    /// `ErrorKind::InvalidInput` if the bucket already holds items. Keys are not
+    /// checked for duplicates.
+    fn bulk_load(&mut self, list_data: impl Iterator<Item = (Vec<u8>, Vec<u8>)>) -> Result<()>
+    where
+        Self: Sized;
+
+    /// Check that the stored key bytes match the `size_key` / `sum_key` / `sum_md5`
+    /// recorded for them in the block list.
+    ///
+    /// Blocks are located by reading every key of the same length, so a key whose sums
+    /// are corrupt is still found. Returns `Ok(false)` if the key is not found.
+    fn validate_key(&mut self, key: Vec<u8>) -> Result<bool>;
+
+    /// Write a compacted copy of this bucket to `dest` and open it.
+    ///
+    /// `self` is left untouched; the writer lock is held while the copy is written.
+    /// `dest` must not exist yet: an existing file (including this bucket's own file)
+    /// fails with `ErrorKind::AlreadyExists`. A copy that fails halfway is removed.
+    fn clone_to(&mut self, dest: String) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Blocks whose `start` falls within `[lo, hi)`, in block list order.
+    ///
+    /// Pairs with [`Trait::read_raw`] to map a suspicious byte range back to its blocks.
+    fn blocks_in_range(&mut self, lo: usize, hi: usize) -> Vec<Block>;
+
+    /// Number of blocks with the same length and `sum_key` as `key` that a lookup checks
+    /// before finding it (including the match), or all of them if the key is missing.
+    ///
+    /// A high number points at a `sum_key` collision hotspot.
+    fn lookup_cost(&mut self, key: Vec<u8>) -> usize;
+
+    /// Create `key` with an empty value if it is absent.
+    ///
+    /// Returns `true` if the key was created and `false` (leaving the value untouched)
+    /// if it already existed. The check and the write happen under one writer lock.
+    fn touch(&mut self, key: Vec<u8>) -> Result<bool>;
+
+    /// Group all items by the part of their key before the first `sep` byte.
+    ///
+    /// Keys without `sep` form a group of their own. Groups and their items follow block
+    /// list order, and items keep their full key.
+    fn group_by_prefix(&mut self, sep: u8) -> Vec<PrefixGroup>;
+
+    /// Open a bucket at `path` in write-once (WORM) mode.
+    ///
+    /// Every write is appended right after the existing data without searching for
+    /// free space, and `delete`, `delete_to` and `list_lock_delete` fail with
+    /// `ErrorKind::PermissionDenied`. Updating a key appends the new value; the old bytes
+    /// stay in the file.
+    fn open_worm(path: String) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Iterate over all items as of the moment of the call.
+    ///
+    /// The whole block list is parsed up front and held by the iterator for its lifetime,
+    /// so keys written or deleted afterwards (through other handles) don't change which
+    /// blocks are visited. Values are still read lazily: a block whose bytes are reused by
+    /// a later write is skipped, or read with its new contents if the key is unchanged.
+    fn snapshot_iter(&mut self) -> BlockIter<'_>;
+
+    /// [`key_hash`] of every stored key, in block list order.
+    ///
+    /// Only keys are read, so a remote side can diff membership without transferring
+    /// values.
+    fn export_key_hashes(&mut self) -> Vec<u64>;
+
+    /// On-disk format version of the header.
+    ///
+    /// `0` is the legacy header without a block list checksum, `1` the current one. An
+    /// empty file reports the current version.
+    fn format_version(&mut self) -> Result<u32>;
+
+    /// Whether the header is older than the format written by this version.
+    ///
+    /// Any write upgrades a legacy header. Unreadable headers return `false`.
+    fn needs_migration(&mut self) -> bool;
+
+    /// Find up to `limit` items whose value starts with `prefix`.
+    ///
+    /// This is a full scan: every value is read until `limit` matches are found, so it
+    /// is only suited to small buckets.
+    fn find_value_prefix(&mut self, prefix: Vec<u8>, limit: u8) -> Vec<(Vec<u8>, Vec<u8>)>;
+
+    /// Copy every item into one of `n` new buckets `{dest_prefix}{i}.db`, picked by
+    /// `hash(key) % n`, and return them opened.
+    ///
+    /// Each shard is written with [`Trait::bulk_load`], so existing non-empty shard files
+    /// make this fail with `ErrorKind::InvalidInput`. `self` is left untouched; the writer
+    /// lock is held while the shards are written.
+    fn shard_into(
+        &mut self,
+        n: usize,
+        dest_prefix: &str,
+        hash: impl Fn(&[u8]) -> u64,
+    ) -> Result<Vec<Self>>
+    where
+        Self: Sized;
+
+    /// Reopen both file handles from the path the bucket was opened from.
+    ///
+    /// Use this after the file was replaced on disk (for example renamed over by another
+    /// process): until then the handles keep reading the old file. Returns
+    /// `ErrorKind::Unsupported` for buckets built from file handles; on error the old
+    /// handles are kept. [`Trait::open_auto_reopen`] does this automatically.
+    fn reopen(&mut self) -> Result<()>;
+
+    /// Store a small named value in the header, outside the block list.
+    ///
+    /// Metadata never shows up in `list` or other key scans and is kept by `compact`.
+    /// All names and values share 48 bytes (each entry costs 2 extra bytes); a value
+    /// that doesn't fit returns `ErrorKind::InvalidInput`.
+    fn set_meta(&mut self, key: &str, value: &[u8]) -> Result<()>;
+
+    /// Get a value stored with [`Trait::set_meta`], or `None` if `key` was never set.
+    fn get_meta(&mut self, key: &str) -> Option<Vec<u8>>;
+
+    /// Smallest and largest block `start` offsets, or `None` if the bucket is empty.
+    ///
+    /// For log-style use: the blocks at these offsets are the physically first and last
+    /// items in the file.
+    fn bounds(&mut self) -> Option<(usize, usize)>;
+
+    /// Open a bucket at `path` and check that no key is stored in more than one block.
+    ///
+    /// Duplicate blocks shadow each other silently; this returns `ErrorKind::InvalidData`
+    /// instead. The check reads every key once, at open only.
+    fn open_strict(path: String) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Items whose key starts with each of `list_prefix`, up to `limit` per prefix.
+    ///
+    /// One scan serves all prefixes: results follow the order of `list_prefix`, and a key
+    /// matching several prefixes is returned in each of their groups.
+    fn get_many_prefix(
+        &mut self,
+        list_prefix: Vec<Vec<u8>>,
+        limit: u8,
+    ) -> Vec<Vec<(Vec<u8>, Vec<u8>)>>;
+
+    /// Hash of all `(key, value)` pairs that ignores block order and file layout.
+    ///
+    /// Buckets with the same logical contents hash equal, e.g. a fragmented bucket and
+    /// its compacted copy. Per-item hashes are combined with a wrapping sum.
+    fn content_hash(&mut self) -> u64;
+
+    /// Store `value` under the next sequence number, encoded with [`encode_u64_key`], and
+    /// return that number.
+    ///
+    /// Numbers start at 0 and never repeat, even after deletes: the next one is kept in the
+    /// `push` metadata entry (see [`Trait::get_meta`]), which is updated before the value
+    /// is written. The last number handed out is `u64::MAX - 1`; after it `push` fails
+    /// with `ErrorKind::StorageFull` and writes nothing.
+    fn push(&mut self, value: Vec<u8>) -> Result<u64>;
+
+    /// Open a bucket at `path` that keeps at most `max_entries` items.
+    ///
+    /// After a write that leaves more items than that, the oldest ones in block list order
+    /// are deleted (FIFO). Updating a key moves it to the end of the list, so it counts as
+    /// the newest write. There is no LRU policy: reads leave no trace in the file.
+    fn open_bounded(path: String, max_entries: usize) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// List up to `limit` keys with the size of their value, without reading values.
+    fn list_sizes(&mut self, limit: u8) -> Vec<(Vec<u8>, usize)>;
+
+    /// Open a bucket at `path`, passing `custom_flags` (e.g. `O_NOATIME`, `O_DIRECT`) to
+    /// `open(2)` for both handles. Unix only.
+    ///
+    /// The flags are platform-specific and not checked. `O_DIRECT` requires offsets, sizes
+    /// and buffers aligned to the device block size, which this crate does not do, so most
+    /// operations fail with `EINVAL` under it. `compact` and `reopen` open the file again
+    /// without the flags.
+    #[cfg(unix)]
+    fn open_with_flags(path: String, custom_flags: i32) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Remove every block whose stored key no longer matches its metadata (or cannot be
+    /// read) from the block list, and return how many were removed. Intact tombstones of
+    /// [`DeleteMode::Tombstone`] are kept.
+    ///
+    /// Good blocks stay where they are; the space of removed blocks becomes free. A list
+    /// that fails to load at all (checksum mismatch, truncated file) is returned as an
+    /// error instead.
+    fn repair(&mut self) -> Result<usize>;
+
+    /// Move `key` into `other`: read it here, `set` it there, then delete it here.
+    ///
+    /// Returns `false` if the key is absent. This is not atomic across the two files: a
+    /// crash or error between the steps can leave the key in both buckets, never in
+    /// neither.
+    fn move_to(&mut self, other: &mut Self, key: Vec<u8>) -> Result<bool>
+    where
+        Self: Sized;
+
+    /// Number, largest, smallest and total size of the free-space holes, as seen by the
+    /// allocator (the gap before the block list counts as a hole). Neighbouring free
+    /// regions form one hole, as in [`Trait::fragment_count`].
+    fn free_list_summary(&mut self) -> FreeListSummary;
+
+    /// [`Trait::find_next`] for several anchor keys, with results in `list_key` order.
+    ///
+    /// The block list is parsed once and blocks shared by overlapping windows are read
+    /// once. Each window matches what `find_next` returns for that key.
+    fn find_next_many(
+        &mut self,
+        list_key: Vec<Vec<u8>>,
+        limit: u8,
+        only_after_key: bool,
+    ) -> Vec<Vec<(Vec<u8>, Vec<u8>)>>;
+
+    /// Open a bucket at `path` through one read/write open of the file.
+    ///
+    /// Like [`Trait::from_file_single`], but keeps the path so `compact` and `reopen`
+    /// work. Reads and writes share one file offset and one open file description, so a
+    /// write is visible to the next read without reopening. `compact` and `reopen` switch
+    /// back to two separate handles.
+    fn open_single(path: String) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Every key held by more than one block, with its number of blocks.
+    ///
+    /// Keys are reported in the order their second copy appears in the block list. An
+    /// empty result means [`Trait::open_strict`] would accept the file.
+    fn find_duplicates(&mut self) -> Vec<(Vec<u8>, usize)>;
+
+    /// Stream every `(key, value)` to `w` in a format independent of the file layout.
+    ///
+    /// The stream is `BBKV\x01`, then per entry a `u32` key length, the key, a `u64`
+    /// value length and the value (little-endian), closed by a key length of `u32::MAX`.
+    /// Read it back with [`Trait::import_raw`].
+    fn export_raw<W: Write>(&mut self, w: W) -> Result<()>
+    where
+        Self: Sized;
+
+    /// Insert every entry of an [`Trait::export_raw`] stream, overwriting existing keys.
+    ///
+    /// Entries are set one at a time as they are read, so memory use is bounded by the
+    /// largest entry. A stream that ends before its end marker fails with
+    /// `ErrorKind::UnexpectedEof` after the entries read so far are stored.
+    fn import_raw<R: Read>(&mut self, r: R) -> Result<()>
+    where
+        Self: Sized;
+
+    /// Run every integrity check in one call.
+    ///
+    /// Never fails: a check that cannot run (unreadable header, legacy header without a
+    /// checksum) leaves its field at the default. See [`HealthReport::is_healthy`].
+    fn health(&mut self) -> HealthReport;
+
+    /// Read the blocks of every key in `[start, end)` so later `get`s hit the page cache.
+    ///
+    /// Keys compare byte-wise. Keys are read first to find the blocks, then the blocks
+    /// are read in file order; nothing is returned or cached by the bucket itself.
+    fn prefetch_range(&mut self, start: Vec<u8>, end: Vec<u8>) -> Result<()>;
+
+    /// Rename the bucket file to `archive_path` and continue on a fresh, empty file at the
+    /// original path.
+    ///
+    /// The rename happens under the writer lock, so every write made through `self` ends
+    /// up in exactly one of the two files. Other handles still open on the old file keep
+    /// reading and writing the archive until they [`Trait::reopen`].
+    fn rotate(&mut self, archive_path: String) -> Result<()>;
+
+    /// Total `size_key + size_data` of the blocks whose key starts with `prefix`.
+    ///
+    /// This is the space deleting those keys would free (before `compact`). Only keys
+    /// are read.
+    fn prefix_reclaimable(&mut self, prefix: Vec<u8>) -> usize;
+
+    /// Overwrite the bytes of the value of `key` starting at `offset` with `patch`.
+    ///
+    /// The value keeps its size and place in the file; only the patched bytes are
+    /// written. Returns `Ok(false)` if the key is not found, `ErrorKind::InvalidInput` if
+    /// `offset + patch.len()` exceeds the value, and `ErrorKind::PermissionDenied` in
+    /// WORM mode.
+    fn set_at(&mut self, key: Vec<u8>, offset: usize, patch: Vec<u8>) -> Result<bool>;
+
+    /// Iterate over all items in ascending file offset instead of block list order.
+    ///
+    /// Same items as [`Trait::snapshot_iter`], but the file is read front to back, which
+    /// suits readahead and spinning disks. Freed slots reused by later writes make the
+    /// two orders differ.
+    fn iter_physical(&mut self) -> BlockIter<'_>;
+
+    /// Estimated number of blocks, without parsing the block list.
+    ///
+    /// Reads the list size from the header and the first few entries of the list, and
+    /// divides. The result is exact when the list fits in the sample and otherwise drifts
+    /// with how much entry sizes vary (larger offsets and sizes take more digits), so
+    /// use it for dashboards, not for logic.
+    fn approx_len(&mut self) -> usize;
+
+    /// Move the block of `key` into the earliest free hole before it that fits.
+    ///
+    /// A one-key alternative to [`Trait::compact`] for tidying hot keys: the block is
+    /// copied first and the list updated after, so a crash in between leaves the key at
+    /// its old place. Returns `Ok(false)` if the key is not found or no earlier hole is
+    /// large enough, and `ErrorKind::PermissionDenied` in WORM mode.
+    fn compact_key(&mut self, key: Vec<u8>) -> Result<bool>;
+
+    /// Write a compacted, read-only snapshot to `<path>.frozen` and open it.
+    ///
+    /// The snapshot never changes after it is written, so any number of processes can
+    /// read it through [`BucketReader`] without locks while writers keep using this
+    /// bucket. It is written to `<path>.frozen.tmp` and renamed into place, so a new
+    /// `freeze` replaces the old snapshot atomically: readers that already opened the old
+    /// one keep reading it until they open the file again.
+    fn freeze(&mut self) -> Result<BucketReader<File>>;
+
+    /// Lazily yield the items for which `pred(key, value)` is true, in block list order.
+    ///
+    /// Blocks are read one per step like [`Trait::snapshot_iter`], so `take(n)` stops
+    /// reading the file once `n` items matched.
+    fn filter_iter<F: FnMut(&[u8], &[u8]) -> bool>(
+        &mut self,
+        pred: F,
+    ) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)>
+    where
+        Self: Sized;
+
+    /// Open a bucket at `path` whose file may not grow past `max_file_size` bytes.
+    ///
+    /// `set`, `set_many`, `touch`, `push` and `bulk_load` check the final file size (new
+    /// blocks plus the rewritten block list) before writing anything, and fail with
+    /// `ErrorKind::StorageFull` instead of growing the file. Writes that fit in freed
+    /// holes still succeed; deletes and `compact` make room.
+    fn open_capped(path: String, max_file_size: u64) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// One line per block list entry, for diagnosing corrupt files:
+    ///
+    /// `<index>: start=<n> size_key=<n> sum_key=<n> sum_md5=<n> size_data=<n> key=<hex>`
+    ///
+    /// A key that does not match its sums is followed by `(does not match sums)`; a key
+    /// that cannot be read is shown as `(unreadable)`.
+    fn debug_blocks(&mut self) -> String;
+
+    /// Write `key` and `value` as one block at file offset `start`, bypassing the
+    /// allocator.
+    ///
+    /// For callers running their own allocation policy. An existing block of `key` is
+    /// replaced. `start` may lie in a hole or past the data (the block list moves after
+    /// it); a block that would overlap another block or the header fails with
+    /// `ErrorKind::InvalidInput` and nothing is written.
+    fn set_at_offset(&mut self, key: Vec<u8>, value: Vec<u8>, start: usize) -> Result<()>;
+
+    /// Number of blocks whose key is `len` bytes long.
+    ///
+    /// Counted from the block list alone: no key is read, so corrupt keys and tombstones
+    /// are counted too.
+    fn count_by_key_len(&mut self, len: usize) -> usize;
+
+    /// Reserve a block for a `size`-byte value of `key`, to be written later in pieces.
+    ///
+    /// The region is taken from the allocator right away but stays invisible to reads:
+    /// fill it with [`Trait::write_value`], then [`Trait::finalize_value`] publishes it,
+    /// replacing any current value of `key`. Bytes never written read as whatever the
+    /// region held before. `compact_step`, `trim_to_last` and `open_bounded` eviction
+    /// leave the region in place, but `compact`, `repair`, `delete_to` and
+    /// `list_lock_delete` drop it and invalidate pending handles (using one fails with
+    /// `ErrorKind::InvalidInput`); the space of an abandoned reservation is freed by
+    /// `repair`.
+    fn reserve_value(&mut self, key: Vec<u8>, size: usize) -> Result<ValueHandle>;
+
+    /// Write `bytes` at `offset` into the value reserved by `handle`.
+    ///
+    /// Fails with `ErrorKind::InvalidInput` if the bytes don't fit in the reserved size.
+    fn write_value(&mut self, handle: &ValueHandle, offset: usize, bytes: &[u8]) -> Result<()>;
+
+    /// Publish the value reserved by `handle` under its key.
+    fn finalize_value(&mut self, handle: ValueHandle) -> Result<()>;
+
+    /// Call `f` with up to `batch_size` items at a time, in block list order.
+    ///
+    /// One buffer of `batch_size` items is reused for every batch, so memory stays
+    /// bounded however large the bucket is. Unreadable blocks are skipped, as in
+    /// [`Trait::snapshot_iter`]. A `batch_size` of 0 is treated as 1.
+    fn for_each_batch<F: FnMut(&[(Vec<u8>, Vec<u8>)])>(&mut self, batch_size: usize, f: F)
+    where
+        Self: Sized;
+
+    /// Zero-based position of `key` among the items in block list order, or `None` if
+    /// the key is not found.
+    ///
+    /// Counts the same items as `list_next`'s `skip`, so `list_next(1, rank)` returns
+    /// the key itself. Only keys are read.
+    fn rank(&mut self, key: Vec<u8>) -> Option<usize>;
+
+    /// [`Trait::get`] that waits out a block list caught mid-write.
+    ///
+    /// A reader without a lock (`AccessMode::WriteLock`) can read the header and list
+    /// while another process rewrites them; the checksum check then rejects the list and
+    /// `get` sees an empty bucket. This reloads the list up to 10 more times, 10 ms
+    /// apart, while it fails the checksum check. Returns `Ok(None)` if the key is not
+    /// found, and the error if the list still fails the checksum or fails to load for any
+    /// other reason. The cache of `open_cached` is bypassed.
+    fn get_consistent(&mut self, key: Vec<u8>) -> Result<Option<(Vec<u8>, Vec<u8>)>>;
+
+    /// Keys that differ between this bucket and `other`.
+    ///
+    /// Keys are matched by [`key_hash`] first and values are read only for keys present
+    /// on both sides, cheapest check (value size) first. Entries only in `self` and value
+    /// differences follow `self`'s block list order, then entries only in `other` follow
+    /// its order.
+    fn diff(&mut self, other: &mut Self) -> Vec<DiffEntry>
+    where
+        Self: Sized;
+
+    /// Keep only the last `n` blocks in block list order and delete the rest in one list
+    /// rewrite. Returns the number of blocks deleted.
+    ///
+    /// Only blocks whose key still matches the block list count towards `n`, so `n`
+    /// readable items remain when there were that many; a corrupt block among them is
+    /// kept, one before them is deleted.
+    ///
+    /// The one-shot form of [`Trait::open_bounded`]'s eviction: keys from `push` are in
+    /// list order, so this keeps the `n` most recent. Fails with
+    /// `ErrorKind::PermissionDenied` in WORM mode.
+    fn trim_to_last(&mut self, n: usize) -> Result<usize>;
+
+    /// Iterate over all items like [`Trait::snapshot_iter`], passing each value through
+    /// `decode` as it is read.
+    ///
+    /// Lets callers parse values (JSON, protobuf, fixed-width numbers, ...) inline
+    /// without this crate depending on a codec. Decoding errors are the closure's
+    /// business: return a `Result` or `Option` as `V` to surface them.
+    fn iter_decoded<V, F: Fn(&[u8]) -> V>(
+        &mut self,
+        decode: F,
+    ) -> impl Iterator<Item = (Vec<u8>, V)>
+    where
+        Self: Sized;
+
+    /// Open a bucket at `path` that follows the file when it is replaced on disk.
+    ///
+    /// Every method that takes the file lock, or would take it for reading under
+    /// `AccessMode::SingleWriterMultiReader`, first compares the file at `path` with the
+    /// open handles by device and inode and calls [`Trait::reopen`] if they differ. This
+    /// costs one `stat` per operation. Iterators are not checked. Replacement is only
+    /// detected on unix.
+    fn open_auto_reopen(path: String) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Open a bucket at `path` that remembers where the last `capacity` keys read with
+    /// [`Trait::get`] are stored.
+    ///
+    /// A hit reads the block directly, skipping the header and block list; the stored
+    /// key is still compared, and a mismatch falls back to a normal lookup. Every write
+    /// through this bucket (`set`, `delete`, `compact`, `reopen`, ...) empties the cache.
+    /// Like [`Trait::open_cached`], writes made through other handles are not seen: a
+    /// key moved by another handle is read from its old place until this bucket writes.
+    /// Returns `ErrorKind::InvalidInput` if `capacity` is 0.
+    fn open_lookup_cache(path: String, capacity: usize) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Open a bucket at `path` whose `set` picks free space by size class.
+    ///
+    /// Holes are grouped into power-of-two classes and a new block goes into the first
+    /// hole that fits in the smallest class holding one, instead of the smallest hole
+    /// overall. The search stops at the first fitting class, at the price of a looser fit
+    /// than the default best-fit. `set`, `touch`, `push` and `reserve_value` use it;
+    /// `set_many` keeps its own placement. The file format is unchanged.
+    fn open_size_classes(path: String) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Open a bucket at `path` whose blocks start at multiples of `alignment` bytes
+    /// (e.g. 512 or 4096).
+    ///
+    /// `set`, `set_many`, `touch`, `push`, `reserve_value` and `bulk_load` round each
+    /// new block's start up to the next boundary, and `compact`, `compact_step` and
+    /// `compact_key` keep blocks aligned when moving them. The padding is ordinary free
+    /// space between blocks, so the file format is unchanged and other handles read the
+    /// bucket normally. The key is aligned, not the value. `set_at_offset` writes where
+    /// it is told. `set_many` writes one key at a time under this option. Returns
+    /// `ErrorKind::InvalidInput` if `alignment` is 0.
+    fn open_aligned(path: String, alignment: usize) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Open a bucket at `path` with a `read_buf`-byte read buffer and a `write_buf`-byte
+    /// write buffer.
+    ///
+    /// The read buffer serves `list`, `list_next`, `find_next`, `export_raw` and the block
+    /// reads of `compact`, `clone_to` and `freeze`: blocks next to each other in the file
+    /// come from one read instead of one read per key and value. The write buffer holds
+    /// the blocks written by `bulk_load`, `compact`, `clone_to` and `freeze`, and is
+    /// flushed before the header is written. Other operations read and write the file
+    /// directly. Without this option scans are unbuffered and the write buffer is 8 KiB.
+    /// Returns `ErrorKind::InvalidInput` if either size is 0.
+    fn open_with_buffers(path: String, read_buf: usize, write_buf: usize) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Open a bucket at `path` with any combination of the open-time options.
+    ///
+    /// `Bucket::open_with(path, BucketOptions::default())` is [`Trait::new`]. Sizes and
+    /// capacities given as `Some(0)` fail with `ErrorKind::InvalidInput` before the file
+    /// is opened.
+    fn open_with(path: String, options: BucketOptions) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Keys deleted under [`DeleteMode::Tombstone`], up to `limit`, in delete order.
+    fn list_tombstones(&mut self, limit: u8) -> Vec<Vec<u8>>;
+}
+
+/// Read-only bucket over any `Read + Seek` source, such as a `Cursor` over a file image
+/// or a bucket embedded in a larger file.
+///
+/// Offsets are relative to the start of `read`. There is no locking and no cache: each
+/// call reads the header and block list again. A corrupt or truncated source reads as an
+/// empty bucket, like the read methods of [`Bucket`].
+pub struct BucketReader<R: Read + Seek> {
+    read: R,
+}
+
+impl<R: Read + Seek> BucketReader<R> {
+    /// Wrap `read`; nothing is read until the first call.
+    pub fn new(read: R) -> Self {
+        Self { read }
+    }
+
+    /// Give back the wrapped source.
+    pub fn into_inner(self) -> R {
+        self.read
+    }
+
+    fn load_list_config(&mut self) -> (usize, Vec<u8>) {
+        get_list_config(&mut self.read).unwrap_or_else(|_| (FIRST_SIZE, Vec::new()))
+    }
+
+    /// See [`Trait::get`].
+    pub fn get(&mut self, key: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
+        let (_, list_block_data) = self.load_list_config();
+        get_one_data(&mut self.read, list_block_data, key)
+    }
+
+    /// See [`Trait::get_value`].
+    pub fn get_value(&mut self, key: Vec<u8>) -> Option<Vec<u8>> {
+        let (_, list_block_data) = self.load_list_config();
+        get_value_data(&mut self.read, list_block_data, key)
+    }
+
+    /// See [`Trait::get_many`].
+    pub fn get_many(&mut self, list_key: Vec<Vec<u8>>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let (_, list_block_data) = self.load_list_config();
+        get_many_data(&mut self.read, list_block_data, list_key)
+    }
+
+    /// See [`Trait::list`].
+    pub fn list(&mut self, limit: u8) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let (_, list_block_data) = self.load_list_config();
+        get_list_data(&mut self.read, list_block_data, limit)
+    }
+
+    /// See [`Trait::list_next`].
+    pub fn list_next(&mut self, limit: u8, skip: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let (_, list_block_data) = self.load_list_config();
+        get_list_next_data(&mut self.read, list_block_data, limit, skip)
+    }
+
+    /// See [`Trait::find_next`].
+    pub fn find_next(
+        &mut self,
+        key: Vec<u8>,
+        limit: u8,
+        only_after_key: bool,
+    ) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let (_, list_block_data) = self.load_list_config();
+        get_find_next_data(&mut self.read, list_block_data, key, limit, only_after_key)
+    }
+}
+
+/// Iterator over `(key, value)` pairs that reads one block per `next()`.
+///
+/// The block list is parsed when the iterator is created; blocks whose key no
+/// longer matches their metadata are skipped.
+pub struct BlockIter<'a> {
+    read: &'a mut File,
+    list_block_info: Vec<Block>,
+    index: usize,
+}
+
+impl Iterator for BlockIter<'_> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.list_block_info.len() {
+            let block_info = &self.list_block_info[self.index];
+            self.index += 1;
+            if let Ok((found_key, found_data)) = pull_data(self.read, block_info)
+                && is_valid_key(&found_key, block_info)
+            {
+                return Some((found_key, found_data));
+            }
+        }
+        None
+    }
+}
+
+/// File-backed bucket storage.
+///
+/// This struct keeps **two independent file handles**:
+/// - `reader`: used for read/scan operations (list, get, find_next…)
+/// - `writer`: used for write operations (set, delete…)
+///
+/// Having separate handles helps avoid seek conflicts when reading and writing
+/// in the same process.
+///
+/// Both handles are unbuffered `File`s on the same file, so every write is in the OS
+/// page cache before the call returns and the next read through the same `Bucket`
+/// sees it (read-your-writes), whichever constructor opened it.
+///
+/// This design makes it easier to:
+/// - read sequentially while writes happen
+/// - avoid frequent seek jumps on a single handle
+/// - keep the code simple (no locking strategy inside the crate)
+pub struct Bucket {
+    /// File handle for read operations (scan / list / get).
+    pub(crate) reader: File,
+
+    /// File handle for write operations (append / update / delete).
+    pub(crate) writer: File,
+
+    /// Path the bucket was opened from (used to reopen handles after `compact`).
+    pub(crate) path: String,
+
+    /// Resident block list (only when opened with `open_cached`).
+    pub(crate) cache: Option<ListCache>,
+
+    /// Which operations take the file lock.
+    pub(crate) access_mode: AccessMode,
+
+    /// Write-once mode (only when opened with `open_worm`).
+    pub(crate) worm: bool,
+
+    /// Item cap enforced after each write (only when opened with `open_bounded`).
+    pub(crate) max_entries: Option<usize>,
+
+    /// File size cap checked before each insert (only when opened with `open_capped`).
+    pub(crate) max_file_size: Option<u64>,
+
+    /// Reopen the handles when the file at `path` was replaced (only when opened with
+    /// `open_auto_reopen`).
+    pub(crate) auto_reopen: bool,
+
+    /// Blocks of recently read keys (only when opened with `open_lookup_cache`).
+    pub(crate) lookup_cache: Option<LookupCache>,
+
+    /// How `set` and `reserve_value` pick free space for a new block.
+    pub(crate) placement: Placement,
+
+    /// Buffer sizes for block scans and whole-file writes.
+    pub(crate) buffers: Buffers,
+
+    /// What `delete` leaves behind (set with `open_with`).
+    pub(crate) delete_mode: DeleteMode,
+}
+
+/// Free-space policy of a [`Bucket`], chosen at open time.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Placement {
+    /// Use the size-class allocator (only when opened with `open_size_classes`).
+    size_classes: bool,
+
+    /// Multiple every block start is rounded up to; 0 and 1 mean no alignment (set by
+    /// `open_aligned`).
+    alignment: usize,
+}
+
+/// Buffer sizes of a [`Bucket`], chosen at open time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Buffers {
+    /// Read buffer for `list`, `list_next`, `find_next`, `export_raw` and the block
+    /// reads of `compact`, `clone_to` and `freeze`; 0 reads the file directly (set by
+    /// `open_with_buffers`).
+    read: usize,
+
+    /// Write buffer for `bulk_load` and the file written by `compact`, `clone_to` and
+    /// `freeze` (set by `open_with_buffers`).
+    write: usize,
+}
+
+impl Default for Buffers {
+    fn default() -> Self {
+        Self {
+            read: 0,
+            write: 8 * 1024,
+        }
+    }
+}
+
+/// Buffered reader for block scans.
+///
+/// `BufReader::seek` always drops the buffer; here a seek to an absolute offset that is
+/// still inside the buffer only moves within it, so neighbouring blocks read in file
+/// order share one read of the file.
+struct ScanReader<R> {
+    inner: BufReader<R>,
+    // vị trí logic hiện tại, chỉ biết sau lần seek đầu tiên
+    pos: Option<u64>,
+}
+
+impl<R: Read> ScanReader<R> {
+    fn new(capacity: usize, read: R) -> Self {
+        Self {
+            inner: BufReader::with_capacity(capacity, read),
+            pos: None,
+        }
+    }
+}
+
+impl<R: Read> Read for ScanReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(pos) = &mut self.pos {
+            *pos += n as u64;
+        }
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for ScanReader<R> {
+    fn seek(&mut self, to: SeekFrom) -> Result<u64> {
+        if let (Start(target), Some(pos)) = (to, self.pos)
+            && target >= pos
+            && target - pos <= self.inner.buffer().len() as u64
+        {
+            self.inner.consume((target - pos) as usize);
+            self.pos = Some(target);
+            return Ok(target);
+        }
+        let pos = self.inner.seek(to)?;
+        self.pos = Some(pos);
+        Ok(pos)
+    }
+}
+
+/// How a [`Bucket`] coordinates with other handles on the same file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AccessMode {
+    /// Only write operations take the (exclusive) file lock.
+    #[default]
+    WriteLock,
+
+    /// Read operations also take a shared file lock.
+    ///
+    /// Many readers run concurrently; a writer waits for them and holds the exclusive
+    /// lock for the duration of one write. Readers therefore see the bucket either
+    /// before or after a write, never half-written. Iterators (`find_next_iter`) read
+    /// without the lock. A read that cannot take the lock does not read unlocked: methods
+    /// returning `Result` return the error, the others an empty result as for an
+    /// unreadable file.
+    SingleWriterMultiReader,
+}
+
+/// What [`Trait::delete`] leaves behind, chosen at open time with [`BucketOptions`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DeleteMode {
+    /// The block is removed from the block list.
+    #[default]
+    Remove,
+
+    /// The block list keeps a tombstone for the key: the key bytes stay in place, the
+    /// value space is freed, and the entry moves to the end of the list.
+    ///
+    /// Reads treat a tombstoned key as absent; [`Trait::list_tombstones`] lists them in
+    /// delete order, so a replication consumer can learn about deletes after it synced
+    /// the key. Deleting the key again replaces its tombstone. A later `set` of the key
+    /// stores a new block and keeps the tombstone before it in the list. An empty key
+    /// gets no tombstone.
+    Tombstone,
+}
+
+/// File lock held for the duration of one operation; unlocks when dropped, including
+/// on early `?` returns.
+struct LockGuard(File);
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        let _ = self.0.unlock();
+    }
+}
+
+/// Parsed block list kept in memory by [`Trait::open_cached`].
+pub(crate) struct ListCache {
+    start_list_point: usize,
+    list_block_data: Vec<u8>,
+    list_block_info: Vec<Block>,
+    map_block_index: HashMap<(usize, usize), Vec<usize>>,
+}
+
+impl ListCache {
+    fn new(start_list_point: usize, list_block_data: Vec<u8>) -> Self {
+        let list_block_info = get_list_block_info(&list_block_data);
+        let mut map_block_index: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for (i, b) in list_block_info.iter().enumerate() {
+            map_block_index
+                .entry((b.size_key, b.sum_key))
+                .or_default()
+                .push(i);
+        }
+        Self {
+            start_list_point,
+            list_block_data,
+            list_block_info,
+            map_block_index,
+        }
+    }
+}
+
+/// Blocks of recently read keys kept by [`Trait::open_lookup_cache`]; the least
+/// recently used key is dropped first.
+pub(crate) struct LookupCache {
+    capacity: usize,
+    tick: u64,
+    map_key_block: HashMap<Vec<u8>, (Block, u64)>,
+}
+
+impl LookupCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            map_key_block: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: &[u8]) -> Option<Block> {
+        self.tick += 1;
+        let (block_info, used) = self.map_key_block.get_mut(key)?;
+        *used = self.tick;
+        Some(block_info.clone())
+    }
+
+    fn insert(&mut self, key: Vec<u8>, block_info: Block) {
+        if self.map_key_block.len() >= self.capacity
+            && !self.map_key_block.contains_key(&key)
+            && let Some(oldest) = self
+                .map_key_block
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(k, _)| k.clone())
+        {
+            self.map_key_block.remove(&oldest);
+        }
+        self.tick += 1;
+        self.map_key_block.insert(key, (block_info, self.tick));
+    }
+}
+
+const MAX_DIGIT_GROUP: u8 = 249;
+const START: u8 = 250;
+const SIZE_KEY: u8 = 251;
+const SUM_KEY: u8 = 252;
+const SUM_MD5: u8 = 253;
+const SIZE_DATA: u8 = 254;
+const END: u8 = 255;
+const FIRST_SIZE: usize = 128;
+/// Start of the bucket metadata region, the tail of the header after the list config.
+const META_START: usize = 80;
+/// Header format written by this version: the block list checksum closed by `SUM_MD5`.
+const FORMAT_VERSION: u32 = 1;
+/// First bytes of an `export_raw` stream: magic and stream format version.
+const RAW_MAGIC: &[u8; 5] = b"BBKV\x01";
+/// Key length that closes an `export_raw` stream instead of starting an entry.
+const RAW_END: u32 = u32::MAX;
+
+/// Attempts after the first made by `get_consistent` while the block list fails the
+/// checksum check.
+const CONSISTENT_READ_RETRIES: u32 = 10;
+/// Pause between `get_consistent` attempts.
+const CONSISTENT_READ_BACKOFF: Duration = Duration::from_millis(10);
+/// Message of the error returned when the block list does not match the header checksum.
+const CHECKSUM_MISMATCH: &str = "corrupt block list: checksum mismatch";
+
+/// Metadata of one stored item, as recorded in the block list.
+///
+/// The key and value bytes live at `[start, start + size_key + size_data)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    /// File offset of the key bytes.
+    pub start: usize,
+    /// Length of the key.
+    pub size_key: usize,
+    /// Sum of the key bytes.
+    pub sum_key: usize,
+    /// Sum of the bytes of the key's md5 digest.
+    pub sum_md5: usize,
+    /// Length of the value.
+    pub size_data: usize,
+}
+
+/// One group of [`Trait::group_by_prefix`]: the shared key segment and its items.
+pub type PrefixGroup = (Vec<u8>, Vec<(Vec<u8>, Vec<u8>)>);
+
+/// Free-space figures returned by [`Trait::free_list_summary`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FreeListSummary {
+    /// Number of holes, including the gap before the block list.
+    pub holes: usize,
+    /// Size of the largest hole (0 if there is none).
+    pub largest: usize,
+    /// Size of the smallest hole (0 if there is none).
+    pub smallest: usize,
+    /// Total free bytes over all holes.
+    pub total_free: usize,
+}
+
+/// A region reserved by [`Trait::reserve_value`], filled with [`Trait::write_value`] and
+/// published with [`Trait::finalize_value`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValueHandle {
+    key: Vec<u8>,
+    start: usize,
+    size: usize,
+}
+
+impl ValueHandle {
+    /// Size of the reserved value.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Block list entry holding the region until it is finalized: an empty key whose
+    /// `sum_md5` (0) no real key has, so reads skip it while the allocator keeps out.
+    fn placeholder(&self) -> Block {
+        Block {
+            start: self.start,
+            size_key: 0,
+            sum_key: 0,
+            sum_md5: 0,
+            size_data: self.key.len() + self.size,
+        }
+    }
+}
+
+/// One difference reported by [`Trait::diff`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiffEntry {
+    /// The key is only in the bucket `diff` was called on.
+    OnlyInSelf(Vec<u8>),
+    /// The key is only in the other bucket.
+    OnlyInOther(Vec<u8>),
+    /// Both buckets hold the key with different values.
+    ValueDiffers(Vec<u8>),
+}
+
+/// Open-time options of a [`Bucket`], combined in one [`Trait::open_with`] call.
+///
+/// The default opens a bucket like [`Trait::new`]; each `open_*` constructor is
+/// `open_with` with one field set. See those constructors for what each option does.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BucketOptions {
+    /// Which operations take the file lock, as in [`Trait::new_with_access`].
+    pub access_mode: AccessMode,
+    /// Keep the parsed block list in memory, as in [`Trait::open_cached`].
+    pub cached: bool,
+    /// Fail to open a bucket holding a duplicate key, as in [`Trait::open_strict`].
+    pub strict: bool,
+    /// Write-once mode, as in [`Trait::open_worm`].
+    pub worm: bool,
+    /// Item cap, as in [`Trait::open_bounded`].
+    pub max_entries: Option<usize>,
+    /// File size cap, as in [`Trait::open_capped`].
+    pub max_file_size: Option<u64>,
+    /// Follow the file when it is replaced, as in [`Trait::open_auto_reopen`].
+    pub auto_reopen: bool,
+    /// Capacity of the lookup cache, as in [`Trait::open_lookup_cache`].
+    pub lookup_cache: Option<usize>,
+    /// Size-class allocator, as in [`Trait::open_size_classes`].
+    pub size_classes: bool,
+    /// Block start alignment, as in [`Trait::open_aligned`].
+    pub alignment: Option<usize>,
+    /// Scan read buffer size, as in [`Trait::open_with_buffers`].
+    pub read_buffer: Option<usize>,
+    /// Write buffer size, as in [`Trait::open_with_buffers`].
+    pub write_buffer: Option<usize>,
+    /// What `delete` leaves behind; there is no separate constructor for this option.
+    pub delete_mode: DeleteMode,
+}
+
+/// Integrity figures returned by [`Trait::health`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HealthReport {
+    /// The header and block list load without error.
+    pub header_ok: bool,
+    /// Whether the block list matches the header checksum; `None` for a legacy header
+    /// without one, or a header too damaged to locate the list.
+    pub checksum_ok: Option<bool>,
+    /// Number of overlapping block pairs, as reported by [`Trait::check_overlaps`].
+    pub overlaps: usize,
+    /// Number of keys held by more than one block, as reported by
+    /// [`Trait::find_duplicates`].
+    pub duplicate_keys: usize,
+    /// Free bytes over the size of the data region, `0.0` for a compact bucket.
+    pub fragmentation: f64,
+    /// Unused bytes between the list config and the metadata region of the header.
+    pub header_headroom: usize,
+}
+
+impl HealthReport {
+    /// No check found a problem. Fragmentation alone does not make a bucket unhealthy.
+    pub fn is_healthy(&self) -> bool {
+        self.header_ok
+            && self.checksum_ok != Some(false)
+            && self.overlaps == 0
+            && self.duplicate_keys == 0
+    }
+}
+
+const EMPTY_BLOCK: Block = Block {
+    start: 0,
+    size_key: 0,
+    sum_key: 0,
+    sum_md5: 0,
+    size_data: 0,
+};
+
+/// Encode `n` as an 8-byte big-endian key.
+///
+/// Byte-wise comparison of encoded keys matches numeric order, unlike little-endian
+/// or decimal text keys.
+///
+/// ```
+/// use blockbucket::{decode_u64_key, encode_u64_key};
+///
+/// assert!(encode_u64_key(2) < encode_u64_key(10));
+/// assert_eq!(decode_u64_key(&encode_u64_key(42)), Some(42));
+/// ```
+pub fn encode_u64_key(n: u64) -> Vec<u8> {
+    n.to_be_bytes().to_vec()
+}
+
+/// Decode a key made by [`encode_u64_key`], or `None` if it is not 8 bytes long.
+pub fn decode_u64_key(key: &[u8]) -> Option<u64> {
+    key.try_into().ok().map(u64::from_be_bytes)
+}
+
+/// 64-bit hash of a key, as returned by [`Trait::export_key_hashes`].
+///
+/// The first 8 bytes of the key's md5 digest, so it is stable across platforms and
+/// versions.
+pub fn key_hash(key: &[u8]) -> u64 {
+    let digest = md5::compute(key);
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest.0[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+/// Upgrade the bucket file at `path` from the legacy header (format version 0, no block
+/// list checksum) to the current one.
+///
+/// The block list is read with the legacy rules and written back in place with its
+/// checksum, under the file lock; blocks, list entries and the meta region are not
+/// touched. Files already in the current format and empty files are left as they are.
+/// Only the header is migrated: a legacy list that no longer parses fails with
+/// `ErrorKind::InvalidData` and the file is unchanged. Opening a legacy file and writing
+/// to it also upgrades the header; this helper does it without a write.
+pub fn migrate_v0_to_v1(path: &str) -> Result<()> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let _lock = lock_exclusive(&file)?;
+    if get_format_version(&mut file)? >= FORMAT_VERSION {
+        return Ok(());
+    }
+    let (start_list_point, list_block_data) = get_list_config(&mut file)?;
+    update_list_block(&mut file, start_list_point, list_block_data)?;
+    file.sync_all()
+}
+
+fn group_digits_to_vec(n: usize) -> Vec<u8> {
+    group_u64_digits_to_vec(n as u64)
+}
+
+fn group_u64_digits_to_vec(mut n: u64) -> Vec<u8> {
+    let mut digits = Vec::new();
+    while n > 0 {
+        digits.push((n % 10) as u8);
+        n /= 10;
+    }
+    digits.reverse();
+
+    let mut result = Vec::new();
+    let mut i = 0;
+
+    while i < digits.len() {
+        // nếu chữ số hiện tại là 0 -> push 0 và tiếp tục
+        if digits[i] == 0 {
+            result.push(0);
+            i += 1;
+            continue;
+        }
+
+        // thử gom 3 chữ số
+        if i + 2 < digits.len() {
+            let v = (digits[i] as u16) * 100 + (digits[i + 1] as u16) * 10 + (digits[i + 2] as u16);
+
+            if v <= MAX_DIGIT_GROUP as u16 {
+                result.push(v as u8);
+                i += 3;
+                continue;
+            }
+        }
+
+        // thử gom 2 chữ số
+        if i + 1 < digits.len() {
+            let v = digits[i] * 10 + digits[i + 1];
+            if v <= MAX_DIGIT_GROUP {
+                result.push(v);
+                i += 2;
+                continue;
+            }
+        }
+
+        // fallback: 1 chữ số
+        result.push(digits[i]);
+        i += 1;
+    }
+
+    result
+}
+#[cfg(test)]
+mod test_group_digits_to_vec {
+    use crate::group_digits_to_vec;
+    #[test]
+    fn test_group_digits_to_vec() {
+        let data = group_digits_to_vec(2502510011110001111);
+        assert_eq!(data, [25u8, 0, 25, 100, 111, 100, 0, 111, 1]);
+    }
+}
+
+fn digits_to_number(digits: &[u8]) -> usize {
+    let mut n: usize = 0;
+    for &x in digits {
+        let count = if x < 10 {
+            1
+        } else if x < 100 {
+            2
+        } else {
+            3
+        };
+        // số quá lớn chỉ có trong file hỏng: giữ ở usize::MAX thay vì tràn số
+        n = n
+            .saturating_mul(10usize.pow(count))
+            .saturating_add(x as usize);
+    }
+    n
+}
+
+/// Read back a checksum written by [`group_u64_digits_to_vec`]; wraps at `u64` so the
+/// value is the same on 32-bit and 64-bit targets.
+fn digits_to_checksum(digits: &[u8]) -> u64 {
+    let mut n: u64 = 0;
+    for &x in digits {
+        let count = if x < 10 {
+            1
+        } else if x < 100 {
+            2
+        } else {
+            3
+        };
+        n = n.wrapping_mul(10u64.pow(count)).wrapping_add(x as u64);
+    }
+    n
+}
+#[cfg(test)]
+mod test_digits_to_number {
+    use crate::digits_to_number;
+    #[test]
+    fn test_digits_to_number() {
+        let data = digits_to_number(&[250u8, 25, 100, 111, 100, 0, 111, 1]);
+        assert_eq!(data, 2502510011110001111);
+        let data = digits_to_number(&[0u8, 250, 25, 100, 111, 100, 0, 111, 1]);
+        assert_eq!(data, 2502510011110001111);
+    }
+
+    #[test]
+    fn test_digits_round_trip() {
+        use crate::group_digits_to_vec;
+        for n in (0..200_000).chain([1 << 32, usize::MAX]) {
+            assert_eq!(digits_to_number(&group_digits_to_vec(n)), n);
+        }
+    }
+
+    #[test]
+    fn test_checksum_round_trip() {
+        use crate::{digits_to_checksum, group_u64_digits_to_vec};
+        for n in [0, 1, u32::MAX as u64 + 1, u64::MAX] {
+            assert_eq!(digits_to_checksum(&group_u64_digits_to_vec(n)), n);
+        }
+    }
+}
+
+fn merge_vec(vec: &[Vec<u8>]) -> Vec<u8> {
+    let total: usize = vec.iter().map(|v| v.len()).sum();
+    let mut out = Vec::with_capacity(total);
+    for v in vec {
+        out.extend_from_slice(v);
+    }
+    out
+}
+#[cfg(test)]
+mod test_merge_vec {
+    use crate::merge_vec;
+    #[test]
+    fn test_merge_vec() {
+        let data = merge_vec(&[vec![8u8, 9, 66, 4], vec![1u8, 1, 11, 1]]);
+        assert_eq!(data, vec![8u8, 9, 66, 4, 1, 1, 11, 1]);
+    }
+}
+
+fn pull_key(read: &mut (impl Read + Seek), info: &Block) -> Result<Vec<u8>> {
+    read.seek(Start(info.start as u64))?;
+    let mut found_key = vec![0u8; info.size_key];
+    read.read_exact(&mut found_key)?;
+    Ok(found_key)
+}
+
+fn pull_data(read: &mut (impl Read + Seek), info: &Block) -> Result<(Vec<u8>, Vec<u8>)> {
+    read.seek(Start(info.start as u64))?;
+    let mut found_key = vec![0u8; info.size_key];
+    read.read_exact(&mut found_key)?;
+    let mut found_data = vec![0u8; info.size_data];
+    read.read_exact(&mut found_data)?;
+    Ok((found_key, found_data))
+}
+
+fn pull_value(read: &mut (impl Read + Seek), info: &Block) -> Result<Vec<u8>> {
+    read.seek(Start((info.start + info.size_key) as u64))?;
+    let mut found_data = vec![0u8; info.size_data];
+    read.read_exact(&mut found_data)?;
+    Ok(found_data)
+}
+
+fn get_sum_key(key: &[u8]) -> usize {
+    key.iter().map(|&x| x as usize).sum()
+}
+
+fn get_sum_md5(key: &[u8]) -> usize {
+    md5::compute(key).0.iter().map(|&x| x as usize).sum()
+}
+
+fn is_valid_key(found_key: &[u8], block_info: &Block) -> bool {
+    found_key.len() == block_info.size_key
+        && get_sum_key(found_key) == block_info.sum_key
+        && get_sum_md5(found_key) == block_info.sum_md5
+}
+
+fn get_list_block_info(list_block_data: &[u8]) -> Vec<Block> {
+    let mut list_block_info: Vec<Block> = Vec::new();
+    let mut block_info = EMPTY_BLOCK;
+    let mut tmp_group: Vec<u8> = Vec::new();
+    for &v in list_block_data {
+        match v {
+            START => {
+                block_info.start = digits_to_number(&tmp_group);
+                tmp_group.clear();
+            }
+            SIZE_KEY => {
+                block_info.size_key = digits_to_number(&tmp_group);
+                tmp_group.clear();
+            }
+            SUM_KEY => {
+                block_info.sum_key = digits_to_number(&tmp_group);
+                tmp_group.clear();
+            }
+            SUM_MD5 => {
+                block_info.sum_md5 = digits_to_number(&tmp_group);
+                tmp_group.clear();
+            }
+            SIZE_DATA => {
+                block_info.size_data = digits_to_number(&tmp_group);
+                tmp_group.clear();
+                list_block_info.push(block_info);
+                block_info = EMPTY_BLOCK;
+            }
+            END => {
+                break;
+            }
+            _ => {
+                tmp_group.push(v);
+            }
+        }
+    }
+    list_block_info
+}
+
+fn get_one_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    key: Vec<u8>,
+) -> (Vec<u8>, Vec<u8>) {
+    let mut result: (Vec<u8>, Vec<u8>) = (Vec::new(), Vec::new());
+    {
+        let len_key = key.len();
+        let sum_key: usize = key.iter().map(|&x| x as usize).sum();
+        let sum_md5: usize = md5::compute(&key)
+            .to_vec()
+            .iter()
+            .map(|&x| x as usize)
+            .sum();
+
+        let mut block_info = EMPTY_BLOCK;
+        let mut tmp_group: Vec<u8> = Vec::new();
+        for v in list_block_data {
+            match v {
+                START => {
+                    block_info.start = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SIZE_KEY => {
+                    block_info.size_key = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SUM_KEY => {
+                    block_info.sum_key = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SUM_MD5 => {
+                    block_info.sum_md5 = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SIZE_DATA => {
+                    block_info.size_data = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                    {
+                        if block_info.size_key == len_key
+                            && block_info.sum_key == sum_key
+                            && block_info.sum_md5 == sum_md5
+                        {
+                            let (found_key, found_data) = pull_data(read, &block_info)
+                                .unwrap_or_else(|_| (Vec::new(), Vec::new()));
+                            if found_key == key {
+                                // success
+                                result.0 = found_key;
+                                result.1 = found_data;
+                                break;
+                            }
+                        }
+                    }
+                    block_info = EMPTY_BLOCK;
+                }
+                END => {
+                    break;
+                }
+                _ => {
+                    tmp_group.push(v);
+                }
+            }
+        }
+    }
+    result
+}
+
+fn get_one_data_cached(
+    read: &mut (impl Read + Seek),
+    cache: &ListCache,
+    key: Vec<u8>,
+) -> (Vec<u8>, Vec<u8>) {
+    if let Some(list_index) = cache.map_block_index.get(&(key.len(), get_sum_key(&key))) {
+        let sum_md5 = get_sum_md5(&key);
+        for &i in list_index {
+            let block_info = &cache.list_block_info[i];
+            if block_info.sum_md5 != sum_md5 {
+                continue;
+            }
+            if let Ok((found_key, found_data)) = pull_data(read, block_info)
+                && found_key == key
+            {
+                // success
+                return (found_key, found_data);
+            }
+        }
+    }
+    (Vec::new(), Vec::new())
+}
+
+fn get_consistent_data(
+    read: &mut (impl Read + Seek),
+    key: Vec<u8>,
+    backoff: Duration,
+) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let mut attempt: u32 = 0;
+    let list_block_data = loop {
+        match get_list_config(read) {
+            Ok((_, list_block_data)) => break list_block_data,
+            // chỉ thử lại khi list không khớp checksum (có thể đang được ghi dở)
+            Err(e)
+                if attempt < CONSISTENT_READ_RETRIES
+                    && e.kind() == ErrorKind::InvalidData
+                    && e.to_string() == CHECKSUM_MISMATCH =>
+            {
+                attempt += 1;
+                thread::sleep(backoff);
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    let (found_key, found_data) = get_one_data(read, list_block_data, key);
+    if found_key.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some((found_key, found_data)))
+}
+
+/// Whether `key` is stored with exactly `data`. The value is only read when the block
+/// list records the same value size.
+fn is_value_unchanged(
+    read: &mut (impl Read + Seek),
+    list_block_data: &[u8],
+    key: &[u8],
+    data: &[u8],
+) -> bool {
+    get_one_block(read, list_block_data, key).is_some_and(|block_info| {
+        block_info.size_data == data.len()
+            && pull_value(read, &block_info).is_ok_and(|found_data| found_data == data)
+    })
+}
+
+/// The block holding `key`, found like [`get_one_data`] but without reading the value.
+fn get_one_block(
+    read: &mut (impl Read + Seek),
+    list_block_data: &[u8],
+    key: &[u8],
+) -> Option<Block> {
+    let len_key = key.len();
+    let sum_key = get_sum_key(key);
+    let sum_md5 = get_sum_md5(key);
+    get_list_block_info(list_block_data)
+        .into_iter()
+        .find(|block_info| {
+            block_info.size_key == len_key
+                && block_info.sum_key == sum_key
+                && block_info.sum_md5 == sum_md5
+                && pull_key(read, block_info).is_ok_and(|found_key| found_key == key)
+        })
+}
+
+fn get_value_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    key: Vec<u8>,
+) -> Option<Vec<u8>> {
+    let len_key = key.len();
+    let sum_key = get_sum_key(&key);
+    let sum_md5 = get_sum_md5(&key);
+    for block_info in get_list_block_info(&list_block_data) {
+        if block_info.size_key == len_key
+            && block_info.sum_key == sum_key
+            && block_info.sum_md5 == sum_md5
+            && pull_key(read, &block_info).unwrap_or_else(|_| Vec::new()) == key
+        {
+            // success
+            return pull_value(read, &block_info).ok();
+        }
+    }
+    None
+}
+
+fn get_many_read_plan(list_block_data: &[u8], list_key: &[Vec<u8>]) -> Vec<(Block, Vec<usize>)> {
+    let mut map_key_index: HashMap<(usize, usize, usize), Vec<usize>> = HashMap::new();
+    for (i, key) in list_key.iter().enumerate() {
+        map_key_index
+            .entry((key.len(), get_sum_key(key), get_sum_md5(key)))
+            .or_default()
+            .push(i);
+    }
+    let mut read_plan: Vec<(Block, Vec<usize>)> = Vec::new();
+    for block_info in get_list_block_info(list_block_data) {
+        let info_key = (block_info.size_key, block_info.sum_key, block_info.sum_md5);
+        if let Some(list_index) = map_key_index.get(&info_key) {
+            read_plan.push((block_info, list_index.clone()));
+        }
+    }
+    read_plan.sort_by_key(|(b, _)| b.start);
+    read_plan
+}
+
+/// Blocks whose key is in `[start, end)` (byte-wise order), sorted by file offset.
+fn get_prefetch_plan(
+    read: &mut (impl Read + Seek),
+    list_block_data: &[u8],
+    start: &[u8],
+    end: &[u8],
+) -> Vec<Block> {
+    let mut read_plan: Vec<Block> = Vec::new();
+    for block_info in get_list_block_info(list_block_data) {
+        let found_key = pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
+        if is_valid_key(&found_key, &block_info)
+            && found_key.as_slice() >= start
+            && found_key.as_slice() < end
+        {
+            read_plan.push(block_info);
+        }
+    }
+    read_plan.sort_by_key(|b| b.start);
+    read_plan
+}
+
+/// Read every block of `read_plan` in order, only to bring it into the OS page cache.
+fn prefetch_data(read: &mut (impl Read + Seek), read_plan: &[Block]) -> Result<()> {
+    let mut buffer: Vec<u8> = Vec::new();
+    for block_info in read_plan {
+        buffer.resize(block_info.size_key + block_info.size_data, 0);
+        read.seek(Start(block_info.start as u64))?;
+        read.read_exact(&mut buffer)?;
+    }
+    Ok(())
+}
+
+fn get_many_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    list_key: Vec<Vec<u8>>,
+) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut list_unique_key: Vec<Vec<u8>> = Vec::new();
+    let mut map_unique_index: HashMap<Vec<u8>, usize> = HashMap::new();
+    for key in &list_key {
+        if !map_unique_index.contains_key(key) {
+            map_unique_index.insert(key.clone(), list_unique_key.len());
+            list_unique_key.push(key.clone());
+        }
+    }
+
+    let mut list_found: Vec<(Vec<u8>, Vec<u8>)> =
+        vec![(Vec::new(), Vec::new()); list_unique_key.len()];
+    for (block_info, list_index) in get_many_read_plan(&list_block_data, &list_unique_key) {
+        let (found_key, found_data) =
+            pull_data(read, &block_info).unwrap_or_else(|_| (Vec::new(), Vec::new()));
+        for i in list_index {
+            if list_found[i].0.is_empty() && found_key == list_unique_key[i] {
+                // success
+                list_found[i] = (found_key.clone(), found_data.clone());
+            }
+        }
+    }
+
+    list_key
+        .iter()
+        .map(|key| list_found[map_unique_index[key]].clone())
+        .collect()
+}
+
+/// Fast path of `get_list_data` when the first `limit` blocks sit back to back in the
+/// file (e.g. after `compact` or `bulk_load`): one read for all of them instead of one
+/// seek + read per block. Returns `None` to fall back to per-block reads.
+fn get_list_contiguous_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: &[u8],
+    limit: u8,
+) -> Option<Vec<(Vec<u8>, Vec<u8>)>> {
+    let list_block_info: Vec<Block> = get_list_block_info(list_block_data)
+        .into_iter()
+        .take(limit as usize)
+        .collect();
+    let first_block = list_block_info.first()?;
+    let mut end_point = first_block.start;
+    for block_info in &list_block_info {
+        if block_info.start != end_point {
+            return None;
+        }
+        end_point += block_info.size_key + block_info.size_data;
+    }
+
+    read.seek(Start(first_block.start as u64)).ok()?;
+    let mut buffer = vec![0u8; end_point - first_block.start];
+    read.read_exact(&mut buffer).ok()?;
+    let mut result: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    let mut current_point = 0;
+    for block_info in &list_block_info {
+        let end_key = current_point + block_info.size_key;
+        let end_data = end_key + block_info.size_data;
+        let found_key = &buffer[current_point..end_key];
+        if !is_valid_key(found_key, block_info) {
+            // có block hỏng: đọc lại từng block để bỏ qua nó
+            return None;
+        }
+        result.push((found_key.to_vec(), buffer[end_key..end_data].to_vec()));
+        current_point = end_data;
+    }
+    Some(result)
+}
+
+fn get_list_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    limit: u8,
+) -> Vec<(Vec<u8>, Vec<u8>)> {
+    if let Some(result) = get_list_contiguous_data(read, &list_block_data, limit) {
+        return result;
+    }
+    let mut result: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    {
+        let mut block_info = EMPTY_BLOCK;
+        let mut tmp_group: Vec<u8> = Vec::new();
+        let mut current: u8 = 0;
+        for v in list_block_data {
+            if current >= limit {
+                break;
+            }
+            match v {
+                START => {
+                    block_info.start = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SIZE_KEY => {
+                    block_info.size_key = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SUM_KEY => {
+                    block_info.sum_key = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SUM_MD5 => {
+                    block_info.sum_md5 = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SIZE_DATA => {
+                    block_info.size_data = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                    {
+                        let (found_key, found_data) = pull_data(read, &block_info)
+                            .unwrap_or_else(|_| (Vec::new(), Vec::new()));
+                        let len_found_key = found_key.len();
+                        if len_found_key == block_info.size_key {
+                            let sum_found_key: usize = found_key.iter().map(|&x| x as usize).sum();
+                            if sum_found_key == block_info.sum_key {
+                                let sum_found_md5: usize = md5::compute(&found_key)
+                                    .to_vec()
+                                    .iter()
+                                    .map(|&x| x as usize)
+                                    .sum();
+                                if sum_found_md5 == block_info.sum_md5 {
+                                    // success
+                                    result.push((found_key, found_data));
+                                    current += 1;
+                                }
+                            }
+                        }
+                    }
+                    block_info = EMPTY_BLOCK;
+                }
+                END => {
+                    break;
+                }
+                _ => {
+                    tmp_group.push(v);
+                }
+            }
+        }
+    }
+    result
+}
+
+fn get_list_lock_delete_data(
+    read: &mut (impl Read + Seek),
+    write: &mut File,
+    start_list_point: usize,
+    list_block_data: Vec<u8>,
+    limit: u8,
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut result: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    let mut end_key: Vec<u8> = Vec::new();
+    {
+        let mut block_info = EMPTY_BLOCK;
+        let mut tmp_group: Vec<u8> = Vec::new();
+        let mut current: u8 = 0;
+        for v in list_block_data.clone() {
+            if current >= limit {
+                break;
+            }
+            match v {
+                START => {
+                    block_info.start = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SIZE_KEY => {
+                    block_info.size_key = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SUM_KEY => {
+                    block_info.sum_key = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SUM_MD5 => {
+                    block_info.sum_md5 = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SIZE_DATA => {
+                    block_info.size_data = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                    {
+                        let (found_key, found_data) = pull_data(read, &block_info)
+                            .unwrap_or_else(|_| (Vec::new(), Vec::new()));
+                        let len_found_key = found_key.len();
+                        if len_found_key == block_info.size_key {
+                            let sum_found_key: usize = found_key.iter().map(|&x| x as usize).sum();
+                            if sum_found_key == block_info.sum_key {
+                                let sum_found_md5: usize = md5::compute(&found_key)
+                                    .to_vec()
+                                    .iter()
+                                    .map(|&x| x as usize)
+                                    .sum();
+                                if sum_found_md5 == block_info.sum_md5 {
+                                    // success
+                                    end_key = found_key.clone();
+                                    result.push((found_key, found_data));
+                                    current += 1;
+                                }
+                            }
+                        }
+                    }
+                    block_info = EMPTY_BLOCK;
+                }
+                END => {
+                    break;
+                }
+                _ => {
+                    tmp_group.push(v);
+                }
+            }
+        }
+    }
+    delete_to_data(
+        read,
+        write,
+        start_list_point,
+        list_block_data,
+        true,
+        end_key,
+    )?;
+    Ok(result)
+}
+
+fn get_list_next_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    limit: u8,
+    skip: usize,
+) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut result: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    {
+        let mut block_info = EMPTY_BLOCK;
+        let mut tmp_group: Vec<u8> = Vec::new();
+        let mut current: u8 = 0;
+        let mut current_skip: usize = 0;
+        for v in list_block_data {
+            if current >= limit {
+                break;
+            }
+            match v {
+                START => {
+                    block_info.start = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SIZE_KEY => {
+                    block_info.size_key = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SUM_KEY => {
+                    block_info.sum_key = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SUM_MD5 => {
+                    block_info.sum_md5 = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SIZE_DATA => {
+                    block_info.size_data = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                    {
+                        let (found_key, found_data) = pull_data(read, &block_info)
+                            .unwrap_or_else(|_| (Vec::new(), Vec::new()));
+                        let len_found_key = found_key.len();
+                        if len_found_key == block_info.size_key {
+                            let sum_found_key: usize = found_key.iter().map(|&x| x as usize).sum();
+                            if sum_found_key == block_info.sum_key {
+                                let sum_found_md5: usize = md5::compute(&found_key)
+                                    .to_vec()
+                                    .iter()
+                                    .map(|&x| x as usize)
+                                    .sum();
+                                if sum_found_md5 == block_info.sum_md5 {
+                                    // success
+                                    if current_skip < skip {
+                                        current_skip += 1;
+                                    } else {
+                                        result.push((found_key, found_data));
+                                        current += 1;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    block_info = EMPTY_BLOCK;
+                }
+                END => {
+                    break;
+                }
+                _ => {
+                    tmp_group.push(v);
+                }
+            }
+        }
+    }
+    result
+}
+
+fn get_find_next_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    key: Vec<u8>,
+    limit: u8,
+    only_after_key: bool,
+) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut result: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    {
+        let mut block_info = EMPTY_BLOCK;
+        let mut tmp_group: Vec<u8> = Vec::new();
+        let mut current: u8 = 0;
+        let len_current_key = key.len();
+        let sum_current_key: usize = key.iter().map(|&x| x as usize).sum();
+        let sum_current_md5: usize = md5::compute(&key)
+            .to_vec()
+            .iter()
+            .map(|&x| x as usize)
+            .sum();
+        let mut check_is_begin = false;
+        for v in list_block_data {
+            if current >= limit {
+                break;
+            }
+            match v {
+                START => {
+                    block_info.start = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SIZE_KEY => {
+                    block_info.size_key = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SUM_KEY => {
+                    block_info.sum_key = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SUM_MD5 => {
+                    block_info.sum_md5 = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SIZE_DATA => {
+                    block_info.size_data = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                    {
+                        if !check_is_begin
+                            && block_info.size_key == len_current_key
+                            && block_info.sum_key == sum_current_key
+                            && block_info.sum_md5 == sum_current_md5
+                        {
+                            let found_key =
+                                pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
+                            check_is_begin = found_key == key;
+                        }
+                        if check_is_begin {
+                            // success
+                            let (found_key, found_data) = pull_data(read, &block_info)
+                                .unwrap_or_else(|_| (Vec::new(), Vec::new()));
+                            let len_found_key = found_key.len();
+                            if len_found_key == block_info.size_key {
+                                let sum_found_key: usize =
+                                    found_key.iter().map(|&x| x as usize).sum();
+                                if sum_found_key == block_info.sum_key {
+                                    let sum_found_md5: usize = md5::compute(&found_key)
+                                        .to_vec()
+                                        .iter()
+                                        .map(|&x| x as usize)
+                                        .sum();
+                                    if sum_found_md5 == block_info.sum_md5 {
+                                        // success
+                                        if !only_after_key || current > 0 {
+                                            result.push((found_key, found_data));
+                                        }
+                                        current += 1;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    block_info = EMPTY_BLOCK;
+                }
+                END => {
+                    break;
+                }
+                _ => {
+                    tmp_group.push(v);
+                }
+            }
+        }
+    }
+    result
+}
+
+fn get_find_suffix_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    suffix: Vec<u8>,
+    limit: u8,
+) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut result: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    for block_info in get_list_block_info(&list_block_data) {
+        if result.len() >= limit as usize {
+            break;
+        }
+        if block_info.size_key < suffix.len() {
+            continue;
+        }
+        let found_key = pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
+        if !is_valid_key(&found_key, &block_info) || !found_key.ends_with(&suffix) {
+            continue;
+        }
+        if let Ok(found) = pull_data(read, &block_info) {
+            // success
+            result.push(found);
+        }
+    }
+    result
+}
+
+fn get_prefix_first_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    prefix: Vec<u8>,
+) -> Option<(Vec<u8>, Vec<u8>)> {
+    for block_info in get_list_block_info(&list_block_data) {
+        if block_info.size_key < prefix.len() {
+            continue;
+        }
+        let found_key = pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
+        if is_valid_key(&found_key, &block_info) && found_key.starts_with(&prefix) {
+            // success
+            return pull_data(read, &block_info).ok();
+        }
+    }
+    None
+}
+
+fn get_overlaps_data(list_block_data: Vec<u8>) -> Vec<(Block, Block)> {
+    let mut list_block_info = get_list_block_info(&list_block_data);
+    list_block_info.sort_by_key(|b| b.start);
+
+    let mut result: Vec<(Block, Block)> = Vec::new();
+    for i in 0..list_block_info.len() {
+        let block_end =
+            list_block_info[i].start + list_block_info[i].size_key + list_block_info[i].size_data;
+        for next in &list_block_info[i + 1..] {
+            if next.start >= block_end {
+                break;
+            }
+            if next.size_key + next.size_data > 0 {
+                result.push((list_block_info[i].clone(), next.clone()));
+            }
+        }
+    }
+    result
+}
+
+fn get_find_next_iter(
+    read: &mut File,
+    list_block_data: Vec<u8>,
+    key: Vec<u8>,
+    only_after_key: bool,
+) -> BlockIter<'_> {
+    let list_block_info = get_list_block_info(&list_block_data);
+    let len_key = key.len();
+    let sum_key = get_sum_key(&key);
+    let sum_md5 = get_sum_md5(&key);
+    let found_index = list_block_info.iter().position(|b| {
+        b.size_key == len_key
+            && b.sum_key == sum_key
+            && b.sum_md5 == sum_md5
+            && pull_key(read, b).unwrap_or_else(|_| Vec::new()) == key
+    });
+    let index = match found_index {
+        Some(i) if only_after_key => i + 1,
+        Some(i) => i,
+        None => list_block_info.len(),
+    };
+    BlockIter {
+        read,
+        list_block_info,
+        index,
+    }
+}
+
+fn validate_key_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    key: Vec<u8>,
+) -> Result<bool> {
+    for block_info in get_list_block_info(&list_block_data) {
+        if block_info.size_key != key.len() {
+            continue;
+        }
+        if pull_key(read, &block_info)? == key {
+            return Ok(is_valid_key(&key, &block_info));
+        }
+    }
+    Ok(false)
+}
+
+fn get_lookup_cost_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    key: Vec<u8>,
+) -> usize {
+    let sum_key = get_sum_key(&key);
+    let mut cost = 0;
+    for block_info in get_list_block_info(&list_block_data) {
+        if block_info.size_key != key.len() || block_info.sum_key != sum_key {
+            continue;
+        }
+        cost += 1;
+        if pull_key(read, &block_info).is_ok_and(|found_key| found_key == key) {
+            break;
+        }
+    }
+    cost
+}
+
+fn get_group_by_prefix_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    sep: u8,
+) -> Vec<PrefixGroup> {
+    let mut result: Vec<PrefixGroup> = Vec::new();
+    let mut map_group_index: HashMap<Vec<u8>, usize> = HashMap::new();
+    for block_info in get_list_block_info(&list_block_data) {
+        let Ok((found_key, found_data)) = pull_data(read, &block_info) else {
+            continue;
+        };
+        if !is_valid_key(&found_key, &block_info) {
+            continue;
+        }
+        let prefix = match found_key.iter().position(|&x| x == sep) {
+            Some(i) => found_key[..i].to_vec(),
+            None => found_key.clone(),
+        };
+        let i = *map_group_index.entry(prefix.clone()).or_insert_with(|| {
+            result.push((prefix, Vec::new()));
+            result.len() - 1
+        });
+        result[i].1.push((found_key, found_data));
+    }
+    result
+}
+
+fn get_find_value_prefix_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    prefix: Vec<u8>,
+    limit: u8,
+) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut result: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    for block_info in get_list_block_info(&list_block_data) {
+        if result.len() >= limit as usize {
+            break;
+        }
+        if block_info.size_data < prefix.len() {
+            continue;
+        }
+        let Ok((found_key, found_data)) = pull_data(read, &block_info) else {
+            continue;
+        };
+        if is_valid_key(&found_key, &block_info) && found_data.starts_with(&prefix) {
+            // success
+            result.push((found_key, found_data));
+        }
+    }
+    result
+}
+
+fn check_duplicate_key_data(read: &mut (impl Read + Seek), list_block_data: Vec<u8>) -> Result<()> {
+    let mut map_key: HashMap<Vec<u8>, usize> = HashMap::new();
+    for block_info in get_list_block_info(&list_block_data) {
+        let found_key = pull_key(read, &block_info)?;
+        if !is_valid_key(&found_key, &block_info) {
+            continue;
+        }
+        if let Some(start) = map_key.insert(found_key, block_info.start) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "duplicate key: blocks at {} and {} hold the same key",
+                    start, block_info.start
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn get_duplicates_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+) -> Vec<(Vec<u8>, usize)> {
+    let mut list_key: Vec<Vec<u8>> = Vec::new();
+    let mut map_count: HashMap<Vec<u8>, usize> = HashMap::new();
+    for block_info in get_list_block_info(&list_block_data) {
+        let found_key = pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
+        if !is_valid_key(&found_key, &block_info) {
+            continue;
+        }
+        let count = map_count.entry(found_key.clone()).or_insert(0);
+        *count += 1;
+        if *count == 2 {
+            list_key.push(found_key);
+        }
+    }
+    list_key
+        .into_iter()
+        .map(|key| {
+            let count = map_count[&key];
+            (key, count)
+        })
+        .collect()
+}
+
+fn export_raw_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    w: &mut impl Write,
+) -> Result<()> {
+    w.write_all(RAW_MAGIC)?;
+    for block_info in get_list_block_info(&list_block_data) {
+        let (found_key, found_data) = pull_data(read, &block_info)?;
+        if !is_valid_key(&found_key, &block_info) {
+            continue;
+        }
+        w.write_all(&(found_key.len() as u32).to_le_bytes())?;
+        w.write_all(&found_key)?;
+        w.write_all(&(found_data.len() as u64).to_le_bytes())?;
+        w.write_all(&found_data)?;
+    }
+    w.write_all(&RAW_END.to_le_bytes())?;
+    w.flush()
+}
+
+fn read_raw_magic(r: &mut impl Read) -> Result<()> {
+    let mut magic = [0u8; RAW_MAGIC.len()];
+    r.read_exact(&mut magic)?;
+    if &magic != RAW_MAGIC {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "not an export_raw stream",
+        ));
+    }
+    Ok(())
+}
+
+/// Read the next `(key, value)` of an `export_raw` stream, `None` at the end marker.
+fn read_raw_entry(r: &mut impl Read) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let mut size = [0u8; 4];
+    r.read_exact(&mut size)?;
+    let size_key = u32::from_le_bytes(size);
+    if size_key == RAW_END {
+        return Ok(None);
+    }
+    let mut key = Vec::new();
+    r.take(size_key as u64).read_to_end(&mut key)?;
+    let mut size = [0u8; 8];
+    if key.len() == size_key as usize {
+        r.read_exact(&mut size)?;
+    }
+    let size_data = u64::from_le_bytes(size);
+    let mut data = Vec::new();
+    r.take(size_data).read_to_end(&mut data)?;
+    if key.len() != size_key as usize || data.len() as u64 != size_data {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "export_raw stream ends inside an entry",
+        ));
+    }
+    Ok(Some((key, data)))
+}
+
+fn get_prefix_reclaimable_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    prefix: Vec<u8>,
+) -> usize {
+    let mut total: usize = 0;
+    for block_info in get_list_block_info(&list_block_data) {
+        if block_info.size_key < prefix.len() {
+            continue;
+        }
+        let found_key = pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
+        if is_valid_key(&found_key, &block_info) && found_key.starts_with(&prefix) {
+            total += block_info.size_key + block_info.size_data;
+        }
+    }
+    total
+}
+
+fn get_debug_blocks_data(read: &mut (impl Read + Seek), list_block_data: Vec<u8>) -> String {
+    let mut result = String::new();
+    for (i, block_info) in get_list_block_info(&list_block_data).iter().enumerate() {
+        let key_hex = match pull_key(read, block_info) {
+            Ok(found_key) => {
+                let hex: String = found_key.iter().map(|x| format!("{:02x}", x)).collect();
+                if is_valid_key(&found_key, block_info) {
+                    hex
+                } else {
+                    format!("{} (does not match sums)", hex)
+                }
+            }
+            Err(_) => String::from("(unreadable)"),
+        };
+        result.push_str(&format!(
+            "{}: start={} size_key={} sum_key={} sum_md5={} size_data={} key={}\n",
+            i,
+            block_info.start,
+            block_info.size_key,
+            block_info.sum_key,
+            block_info.sum_md5,
+            block_info.size_data,
+            key_hex
+        ));
+    }
+    result
+}
+
+fn get_rank_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    key: Vec<u8>,
+) -> Option<usize> {
+    let mut rank = 0;
+    for block_info in get_list_block_info(&list_block_data) {
+        let found_key = pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
+        if !is_valid_key(&found_key, &block_info) {
+            continue;
+        }
+        if found_key == key {
+            return Some(rank);
+        }
+        rank += 1;
+    }
+    None
+}
+
+/// Every valid `(key, block)` in block list order, reading keys only.
+fn get_list_key_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: &[u8],
+) -> Vec<(Vec<u8>, Block)> {
+    let mut result: Vec<(Vec<u8>, Block)> = Vec::new();
+    for block_info in get_list_block_info(list_block_data) {
+        let found_key = pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
+        if is_valid_key(&found_key, &block_info) {
+            result.push((found_key, block_info));
+        }
+    }
+    result
+}
+
+fn get_diff_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    other_read: &mut (impl Read + Seek),
+    other_list_block_data: Vec<u8>,
+) -> Vec<DiffEntry> {
+    let list_key = get_list_key_data(read, &list_block_data);
+    let list_other_key = get_list_key_data(other_read, &other_list_block_data);
+    let mut map_other_hash: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, (key, _)) in list_other_key.iter().enumerate() {
+        map_other_hash.entry(key_hash(key)).or_default().push(i);
+    }
+
+    let mut result: Vec<DiffEntry> = Vec::new();
+    let mut matched = vec![false; list_other_key.len()];
+    for (key, block_info) in list_key {
+        // hash khác nhau thì chắc chắn khác key, chỉ so key khi trùng hash
+        let found_index = map_other_hash
+            .get(&key_hash(&key))
+            .and_then(|list_index| list_index.iter().find(|&&i| list_other_key[i].0 == key));
+        let Some(&i) = found_index else {
+            result.push(DiffEntry::OnlyInSelf(key));
+            continue;
+        };
+        matched[i] = true;
+        let other_block = &list_other_key[i].1;
+        let is_same = block_info.size_data == other_block.size_data
+            && pull_value(read, &block_info).ok() == pull_value(other_read, other_block).ok();
+        if !is_same {
+            result.push(DiffEntry::ValueDiffers(key));
+        }
+    }
+    for (i, (key, _)) in list_other_key.into_iter().enumerate() {
+        if !matched[i] {
+            result.push(DiffEntry::OnlyInOther(key));
+        }
+    }
+    result
+}
+
+fn get_many_prefix_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    list_prefix: Vec<Vec<u8>>,
+    limit: u8,
+) -> Vec<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut result: Vec<Vec<(Vec<u8>, Vec<u8>)>> = vec![Vec::new(); list_prefix.len()];
+    for block_info in get_list_block_info(&list_block_data) {
+        let list_open: Vec<usize> = (0..list_prefix.len())
+            .filter(|&i| result[i].len() < limit as usize)
+            .collect();
+        if list_open.is_empty() {
+            break;
+        }
+        if list_open
+            .iter()
+            .all(|&i| list_prefix[i].len() > block_info.size_key)
+        {
+            continue;
+        }
+        let found_key = pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
+        if !is_valid_key(&found_key, &block_info) {
+            continue;
+        }
+        let list_match: Vec<usize> = list_open
+            .into_iter()
+            .filter(|&i| found_key.starts_with(&list_prefix[i]))
+            .collect();
+        if list_match.is_empty() {
+            continue;
+        }
+        if let Ok(found) = pull_data(read, &block_info) {
+            // success
+            for i in list_match {
+                result[i].push(found.clone());
+            }
+        }
+    }
+    result
+}
+
+fn get_content_hash_data(read: &mut (impl Read + Seek), list_block_data: Vec<u8>) -> u64 {
+    let mut result: u64 = 0;
+    for block_info in get_list_block_info(&list_block_data) {
+        let Ok((found_key, found_data)) = pull_data(read, &block_info) else {
+            continue;
+        };
+        if !is_valid_key(&found_key, &block_info) {
+            continue;
+        }
+        // độ dài key nằm trong dữ liệu hash để ("ab", "c") khác ("a", "bc")
+        let item_data = merge_vec(&[
+            (found_key.len() as u64).to_le_bytes().to_vec(),
+            found_key,
+            found_data,
+        ]);
+        result = result.wrapping_add(key_hash(&item_data));
+    }
+    result
+}
+
+fn get_list_sizes_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    limit: u8,
+) -> Vec<(Vec<u8>, usize)> {
+    let mut result: Vec<(Vec<u8>, usize)> = Vec::new();
+    for block_info in get_list_block_info(&list_block_data) {
+        if result.len() >= limit as usize {
+            break;
+        }
+        let found_key = pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
+        if is_valid_key(&found_key, &block_info) {
+            result.push((found_key, block_info.size_data));
+        }
+    }
+    result
+}
+
+fn repair_data(
+    read: &mut (impl Read + Seek),
+    write: &mut File,
+    start_list_point: usize,
+    list_block_data: Vec<u8>,
+) -> Result<usize> {
+    let mut new_list_block_data: Vec<u8> = Vec::new();
+    let mut dropped = 0;
+    for block_info in get_list_block_info(&list_block_data) {
+        let found_key = pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
+        if is_valid_key(&found_key, &block_info) || is_tombstone(&found_key, &block_info) {
+            new_list_block_data.extend(push_block_to_data(Vec::new(), &block_info));
+        } else {
+            dropped += 1;
+        }
+    }
+    if dropped > 0 {
+        update_list_block(write, start_list_point, new_list_block_data)?;
+    }
+    Ok(dropped)
+}
+
+fn get_find_next_many_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    list_key: Vec<Vec<u8>>,
+    limit: u8,
+    only_after_key: bool,
+) -> Vec<Vec<(Vec<u8>, Vec<u8>)>> {
+    let list_block_info = get_list_block_info(&list_block_data);
+    let mut map_found = HashMap::new();
+    let mut result: Vec<Vec<(Vec<u8>, Vec<u8>)>> = Vec::new();
+    for key in list_key {
+        let sum_key = get_sum_key(&key);
+        let sum_md5 = get_sum_md5(&key);
+        let found_index = list_block_info.iter().position(|b| {
+            b.size_key == key.len()
+                && b.sum_key == sum_key
+                && b.sum_md5 == sum_md5
+                && pull_key(read, b).is_ok_and(|found_key| found_key == key)
+        });
+        let mut window: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+        if let Some(found_index) = found_index {
+            // giống find_next: key gốc được tính vào limit kể cả khi only_after_key
+            let mut current: u8 = 0;
+            for (i, block_info) in list_block_info.iter().enumerate().skip(found_index) {
+                if current >= limit {
+                    break;
+                }
+                let found = map_found.entry(i).or_insert_with(|| {
+                    pull_data(read, block_info)
+                        .ok()
+                        .filter(|(found_key, _)| is_valid_key(found_key, block_info))
+                });
+                if let Some(found) = found {
+                    if !only_after_key || current > 0 {
+                        window.push(found.clone());
+                    }
+                    current += 1;
+                }
+            }
+        }
+        result.push(window);
+    }
+    result
+}
+
+fn delete_to_data(
+    read: &mut (impl Read + Seek),
+    write: &mut File,
+    start_list_point: usize,
+    list_block_data: Vec<u8>,
+    also_delete_the_found_block: bool,
+    key: Vec<u8>,
+) -> Result<()> {
+    let mut this_found_index: usize = 0;
+    let mut this_found_finish_index: usize = 0;
+    let mut last_found_block_info: Block = EMPTY_BLOCK;
+    {
+        let mut block_control_index_before: usize = 0;
+        let mut block_info = EMPTY_BLOCK;
+        let mut tmp_group: Vec<u8> = Vec::new();
+        let len_current_key = key.len();
+        let sum_current_key: usize = key.iter().map(|&x| x as usize).sum();
+        let sum_current_md5: usize = md5::compute(&key)
+            .to_vec()
+            .iter()
+            .map(|&x| x as usize)
+            .sum();
+        for (i, &v) in list_block_data.iter().enumerate() {
+            match v {
+                START => {
+                    block_info.start = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SIZE_KEY => {
+                    block_info.size_key = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SUM_KEY => {
+                    block_info.sum_key = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SUM_MD5 => {
+                    block_info.sum_md5 = digits_to_number(&tmp_group);
+                    tmp_group.clear();
+                }
+                SIZE_DATA => {
+                    block_info.size_data = digits_to_number(&tmp_group);
                     tmp_group.clear();
+                    {
+                        if block_info.size_key == len_current_key
+                            && block_info.sum_key == sum_current_key
+                            && block_info.sum_md5 == sum_current_md5
+                        {
+                            let found_key =
+                                pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
+                            if found_key == key {
+                                // success
+                                last_found_block_info = block_info;
+                                this_found_index = block_control_index_before + 1;
+                                this_found_finish_index = i + 1;
+                            }
+                        }
+                    }
+                    block_info = EMPTY_BLOCK;
+                    block_control_index_before = i;
+                }
+                END => {
+                    break;
+                }
+                _ => {
+                    tmp_group.push(v);
+                }
+            }
+        }
+    }
+    if this_found_index == 0
+        || last_found_block_info.start == 0
+        || last_found_block_info.size_key == 0
+        || last_found_block_info.sum_key == 0
+        || last_found_block_info.sum_md5 == 0
+    {
+        return Ok(());
+    }
+    if also_delete_the_found_block {
+        update_list_block(
+            write,
+            start_list_point,
+            list_block_data[this_found_finish_index..].to_vec(),
+        )
+    } else {
+        update_list_block(
+            write,
+            start_list_point,
+            list_block_data[this_found_index..].to_vec(),
+        )
+    }
+}
+
+fn delete_one_data(
+    read: &mut (impl Read + Seek),
+    write: &mut File,
+    list_block_data: Vec<u8>,
+    key: Vec<u8>,
+    start_list_point: usize,
+) -> Result<()> {
+    let (new_list_block_data, _) = get_new_list_not_contain_key(read, list_block_data, key, false);
+    update_list_block(write, start_list_point, new_list_block_data)
+}
+
+/// Replace the block of `key` with a tombstone at the end of the block list, dropping an
+/// older tombstone of the same key. Nothing is written if `key` is not stored.
+fn tombstone_one_data(
+    read: &mut (impl Read + Seek),
+    write: &mut File,
+    list_block_data: Vec<u8>,
+    key: Vec<u8>,
+    start_list_point: usize,
+) -> Result<()> {
+    let sum_key = get_sum_key(&key);
+    let sum_md5 = get_sum_md5(&key);
+    let mut tombstone = None;
+    let mut new_list_block_data: Vec<u8> = Vec::new();
+    for block_info in get_list_block_info(&list_block_data) {
+        let is_same_key = block_info.size_key == key.len()
+            && block_info.sum_key == sum_key
+            && (block_info.sum_md5 == sum_md5 || block_info.sum_md5 == 0)
+            && pull_key(read, &block_info).is_ok_and(|found_key| found_key == key);
+        if !is_same_key {
+            new_list_block_data.extend(push_block_to_data(Vec::new(), &block_info));
+        } else if block_info.sum_md5 == sum_md5 {
+            // key vẫn nằm nguyên chỗ cũ, chỉ vùng value được trả lại
+            tombstone = Some(Block {
+                sum_md5: 0,
+                size_data: 0,
+                ..block_info
+            });
+        }
+    }
+    let Some(tombstone) = tombstone else {
+        return Ok(());
+    };
+    new_list_block_data.extend(push_block_to_data(Vec::new(), &tombstone));
+    update_list_block(write, start_list_point, new_list_block_data)
+}
+
+/// Whether `block_info` is a tombstone left by `DeleteMode::Tombstone` whose key bytes
+/// `found_key` are intact: `sum_md5` 0, which no real key has, and no value.
+fn is_tombstone(found_key: &[u8], block_info: &Block) -> bool {
+    block_info.size_key > 0
+        && block_info.sum_md5 == 0
+        && block_info.size_data == 0
+        && found_key.len() == block_info.size_key
+        && get_sum_key(found_key) == block_info.sum_key
+}
+
+fn get_tombstones_data(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    limit: u8,
+) -> Vec<Vec<u8>> {
+    get_list_block_info(&list_block_data)
+        .iter()
+        .filter(|block_info| block_info.size_key > 0 && block_info.sum_md5 == 0)
+        .filter_map(|block_info| {
+            pull_key(read, block_info)
+                .ok()
+                .filter(|found_key| is_tombstone(found_key, block_info))
+        })
+        .take(limit as usize)
+        .collect()
+}
+
+fn compact_key_data(
+    read: &mut (impl Read + Seek),
+    write: &mut File,
+    list_block_data: Vec<u8>,
+    key: Vec<u8>,
+    start_list_point: usize,
+    alignment: usize,
+) -> Result<bool> {
+    let mut list_block_info = get_list_block_info(&list_block_data);
+    let len_key = key.len();
+    let sum_key = get_sum_key(&key);
+    let sum_md5 = get_sum_md5(&key);
+    let mut found_index = None;
+    for (i, block_info) in list_block_info.iter().enumerate() {
+        if block_info.size_key == len_key
+            && block_info.sum_key == sum_key
+            && block_info.sum_md5 == sum_md5
+            && pull_key(read, block_info)? == key
+        {
+            found_index = Some(i);
+            break;
+        }
+    }
+    let Some(found_index) = found_index else {
+        return Ok(false);
+    };
+    let block_info = list_block_info[found_index].clone();
+    let block_size = block_info.size_key + block_info.size_data;
+    let list_space = align_list_space(
+        get_list_space(start_list_point, list_block_info.clone()),
+        alignment.max(1),
+    );
+    let Some(space) = list_space
+        .iter()
+        .find(|s| s.start < block_info.start && s.size_data >= block_size)
+    else {
+        return Ok(false);
+    };
+
+    // ghi block vào chỗ mới trước, rồi mới cập nhật list
+    let (found_key, found_data) = pull_data(read, &block_info)?;
+    write.seek(Start(space.start as u64))?;
+    write.write_all(&merge_vec(&[found_key, found_data]))?;
+    list_block_info[found_index].start = space.start;
+    let mut new_list_block_data: Vec<u8> = Vec::new();
+    for b in &list_block_info {
+        new_list_block_data = push_block_to_data(new_list_block_data, b);
+    }
+    update_list_block(write, start_list_point, new_list_block_data)?;
+    Ok(true)
+}
+
+fn set_at_data(
+    read: &mut (impl Read + Seek),
+    write: &mut File,
+    list_block_data: Vec<u8>,
+    key: Vec<u8>,
+    offset: usize,
+    patch: Vec<u8>,
+) -> Result<bool> {
+    let len_key = key.len();
+    let sum_key = get_sum_key(&key);
+    let sum_md5 = get_sum_md5(&key);
+    for block_info in get_list_block_info(&list_block_data) {
+        if block_info.size_key == len_key
+            && block_info.sum_key == sum_key
+            && block_info.sum_md5 == sum_md5
+            && pull_key(read, &block_info)? == key
+        {
+            if offset.saturating_add(patch.len()) > block_info.size_data {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "patch of {} bytes at offset {} exceeds the value size {}",
+                        patch.len(),
+                        offset,
+                        block_info.size_data
+                    ),
+                ));
+            }
+            write.seek(Start(
+                (block_info.start + block_info.size_key + offset) as u64,
+            ))?;
+            write.write_all(&patch)?;
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn set_one_data(
+    read: &mut (impl Read + Seek),
+    write: &mut File,
+    list_block_data: Vec<u8>,
+    (key, data): (Vec<u8>, Vec<u8>),
+    start_list_point: usize,
+    max_file_size: Option<u64>,
+    placement: Placement,
+) -> Result<()> {
+    let (new_list_block_data, new_list_block_info) =
+        get_new_list_not_contain_key(read, list_block_data, key.clone(), true);
+
+    let size_key = key.len();
+    let size_data = data.len();
+    let block_size = size_key + size_data;
+    let sum_key: usize = key.iter().map(|&x| x as usize).sum();
+    let sum_md5: usize = md5::compute(&key)
+        .to_vec()
+        .iter()
+        .map(|&x| x as usize)
+        .sum();
+
+    let list_space = get_list_space(start_list_point, new_list_block_info);
+    let (start_list, start_block) = get_space(list_space, start_list_point, block_size, placement);
+    let info_data = push_block_to_data(
+        Vec::new(),
+        &Block {
+            start: start_block,
+            size_key,
+            sum_key,
+            sum_md5,
+            size_data,
+        },
+    );
+
+    let list_block_data = merge_vec(&[new_list_block_data, info_data]);
+    check_file_size(write, start_list + list_block_data.len() + 1, max_file_size)?;
+    update_list_block(write, start_list, list_block_data)?;
+
+    write.seek(Start(start_block as u64))?;
+    write.write_all(&merge_vec(&[key, data]))
+}
+
+fn set_at_offset_data(
+    read: &mut (impl Read + Seek),
+    write: &mut File,
+    list_block_data: Vec<u8>,
+    (key, data): (Vec<u8>, Vec<u8>),
+    start_block: usize,
+    start_list_point: usize,
+    max_file_size: Option<u64>,
+) -> Result<()> {
+    if start_block < FIRST_SIZE {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("offset {} is inside the header", start_block),
+        ));
+    }
+    let Some(end_block) = start_block
+        .checked_add(key.len())
+        .and_then(|end| end.checked_add(data.len()))
+    else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("block at {} would end past usize::MAX", start_block),
+        ));
+    };
+    let (new_list_block_data, list_block_info) =
+        get_new_list_not_contain_key(read, list_block_data, key.clone(), true);
+    // chỉ kiểm tra vùng sẽ ghi: chồng lấn có sẵn giữa các block khác không liên quan
+    if let Some(block_info) = list_block_info.iter().find(|b| {
+        b.start < end_block
+            && start_block
+                < b.start
+                    .saturating_add(b.size_key)
+                    .saturating_add(b.size_data)
+    }) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "block at {} would overlap the block at {}",
+                start_block, block_info.start
+            ),
+        ));
+    }
+    let info_data = push_block_to_data(
+        Vec::new(),
+        &Block {
+            start: start_block,
+            size_key: key.len(),
+            sum_key: get_sum_key(&key),
+            sum_md5: get_sum_md5(&key),
+            size_data: data.len(),
+        },
+    );
+    let list_block_data = merge_vec(&[new_list_block_data, info_data]);
+    let start_list = start_list_point.max(end_block);
+    check_file_size(write, start_list + list_block_data.len() + 1, max_file_size)?;
+    update_list_block(write, start_list, list_block_data)?;
+
+    write.seek(Start(start_block as u64))?;
+    write.write_all(&merge_vec(&[key, data]))
+}
+
+fn reserve_value_data(
+    write: &mut File,
+    list_block_data: Vec<u8>,
+    key: Vec<u8>,
+    size: usize,
+    start_list_point: usize,
+    max_file_size: Option<u64>,
+    placement: Placement,
+) -> Result<ValueHandle> {
+    let list_space = get_list_space(start_list_point, get_list_block_info(&list_block_data));
+    let (start_list, start_block) =
+        get_space(list_space, start_list_point, key.len() + size, placement);
+    let handle = ValueHandle {
+        key,
+        start: start_block,
+        size,
+    };
+    let list_block_data = push_block_to_data(list_block_data, &handle.placeholder());
+    check_file_size(write, start_list + list_block_data.len() + 1, max_file_size)?;
+    update_list_block(write, start_list, list_block_data)?;
+    Ok(handle)
+}
+
+fn check_reservation(list_block_data: &[u8], handle: &ValueHandle) -> Result<()> {
+    if !get_list_block_info(list_block_data).contains(&handle.placeholder()) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "value handle is not reserved in the block list (finalized or dropped)",
+        ));
+    }
+    Ok(())
+}
+
+fn finalize_value_data(
+    read: &mut (impl Read + Seek),
+    write: &mut File,
+    list_block_data: Vec<u8>,
+    handle: &ValueHandle,
+    start_list_point: usize,
+) -> Result<()> {
+    check_reservation(&list_block_data, handle)?;
+    write.seek(Start(handle.start as u64))?;
+    write.write_all(&handle.key)?;
+
+    let (_, list_block_info) =
+        get_new_list_not_contain_key(read, list_block_data, handle.key.clone(), true);
+    let placeholder = handle.placeholder();
+    let mut new_list_block_data: Vec<u8> = Vec::new();
+    for block_info in list_block_info.iter().filter(|&b| *b != placeholder) {
+        new_list_block_data = push_block_to_data(new_list_block_data, block_info);
+    }
+    let block_info = Block {
+        start: handle.start,
+        size_key: handle.key.len(),
+        sum_key: get_sum_key(&handle.key),
+        sum_md5: get_sum_md5(&handle.key),
+        size_data: handle.size,
+    };
+    new_list_block_data = push_block_to_data(new_list_block_data, &block_info);
+    update_list_block(write, start_list_point, new_list_block_data)
+}
+
+fn append_data(
+    read: &mut (impl Read + Seek),
+    write: &mut File,
+    list_block_data: Vec<u8>,
+    list_data: Vec<(Vec<u8>, Vec<u8>)>,
+    start_list_point: usize,
+    max_file_size: Option<u64>,
+) -> Result<()> {
+    // key lặp lại trong cùng một lần ghi: giữ giá trị cuối cùng
+    let mut map_last_index: HashMap<&[u8], usize> = HashMap::new();
+    for (i, (key, _)) in list_data.iter().enumerate() {
+        map_last_index.insert(key, i);
+    }
+    let (mut new_list_block_data, _) =
+        get_new_list_not_contain_list_key(read, list_block_data, &list_data, false);
+
+    let mut block_data: Vec<u8> = Vec::new();
+    for (i, (key, data)) in list_data.iter().enumerate() {
+        if map_last_index[key.as_slice()] != i {
+            continue;
+        }
+        new_list_block_data.extend(push_block_to_data(
+            Vec::new(),
+            &Block {
+                start: start_list_point + block_data.len(),
+                size_key: key.len(),
+                sum_key: get_sum_key(key),
+                sum_md5: get_sum_md5(key),
+                size_data: data.len(),
+            },
+        ));
+        block_data.extend_from_slice(key);
+        block_data.extend_from_slice(data);
+    }
+    let start_list = start_list_point + block_data.len();
+    check_file_size(
+        write,
+        start_list + new_list_block_data.len() + 1,
+        max_file_size,
+    )?;
+    update_list_block(write, start_list, new_list_block_data)?;
+
+    write.seek(Start(start_list_point as u64))?;
+    write.write_all(&block_data)
+}
+
+fn set_many_data(
+    read: &mut (impl Read + Seek),
+    write: &mut File,
+    list_block_data: Vec<u8>,
+    list_data: Vec<(Vec<u8>, Vec<u8>)>,
+    start_list_point: usize,
+    max_file_size: Option<u64>,
+) -> Result<()> {
+    // key lặp lại trong cùng một lần ghi: chỉ giữ giá trị cuối cùng
+    let mut map_last_index: HashMap<&[u8], usize> = HashMap::new();
+    for (i, (key, _)) in list_data.iter().enumerate() {
+        map_last_index.insert(key, i);
+    }
+    let list_data: Vec<(Vec<u8>, Vec<u8>)> = list_data
+        .iter()
+        .enumerate()
+        .filter(|(i, (key, _))| map_last_index[key.as_slice()] == *i)
+        .map(|(_, item)| item.clone())
+        .collect();
+    let (new_list_block_data, new_list_block_info) =
+        get_new_list_not_contain_list_key(read, list_block_data, &list_data, true);
+
+    let mut min_size_block: usize = 0;
+    let mut list_config_insert: Vec<Block> = Vec::new();
+    {
+        let mut max_size_block: usize = 0;
+        for (i, (key, data)) in list_data.iter().enumerate() {
+            let size_key = key.len();
+            let size_data = data.len();
+            let block_size = size_key + size_data;
+            if min_size_block == 0 || block_size < min_size_block {
+                min_size_block = block_size;
+            }
+            if block_size > max_size_block {
+                max_size_block = block_size;
+            }
+            let sum_key: usize = key.iter().map(|&x| x as usize).sum();
+            let sum_md5: usize = md5::compute(key).to_vec().iter().map(|&x| x as usize).sum();
+            list_config_insert.push(Block {
+                start: i,
+                size_key,
+                sum_key,
+                sum_md5,
+                size_data,
+            });
+        }
+
+        list_config_insert.sort_by(|a, b| {
+            let ka = a.size_key + a.size_data;
+            let kb = b.size_key + b.size_data;
+            kb.cmp(&ka)
+        }); // sort decrement [ max ---> min ]
+    }
+
+    let mut start_list_block = start_list_point;
+    let mut list_info_data: Vec<u8> = Vec::new();
+    let mut selected: HashMap<usize, bool> = HashMap::new();
+    let mut list_write_data: Vec<(usize, Vec<u8>, Vec<u8>)> = Vec::new();
+    let mut total_last_space_used: usize = 0;
+    let mut map_block_insert: HashMap<usize, Block> = HashMap::new();
+
+    {
+        let list_space = get_list_space(start_list_point, new_list_block_info);
+        for s in list_space {
+            let mut this_space_used = 0;
+            if s.size_data < min_size_block && s.sum_key == 0 {
+                continue;
+            }
+            for c in list_config_insert.clone() {
+                if selected.get(&c.start) == Some(&true) {
+                    continue;
+                }
+                let block_size = c.size_key + c.size_data;
+                if s.size_data >= block_size + this_space_used || s.sum_key == 1 {
+                    selected.insert(c.start, true);
+                    {
+                        let start_block = s.start + this_space_used;
+                        let (key, data) = list_data[c.start].clone();
+                        add_to_map_sort(&mut map_block_insert, c, start_block);
+                        list_write_data.push((start_block, key, data));
+                        this_space_used += block_size;
+                    }
+                    if s.sum_key == 1 {
+                        start_list_block = s.start;
+                        total_last_space_used += block_size;
+                    }
+                }
+            }
+        }
+    }
+
+    // nếu những block còn lại không đủ khoảng trống thì xử lý thêm vào cuối
+    for c in list_config_insert.clone() {
+        if selected.get(&c.start) == Some(&true) {
+            continue;
+        }
+        let block_size = c.size_key + c.size_data;
+        let start_block = start_list_block + total_last_space_used;
+        selected.insert(c.start, true);
+        let (key, data) = list_data[c.start].clone();
+        add_to_map_sort(&mut map_block_insert, c, start_block);
+        list_write_data.push((start_block, key, data));
+        total_last_space_used += block_size;
+    }
+
+    for i in 0..list_data.len() {
+        match map_block_insert.get(&i) {
+            None => {
+                continue;
+            }
+            Some(block) => {
+                let info_data = push_block_to_data(Vec::new(), block);
+                list_info_data = merge_vec(&[list_info_data, info_data]);
+            }
+        }
+    }
+
+    let new_list_block_data = merge_vec(&[new_list_block_data, list_info_data]);
+    let start_list = start_list_block + total_last_space_used;
+    check_file_size(
+        write,
+        start_list + new_list_block_data.len() + 1,
+        max_file_size,
+    )?;
+    update_list_block(write, start_list, new_list_block_data)?;
+
+    for (start_block, key, data) in list_write_data {
+        write.seek(Start(start_block as u64))?;
+        write.write_all(&merge_vec(&[key, data]))?;
+    }
+    Ok(())
+}
+
+/// Record where item `c.start` of the batch was placed, keyed by its batch index.
+fn add_to_map_sort(map_block_sort: &mut HashMap<usize, Block>, c: Block, start_block: usize) {
+    map_block_sort.insert(
+        c.start,
+        Block {
+            start: start_block,
+            size_key: c.size_key,
+            sum_key: c.sum_key,
+            sum_md5: c.sum_md5,
+            size_data: c.size_data,
+        },
+    );
+}
+
+fn get_new_list_not_contain_key(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    key: Vec<u8>,
+    is_return_list_info: bool,
+) -> (Vec<u8>, Vec<Block>) {
+    let mut new_list_block_data: Vec<u8> = Vec::new();
+    let mut new_list_block_info: Vec<Block> = Vec::new();
+
+    let len_key = key.len();
+    let sum_key: usize = key.iter().map(|&x| x as usize).sum();
+    let sum_md5: usize = md5::compute(&key)
+        .to_vec()
+        .iter()
+        .map(|&x| x as usize)
+        .sum();
+
+    let mut block_info = EMPTY_BLOCK;
+    let mut tmp_group: Vec<u8> = Vec::new();
+    for v in list_block_data {
+        match v {
+            START => {
+                block_info.start = digits_to_number(&tmp_group);
+                tmp_group.clear();
+            }
+            SIZE_KEY => {
+                block_info.size_key = digits_to_number(&tmp_group);
+                tmp_group.clear();
+            }
+            SUM_KEY => {
+                block_info.sum_key = digits_to_number(&tmp_group);
+                tmp_group.clear();
+            }
+            SUM_MD5 => {
+                block_info.sum_md5 = digits_to_number(&tmp_group);
+                tmp_group.clear();
+            }
+            SIZE_DATA => {
+                block_info.size_data = digits_to_number(&tmp_group);
+                tmp_group.clear();
+                {
+                    {
+                        if block_info.size_key == len_key
+                            && block_info.sum_key == sum_key
+                            && block_info.sum_md5 == sum_md5
+                        {
+                            let found_key =
+                                pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
+                            if found_key == key {
+                                // success
+                                continue;
+                            }
+                        }
+                    }
+                    new_list_block_data = push_block_to_data(new_list_block_data, &block_info);
+                    if is_return_list_info {
+                        new_list_block_info.push(block_info);
+                    }
+                }
+                block_info = EMPTY_BLOCK;
+            }
+            END => {
+                break;
+            }
+            _ => {
+                tmp_group.push(v);
+            }
+        }
+    }
+    (new_list_block_data, new_list_block_info)
+}
+
+fn get_new_list_not_contain_list_key(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    list_data: &Vec<(Vec<u8>, Vec<u8>)>,
+    is_return_list_info: bool,
+) -> (Vec<u8>, Vec<Block>) {
+    let mut list_skip_check: HashMap<Vec<u8>, bool> = HashMap::new();
+
+    let mut new_list_block_data: Vec<u8> = Vec::new();
+    let mut new_list_block_info: Vec<Block> = Vec::new();
+
+    let mut block_info = EMPTY_BLOCK;
+    let mut tmp_group: Vec<u8> = Vec::new();
+    for v in list_block_data {
+        match v {
+            START => {
+                block_info.start = digits_to_number(&tmp_group);
+                tmp_group.clear();
+            }
+            SIZE_KEY => {
+                block_info.size_key = digits_to_number(&tmp_group);
+                tmp_group.clear();
+            }
+            SUM_KEY => {
+                block_info.sum_key = digits_to_number(&tmp_group);
+                tmp_group.clear();
+            }
+            SUM_MD5 => {
+                block_info.sum_md5 = digits_to_number(&tmp_group);
+                tmp_group.clear();
+            }
+            SIZE_DATA => {
+                block_info.size_data = digits_to_number(&tmp_group);
+                tmp_group.clear();
+                {
+                    {
+                        let mut is_found_key = false;
+                        for (cur_key, _) in list_data {
+                            if list_skip_check.get(cur_key) == Some(&true) {
+                                continue;
+                            }
+                            let key = cur_key.clone();
+                            let len_key = key.len();
+                            let sum_key: usize = key.iter().map(|&x| x as usize).sum();
+                            let sum_md5: usize = md5::compute(&key)
+                                .to_vec()
+                                .iter()
+                                .map(|&x| x as usize)
+                                .sum();
+                            {
+                                if block_info.size_key == len_key
+                                    && block_info.sum_key == sum_key
+                                    && block_info.sum_md5 == sum_md5
+                                {
+                                    let found_key =
+                                        pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
+                                    if found_key == key {
+                                        // success
+                                        is_found_key = true;
+                                        list_skip_check.insert(key, true);
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                        if is_found_key {
+                            continue;
+                        }
+                    }
+                    new_list_block_data = push_block_to_data(new_list_block_data, &block_info);
+                    if is_return_list_info {
+                        new_list_block_info.push(block_info);
+                    }
+                }
+                block_info = EMPTY_BLOCK;
+            }
+            END => {
+                break;
+            }
+            _ => {
+                tmp_group.push(v);
+            }
+        }
+    }
+    (new_list_block_data, new_list_block_info)
+}
+
+fn push_block_to_data(list_block_data: Vec<u8>, block_info: &Block) -> Vec<u8> {
+    merge_vec(&[
+        list_block_data,
+        group_digits_to_vec(block_info.start),
+        vec![START],
+        group_digits_to_vec(block_info.size_key),
+        vec![SIZE_KEY],
+        group_digits_to_vec(block_info.sum_key),
+        vec![SUM_KEY],
+        group_digits_to_vec(block_info.sum_md5),
+        vec![SUM_MD5],
+        group_digits_to_vec(block_info.size_data),
+        vec![SIZE_DATA],
+    ])
+}
+
+fn get_list_space(start_list_point: usize, list_block_info: Vec<Block>) -> Vec<Block> {
+    let mut list_start_block: Vec<usize> = Vec::new();
+    let mut map_start_block: HashMap<usize, usize> = HashMap::new();
+    for b in list_block_info {
+        list_start_block.push(b.start);
+        map_start_block.insert(b.start, b.size_key + b.size_data);
+    }
+    list_start_block.sort();
+
+    // khoảng trống là cả đoạn giữa hai block còn trong list, nên các block đã xoá nằm
+    // liền nhau luôn thành một khoảng; không có free list nào phải gộp thêm
+    let mut current_point = FIRST_SIZE;
+    let mut list_space: Vec<Block> = Vec::new();
+    for start in list_start_block {
+        if current_point < start {
+            list_space.push(Block {
+                start: current_point,
+                size_key: 0,
+                sum_key: 0,
+                sum_md5: 0,
+                size_data: start - current_point,
+            });
+        }
+        current_point = start + map_start_block[&start];
+    }
+    let last_space_size = start_list_point.saturating_sub(current_point);
+    if last_space_size > 0 {
+        list_space.push(Block {
+            start: current_point,
+            size_key: 0,
+            sum_key: 1, // space này là vị trí còn trống cuối cùng trước list
+            sum_md5: 0,
+            size_data: last_space_size,
+        });
+    }
+    list_space
+}
+
+fn get_perfect_space(
+    list_space: Vec<Block>,
+    start_list_point: usize,
+    block_size: usize,
+) -> (usize, usize) {
+    let mut perfect_block_size: usize = block_size;
+    let mut perfect_free_size: usize = 0;
+    let mut start_block = start_list_point;
+    let mut is_last_space: bool = false;
+    for s in list_space {
+        if s.size_data >= block_size || s.sum_key == 1 {
+            let new_size = s.size_data;
+            if perfect_free_size == 0
+                || (perfect_free_size > new_size && start_list_point > s.start + s.size_data)
+            {
+                perfect_free_size = new_size;
+                start_block = s.start;
+                is_last_space = s.sum_key == 1;
+            }
+        }
+    }
+    if perfect_free_size >= perfect_block_size {
+        perfect_block_size = 0
+    }
+    let start_list = if is_last_space {
+        start_block + block_size
+    } else {
+        start_list_point + perfect_block_size
+    };
+    (start_list, start_block)
+}
+
+/// Pick the hole for a new block with the allocator selected by `placement`.
+fn get_space(
+    list_space: Vec<Block>,
+    start_list_point: usize,
+    block_size: usize,
+    placement: Placement,
+) -> (usize, usize) {
+    let alignment = placement.alignment.max(1);
+    let list_space = align_list_space(list_space, alignment);
+    let (start_list, start_block) = if placement.size_classes {
+        get_class_space(list_space, start_list_point, block_size)
+    } else {
+        get_perfect_space(list_space, start_list_point, block_size)
+    };
+    if start_block == start_list_point {
+        // không có lỗ nào vừa: block nằm sau list cũ, từ mốc căn lề kế tiếp
+        let start_block = align_up(start_list_point, alignment);
+        return (start_block + block_size, start_block);
+    }
+    (start_list, start_block)
+}
+
+/// Round `n` up to a multiple of `alignment` (at least 1).
+fn align_up(n: usize, alignment: usize) -> usize {
+    n.div_ceil(alignment) * alignment
+}
+
+/// Shrink each hole to the part starting at a multiple of `alignment`; the padding in
+/// front stays free space. Holes left empty are dropped.
+fn align_list_space(list_space: Vec<Block>, alignment: usize) -> Vec<Block> {
+    list_space
+        .into_iter()
+        .filter_map(|s| {
+            let start = align_up(s.start, alignment);
+            let padding = start - s.start;
+            (s.size_data > padding).then(|| Block {
+                start,
+                size_data: s.size_data - padding,
+                ..s
+            })
+        })
+        .collect()
+}
+
+/// Size-class variant of `get_perfect_space`: holes are grouped by the power of two
+/// below their size, and the first hole that fits in the smallest class holding one is
+/// used. The gap before the list is only used when no other hole fits.
+fn get_class_space(
+    list_space: Vec<Block>,
+    start_list_point: usize,
+    block_size: usize,
+) -> (usize, usize) {
+    let mut map_class_space: BTreeMap<u32, Vec<Block>> = BTreeMap::new();
+    let mut last_space: Option<Block> = None;
+    for s in list_space {
+        if s.sum_key == 1 {
+            last_space = Some(s);
+        } else {
+            map_class_space
+                .entry(s.size_data.ilog2())
+                .or_default()
+                .push(s);
+        }
+    }
+    for list_class_space in map_class_space
+        .range(block_size.max(1).ilog2()..)
+        .map(|(_, v)| v)
+    {
+        if let Some(s) = list_class_space.iter().find(|s| s.size_data >= block_size) {
+            return (start_list_point, s.start);
+        }
+    }
+    match last_space {
+        Some(s) => (s.start + block_size, s.start),
+        None => (start_list_point + block_size, start_list_point),
+    }
+}
+
+/// Create an empty bucket file and make its directory entry durable.
+fn create_bucket_file(path: &str) -> Result<()> {
+    File::create(path)?.sync_all()?;
+    sync_parent_dir(path)
+}
+
+/// Fsync the directory holding `path` so a newly created entry survives a crash.
+#[cfg(unix)]
+fn sync_parent_dir(path: &str) -> Result<()> {
+    let dir = match std::path::Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => std::path::Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+/// Directories cannot be opened as files here; nothing to sync.
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &str) -> Result<()> {
+    Ok(())
+}
+
+fn get_format_version(read: &mut (impl Read + Seek)) -> Result<u32> {
+    let file_size = read.seek(End(0))?;
+    if file_size == 0 {
+        return Ok(FORMAT_VERSION);
+    }
+    if file_size < FIRST_SIZE as u64 {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "truncated bucket file: shorter than the header",
+        ));
+    }
+    read.seek(Start(0))?;
+    let mut buffer = vec![0u8; FIRST_SIZE];
+    read.read_exact(&mut buffer)?;
+    // checksum nằm sau END thứ hai và được đóng bằng SUM_MD5
+    let checksum_data = buffer.split(|&x| x == END).nth(2).unwrap_or(&[]);
+    if checksum_data.contains(&SUM_MD5) {
+        Ok(FORMAT_VERSION)
+    } else {
+        Ok(0)
+    }
+}
+
+/// Bytes of the block list sampled by `approx_len` to measure the average entry size.
+const APPROX_SAMPLE_SIZE: usize = 256;
+
+/// Estimate the number of blocks from the list size in the header and the average size
+/// of the entries in the first `APPROX_SAMPLE_SIZE` bytes of the list.
+fn get_approx_len_data(read: &mut (impl Read + Seek)) -> Result<usize> {
+    let file_size = read.seek(End(0))?;
+    if file_size < FIRST_SIZE as u64 {
+        return Ok(0);
+    }
+    let mut buffer = vec![0u8; META_START];
+    read.seek(Start(0))?;
+    read.read_exact(&mut buffer)?;
+    let list_part: Vec<&[u8]> = buffer.splitn(3, |&x| x == END).collect();
+    if list_part.len() < 3 {
+        return Ok(0);
+    }
+    let start_list_point = digits_to_number(list_part[0]) as u64;
+    let size_list = digits_to_number(list_part[1]);
+    if start_list_point < FIRST_SIZE as u64 || size_list == 0 {
+        return Ok(0);
+    }
+    let size_sample = size_list
+        .min(APPROX_SAMPLE_SIZE)
+        .min(file_size.saturating_sub(start_list_point) as usize);
+    let mut sample = vec![0u8; size_sample];
+    read.seek(Start(start_list_point))?;
+    read.read_exact(&mut sample)?;
+    let count = sample.iter().filter(|&&x| x == SIZE_DATA).count();
+    let Some(last) = sample.iter().rposition(|&x| x == SIZE_DATA) else {
+        return Ok(0);
+    };
+    Ok((size_list * count).div_ceil(last + 1))
+}
+
+/// Compare the block list against the header checksum without the other list checks.
+///
+/// Returns `(checksum_ok, header_headroom)`; `checksum_ok` is `None` when the header
+/// has no checksum or does not point at a readable list.
+fn get_header_status(read: &mut (impl Read + Seek)) -> Result<(Option<bool>, usize)> {
+    let file_size = read.seek(End(0))?;
+    if file_size == 0 {
+        return Ok((Some(true), META_START));
+    }
+    let mut buffer = vec![0u8; FIRST_SIZE.min(file_size as usize)];
+    read.seek(Start(0))?;
+    read.read_exact(&mut buffer)?;
+    let list_part: Vec<&[u8]> = buffer[..META_START.min(buffer.len())]
+        .splitn(3, |&x| x == END)
+        .collect();
+    if list_part.len() < 3 {
+        return Ok((None, 0));
+    }
+    let size_config = list_part[0].len() + list_part[1].len() + 2;
+    let Some(size_checksum) = list_part[2].iter().position(|&x| x == SUM_MD5) else {
+        return Ok((None, META_START.saturating_sub(size_config)));
+    };
+    let header_headroom = META_START.saturating_sub(size_config + size_checksum + 1);
+
+    let start_list_point = digits_to_number(list_part[0]) as u64;
+    let size_list = digits_to_number(list_part[1]) as u64;
+    if start_list_point < FIRST_SIZE as u64
+        || start_list_point.saturating_add(size_list) > file_size
+    {
+        return Ok((None, header_headroom));
+    }
+    let mut list_block_data = vec![0u8; size_list as usize];
+    read.seek(Start(start_list_point))?;
+    read.read_exact(&mut list_block_data)?;
+    if let Some(pos) = list_block_data.iter().position(|&x| x == END) {
+        list_block_data.truncate(pos);
+    }
+    let checksum = digits_to_checksum(&list_part[2][..size_checksum]);
+    Ok((
+        Some(get_checksum(&list_block_data) == checksum),
+        header_headroom,
+    ))
+}
+
+fn get_health_data(read: &mut (impl Read + Seek)) -> HealthReport {
+    let mut report = HealthReport::default();
+    if let Ok((checksum_ok, header_headroom)) = get_header_status(read) {
+        report.checksum_ok = checksum_ok;
+        report.header_headroom = header_headroom;
+    }
+    let Ok((start_list_point, list_block_data)) = get_list_config(read) else {
+        return report;
+    };
+    report.header_ok = true;
+    report.overlaps = get_overlaps_data(list_block_data.clone()).len();
+    report.duplicate_keys = get_duplicates_data(read, list_block_data.clone()).len();
+    let size_data_region = start_list_point - FIRST_SIZE;
+    if size_data_region > 0 {
+        let list_space = get_list_space(start_list_point, get_list_block_info(&list_block_data));
+        let total_free: usize = list_space.iter().map(|s| s.size_data).sum();
+        report.fragmentation = total_free as f64 / size_data_region as f64;
+    }
+    report
+}
+
+fn get_list_config(read: &mut (impl Read + Seek)) -> Result<(usize, Vec<u8>)> {
+    let file_size = read.seek(End(0))?;
+    if file_size == 0 {
+        // file mới tạo, chưa có header
+        return Ok((FIRST_SIZE, Vec::new()));
+    }
+    if file_size < FIRST_SIZE as u64 {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "truncated bucket file: shorter than the header",
+        ));
+    }
+    read.seek(Start(0))?;
+    let mut buffer = vec![0u8; FIRST_SIZE];
+    read.read_exact(&mut buffer)?;
+
+    let mut start_list_data = Vec::new();
+    let mut size_list_data = Vec::new();
+    let mut checksum_list_data = Vec::new();
+    let mut has_checksum = false;
+    {
+        let mut position_list_check: u8 = 0;
+        for v in buffer {
+            if position_list_check == 2 {
+                // checksum được đóng bằng SUM_MD5, header cũ (chỉ có END) sẽ không có checksum
+                if v == SUM_MD5 {
+                    has_checksum = true;
+                    break;
+                }
+                if v == END {
+                    break;
+                }
+                checksum_list_data.push(v);
+                continue;
+            }
+            if v == END {
+                position_list_check += 1;
+                continue;
+            }
+            if position_list_check == 0 {
+                start_list_data.push(v)
+            } else {
+                size_list_data.push(v)
+            }
+        }
+    }
+    // println!("0 === {:?} {:?}", start_list_data, size_list_data);
+
+    let mut start_list_point = digits_to_number(&start_list_data);
+    let mut list_block_data: Vec<u8>;
+    {
+        if start_list_point < FIRST_SIZE {
+            start_list_point = FIRST_SIZE;
+            list_block_data = Vec::new();
+        } else {
+            let size_list = digits_to_number(&size_list_data);
+            if (start_list_point as u64).saturating_add(size_list as u64) > file_size {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "truncated bucket file: block list extends past the end",
+                ));
+            }
+            read.seek(Start(start_list_point as u64))?;
+            list_block_data = vec![0u8; size_list];
+            read.read_exact(&mut list_block_data)?;
+            if let Some(pos) = list_block_data.iter().position(|&x| x == END) {
+                list_block_data.truncate(pos);
+            }
+            if !is_valid_list(&list_block_data) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "corrupt block list: incomplete block entry",
+                ));
+            }
+            if has_checksum
+                && get_checksum(&list_block_data) != digits_to_checksum(&checksum_list_data)
+            {
+                return Err(Error::new(ErrorKind::InvalidData, CHECKSUM_MISMATCH));
+            }
+            if !is_valid_block_range(&list_block_data, start_list_point) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "corrupt block list: block outside the data region",
+                ));
+            }
+        }
+    }
+    // println!("1 === {:?} {:?}", start_list_point, list_block_data);
+
+    Ok((start_list_point, list_block_data))
+}
+
+/// Check that the list is a sequence of complete block entries.
+///
+/// Offsets and sizes are stored as digit groups (`<= MAX_DIGIT_GROUP`), so only the
+/// markers need checking: they must repeat `START .. SIZE_DATA` and end on `SIZE_DATA`.
+fn is_valid_list(list_block_data: &[u8]) -> bool {
+    let list_marker = [START, SIZE_KEY, SUM_KEY, SUM_MD5, SIZE_DATA];
+    let mut next_marker = 0;
+    for &v in list_block_data {
+        if v <= MAX_DIGIT_GROUP {
+            continue;
+        }
+        if v != list_marker[next_marker] {
+            return false;
+        }
+        next_marker = (next_marker + 1) % list_marker.len();
+    }
+    next_marker == 0
+}
+
+fn pull_meta_region(read: &mut (impl Read + Seek)) -> Result<Vec<u8>> {
+    let mut buffer = vec![0u8; FIRST_SIZE - META_START];
+    if read.seek(End(0))? >= FIRST_SIZE as u64 {
+        read.seek(Start(META_START as u64))?;
+        read.read_exact(&mut buffer)?;
+    }
+    Ok(buffer)
+}
+
+/// Entries are `len(name) name len(value) value`; a zero name length ends the region.
+fn get_list_meta(meta_data: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut result: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    let mut i = 0;
+    while i < meta_data.len() && meta_data[i] > 0 {
+        let end_name = i + 1 + meta_data[i] as usize;
+        let Some(&size_value) = meta_data.get(end_name) else {
+            break;
+        };
+        let end_value = end_name + 1 + size_value as usize;
+        if end_value > meta_data.len() {
+            break;
+        }
+        result.push((
+            meta_data[i + 1..end_name].to_vec(),
+            meta_data[end_name + 1..end_value].to_vec(),
+        ));
+        i = end_value;
+    }
+    result
+}
+
+fn set_meta_data(
+    read: &mut (impl Read + Seek),
+    write: &mut File,
+    start_list_point: usize,
+    list_block_data: Vec<u8>,
+    name: &[u8],
+    value: &[u8],
+) -> Result<()> {
+    if name.is_empty() || name.len() > u8::MAX as usize || value.len() > u8::MAX as usize {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "metadata name must be 1..=255 bytes and value at most 255 bytes",
+        ));
+    }
+    let mut list_meta = get_list_meta(&pull_meta_region(read)?);
+    match list_meta.iter_mut().find(|(n, _)| n == name) {
+        Some(meta) => meta.1 = value.to_vec(),
+        None => list_meta.push((name.to_vec(), value.to_vec())),
+    }
+    let mut meta_data: Vec<u8> = Vec::new();
+    for (n, v) in list_meta {
+        meta_data.push(n.len() as u8);
+        meta_data.extend(n);
+        meta_data.push(v.len() as u8);
+        meta_data.extend(v);
+    }
+    if meta_data.len() > FIRST_SIZE - META_START {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "bucket metadata does not fit in the header",
+        ));
+    }
+    meta_data.resize(FIRST_SIZE - META_START, 0);
+    // ghi lại header theo dạng có checksum để phần meta không bị đọc nhầm là checksum
+    update_list_block(write, start_list_point, list_block_data)?;
+    write.seek(Start(META_START as u64))?;
+    write.write_all(&meta_data)
+}
+
+/// Check that every block lies within `[FIRST_SIZE, start_list_point)`.
+///
+/// Later offset arithmetic and buffer sizes rely on this to stay within the file.
+fn is_valid_block_range(list_block_data: &[u8], start_list_point: usize) -> bool {
+    get_list_block_info(list_block_data)
+        .iter()
+        .all(|block_info| {
+            block_info.start >= FIRST_SIZE
+                && block_info
+                    .start
+                    .checked_add(block_info.size_key)
+                    .and_then(|end| end.checked_add(block_info.size_data))
+                    .is_some_and(|end| end <= start_list_point)
+        })
+}
+
+fn get_checksum(list_block_data: &[u8]) -> u64 {
+    let digest = md5::compute(list_block_data);
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest.0[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn get_compact_list(list_block_info: &[Block], alignment: usize) -> (usize, Vec<u8>) {
+    let mut new_list_block_data: Vec<u8> = Vec::new();
+    let mut current_point = FIRST_SIZE;
+    for block_info in list_block_info {
+        current_point = align_up(current_point, alignment.max(1));
+        new_list_block_data = push_block_to_data(
+            new_list_block_data,
+            &Block {
+                start: current_point,
+                ..block_info.clone()
+            },
+        );
+        current_point += block_info.size_key + block_info.size_data;
+    }
+    (current_point, new_list_block_data)
+}
+
+fn write_compact_file(
+    read: &mut (impl Read + Seek),
+    list_block_data: Vec<u8>,
+    tmp_path: &str,
+    alignment: usize,
+    write_buffer: usize,
+) -> Result<()> {
+    // không bao giờ ghi đè một file đã có (kể cả chính file của bucket)
+    let mut write = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(tmp_path)?;
+    let list_block_info = get_list_block_info(&list_block_data);
+    write.seek(Start(FIRST_SIZE as u64))?;
+    {
+        // các block được ghi nối tiếp nhau nên ghi qua buffer, khoảng đệm là byte 0
+        let mut buffer = BufWriter::with_capacity(write_buffer, &mut write);
+        let mut current_point = FIRST_SIZE;
+        for block_info in &list_block_info {
+            let padding = align_up(current_point, alignment.max(1)) - current_point;
+            buffer.write_all(&vec![0u8; padding])?;
+            current_point += padding;
+            let (key, data) = pull_data(read, block_info)?;
+            buffer.write_all(&key)?;
+            buffer.write_all(&data)?;
+            current_point += block_info.size_key + block_info.size_data;
+        }
+        // dữ liệu phải nằm trong file trước khi ghi header
+        buffer.flush()?;
+    }
+    let (start_list_point, new_list_block_data) = get_compact_list(&list_block_info, alignment);
+    update_list_block(&mut write, start_list_point, new_list_block_data)?;
+    write.seek(Start(META_START as u64))?;
+    write.write_all(&pull_meta_region(read)?)?;
+    write.sync_all()
+}
+
+fn compact_step_data(
+    read: &mut (impl Read + Seek),
+    write: &mut File,
+    start_list_point: usize,
+    list_block_data: Vec<u8>,
+    max_blocks: usize,
+    alignment: usize,
+) -> Result<bool> {
+    let mut list_block_info = get_list_block_info(&list_block_data);
+    let mut list_index: Vec<usize> = (0..list_block_info.len()).collect();
+    list_index.sort_by_key(|&i| list_block_info[i].start);
+
+    // block chỉ được chép vào vùng trống theo list hiện tại: sau list cũ hoặc vào khoảng
+    // trống không đè lên chính nó, để crash trước khi ghi list không làm hỏng dữ liệu
+    let mut tail_point = start_list_point + list_block_data.len() + 1;
+    let mut moved: usize = 0;
+    let mut current_point = FIRST_SIZE;
+    // cuối chỗ cũ của các block đã dời trong lần gọi này: list trên đĩa vẫn trỏ tới đó
+    // cho tới khi ghi list mới, nên không được chép block khác vào
+    let mut moved_from_end = FIRST_SIZE;
+    let mut is_done = true;
+    for i in list_index {
+        let block_size = list_block_info[i].size_key + list_block_info[i].size_data;
+        // chỗ giữ của reserve_value: ValueHandle trỏ tới vị trí này, không được dời
+        let is_placeholder = list_block_info[i].size_key == 0 && list_block_info[i].sum_md5 == 0;
+        current_point = align_up(current_point, alignment.max(1));
+        if list_block_info[i].start > current_point
+            && !is_placeholder
+            && current_point < moved_from_end
+        {
+            // khoảng trống này lần gọi sau mới lấp được
+            current_point =
+                align_up(moved_from_end, alignment.max(1)).min(list_block_info[i].start);
+            is_done = false;
+        }
+        if list_block_info[i].start > current_point && !is_placeholder {
+            if moved >= max_blocks {
+                is_done = false;
+                break;
+            }
+            let (key, data) = pull_data(read, &list_block_info[i])?;
+            moved_from_end = moved_from_end.max(list_block_info[i].start + block_size);
+            if current_point + block_size <= list_block_info[i].start {
+                write.seek(Start(current_point as u64))?;
+                write.write_all(&merge_vec(&[key, data]))?;
+                list_block_info[i].start = current_point;
+            } else {
+                // khoảng trống nhỏ hơn block: chuyển block ra sau list, lần sau khoảng
+                // trống đã gồm cả chỗ cũ của nó
+                tail_point = align_up(tail_point, alignment.max(1));
+                write.seek(Start(tail_point as u64))?;
+                write.write_all(&merge_vec(&[key, data]))?;
+                list_block_info[i].start = tail_point;
+                tail_point += block_size;
+                is_done = false;
+                moved += 1;
+                continue;
+            }
+            moved += 1;
+        }
+        current_point = current_point.max(list_block_info[i].start + block_size);
+    }
+
+    let new_list_block_data = list_block_info.iter().fold(Vec::new(), push_block_to_data);
+    if !is_done {
+        let start_list = if tail_point > start_list_point + list_block_data.len() + 1 {
+            tail_point
+        } else {
+            start_list_point
+        };
+        update_list_block(write, start_list, new_list_block_data)?;
+        return Ok(true);
+    }
+    let end_point = current_point + new_list_block_data.len() + 1;
+    if moved == 0
+        && current_point == start_list_point
+        && write.metadata()?.len() == end_point as u64
+    {
+        return Ok(false);
+    }
+    if current_point < moved_from_end {
+        // list mới sẽ đè lên chỗ cũ của block vừa dời: ghi sau chỗ đó (hoặc ra cuối file
+        // nếu đè lên list cũ), lần sau mới dời về sát data
+        let start_list = if moved_from_end + new_list_block_data.len() < start_list_point {
+            moved_from_end
+        } else {
+            write.metadata()?.len() as usize
+        };
+        update_list_block(write, start_list, new_list_block_data)?;
+        return Ok(true);
+    }
+    if current_point < start_list_point && end_point > start_list_point {
+        // list mới sẽ đè lên list cũ: ghi ra cuối file trước, lần sau mới dời về sát data
+        let file_size = write.metadata()?.len() as usize;
+        update_list_block(write, file_size, new_list_block_data)?;
+        return Ok(true);
+    }
+    // không còn khoảng trống giữa các block: dời list về sát data rồi cắt file
+    update_list_block(write, current_point, new_list_block_data)?;
+    write.set_len(end_point as u64)?;
+    Ok(false)
+}
+
+/// Keep the last `max_entries` readable blocks of the block list and drop every block
+/// before them, returning how many were dropped.
+///
+/// Only blocks whose key validates are counted; unreadable blocks among the kept ones
+/// stay. Placeholders of `reserve_value` are not counted and always kept.
+fn trim_list_data(
+    read: &mut (impl Read + Seek),
+    write: &mut File,
+    start_list_point: usize,
+    list_block_data: Vec<u8>,
+    max_entries: usize,
+) -> Result<usize> {
+    let list_block_info = get_list_block_info(&list_block_data);
+    let is_placeholder = |block_info: &Block| block_info.size_key == 0 && block_info.sum_md5 == 0;
+    // số block không phải chỗ giữ là cận trên: chưa vượt thì không cần đọc key
+    let entries = list_block_info
+        .iter()
+        .filter(|b| !is_placeholder(b))
+        .count();
+    if entries <= max_entries {
+        return Ok(0);
+    }
+    let mut kept: usize = 0;
+    let mut cut_index: usize = 0;
+    for (i, block_info) in list_block_info.iter().enumerate().rev() {
+        if is_placeholder(block_info) {
+            continue;
+        }
+        if pull_key(read, block_info).is_ok_and(|found_key| is_valid_key(&found_key, block_info)) {
+            if kept == max_entries {
+                cut_index = i + 1;
+                break;
+            }
+            kept += 1;
+        }
+    }
+    let mut dropped: usize = 0;
+    let mut new_list_block_data: Vec<u8> = Vec::new();
+    for (i, block_info) in list_block_info.iter().enumerate() {
+        if i < cut_index && !is_placeholder(block_info) {
+            dropped += 1;
+            continue;
+        }
+        new_list_block_data.extend(push_block_to_data(Vec::new(), block_info));
+    }
+    if dropped > 0 {
+        update_list_block(write, start_list_point, new_list_block_data)?;
+    }
+    Ok(dropped)
+}
+
+fn bulk_load_data(
+    write: &mut File,
+    list_data: impl Iterator<Item = (Vec<u8>, Vec<u8>)>,
+    max_file_size: Option<u64>,
+    (alignment, write_buffer): (usize, usize),
+) -> Result<()> {
+    let mut new_list_block_data: Vec<u8> = Vec::new();
+    let mut current_point = FIRST_SIZE;
+    write.seek(Start(FIRST_SIZE as u64))?;
+    {
+        let mut buffer = BufWriter::with_capacity(write_buffer, &mut *write);
+        for (key, data) in list_data {
+            let padding = align_up(current_point, alignment.max(1)) - current_point;
+            buffer.write_all(&vec![0u8; padding])?;
+            current_point += padding;
+            let block_info = Block {
+                start: current_point,
+                size_key: key.len(),
+                sum_key: get_sum_key(&key),
+                sum_md5: get_sum_md5(&key),
+                size_data: data.len(),
+            };
+            new_list_block_data.extend(push_block_to_data(Vec::new(), &block_info));
+            current_point += key.len() + data.len();
+            check_file_size(
+                buffer.get_ref(),
+                current_point + new_list_block_data.len() + 1,
+                max_file_size,
+            )?;
+            buffer.write_all(&key)?;
+            buffer.write_all(&data)?;
+        }
+        buffer.flush()?;
+    }
+    update_list_block(write, current_point, new_list_block_data)
+}
+
+/// Fail with `ErrorKind::StorageFull` if ending the file at `end` grows it past
+/// `max_file_size`. A file already over the cap may keep its size or shrink.
+fn check_file_size(write: &File, end: usize, max_file_size: Option<u64>) -> Result<()> {
+    let Some(max_file_size) = max_file_size else {
+        return Ok(());
+    };
+    if end as u64 > max_file_size && end as u64 > write.metadata()?.len() {
+        return Err(Error::new(
+            ErrorKind::StorageFull,
+            format!(
+                "write would grow the bucket file to {} bytes, over max_file_size {}",
+                end, max_file_size
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn update_list_block(write: &mut File, start: usize, list_block_data: Vec<u8>) -> Result<()> {
+    let first_block_data = merge_vec(&[
+        group_digits_to_vec(start),
+        vec![END],
+        group_digits_to_vec(list_block_data.len()),
+        vec![END],
+        group_u64_digits_to_vec(get_checksum(&list_block_data)),
+        vec![SUM_MD5],
+    ]);
+    write.seek(Start(start as u64))?;
+    write.write_all(&merge_vec(&[list_block_data, vec![END]]))?;
+    write.seek(Start(0))?;
+    write.write_all(&first_block_data)
+}
+
+#[cfg(test)]
+mod test_md5 {
+    #[test]
+    fn test_md5() {
+        let digest = md5::compute(b"abcdefghijklmnopqrstuvwxyz");
+        assert_eq!(format!("{:x}", digest), "c3fcd3d76192e4007dfb496cca67e13b");
+    }
+}
+
+/// Whether `path` now names a different file than the open handle `file`.
+#[cfg(unix)]
+fn is_file_replaced(path: &str, file: &File) -> Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let on_disk = fs::metadata(path)?;
+    let opened = file.metadata()?;
+    Ok((on_disk.dev(), on_disk.ino()) != (opened.dev(), opened.ino()))
+}
+
+/// File identity is not exposed on stable Rust here; replacement is never detected.
+#[cfg(not(unix))]
+fn is_file_replaced(_path: &str, _file: &File) -> Result<bool> {
+    Ok(false)
+}
+
+/// Take the exclusive lock of `file` through a duplicated handle that shares it.
+fn lock_exclusive(file: &File) -> Result<LockGuard> {
+    let file = file.try_clone()?;
+    file.lock()?;
+    Ok(LockGuard(file))
+}
+
+impl Bucket {
+    fn write_shards(
+        &mut self,
+        n: usize,
+        dest_prefix: &str,
+        hash: impl Fn(&[u8]) -> u64,
+    ) -> Result<Vec<Self>> {
+        let (_, list_block_data) = get_list_config(&mut self.reader)?;
+        let list_block_info = get_list_block_info(&list_block_data);
+        let mut list_shard_index: Vec<Option<usize>> = Vec::new();
+        for block_info in &list_block_info {
+            let found_key = pull_key(&mut self.reader, block_info)?;
+            list_shard_index.push(
+                is_valid_key(&found_key, block_info)
+                    .then(|| (hash(&found_key) % n as u64) as usize),
+            );
+        }
+
+        let mut result: Vec<Self> = Vec::new();
+        for i in 0..n {
+            let mut shard = Self::new(format!("{}{}.db", dest_prefix, i))?;
+            let read = &mut self.reader;
+            shard.bulk_load(
+                list_block_info
+                    .iter()
+                    .zip(&list_shard_index)
+                    .filter(|(_, shard_index)| **shard_index == Some(i))
+                    .filter_map(|(block_info, _)| pull_data(read, block_info).ok()),
+            )?;
+            result.push(shard);
+        }
+        Ok(result)
+    }
+
+    fn evict_oldest(&mut self) -> Result<()> {
+        let Some(max_entries) = self.max_entries else {
+            return Ok(());
+        };
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        trim_list_data(
+            &mut self.reader,
+            &mut self.writer,
+            start_list_point,
+            list_block_data,
+            max_entries,
+        )?;
+        Ok(())
+    }
+
+    fn check_deletable(&self) -> Result<()> {
+        if self.worm {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "bucket is write-once (WORM): delete is disabled",
+            ));
+        }
+        Ok(())
+    }
+
+    fn lock_read(&mut self) -> Result<Option<LockGuard>> {
+        self.reopen_if_replaced()?;
+        if self.access_mode != AccessMode::SingleWriterMultiReader {
+            return Ok(None);
+        }
+        // bản sao handle dùng chung lock với reader, unlock khi LockGuard bị drop
+        let file = self.reader.try_clone()?;
+        file.lock_shared()?;
+        Ok(Some(LockGuard(file)))
+    }
+
+    fn lock_write(&mut self) -> Result<LockGuard> {
+        self.reopen_if_replaced()?;
+        lock_exclusive(&self.writer)
+    }
+
+    fn reopen_if_replaced(&mut self) -> Result<()> {
+        if self.auto_reopen && is_file_replaced(&self.path, &self.reader)? {
+            self.reopen()?;
+        }
+        Ok(())
+    }
+
+    fn lock_with_retry(&mut self, retries: u32, backoff: Duration) -> Result<LockGuard> {
+        self.reopen_if_replaced()?;
+        let file = self.writer.try_clone()?;
+        let mut attempt: u32 = 0;
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(LockGuard(file)),
+                Err(TryLockError::WouldBlock) if attempt < retries => {
+                    attempt += 1;
+                    thread::sleep(backoff);
                 }
-                SIZE_DATA => {
-                    block_info.size_data = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                    {
-                        let (found_key, found_data) = pull_data(read, &block_info)
-                            .unwrap_or_else(|_| (Vec::new(), Vec::new()));
-                        let len_found_key = found_key.len();
-                        if len_found_key == block_info.size_key {
-                            let sum_found_key: usize = found_key.iter().map(|&x| x as usize).sum();
-                            if sum_found_key == block_info.sum_key {
-                                let sum_found_md5: usize = md5::compute(&found_key)
-                                    .to_vec()
-                                    .iter()
-                                    .map(|&x| x as usize)
-                                    .sum();
-                                if sum_found_md5 == block_info.sum_md5 {
-                                    // success
-                                    result.push((found_key, found_data));
-                                    current += 1;
-                                }
-                            }
-                        }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(Error::new(ErrorKind::WouldBlock, "bucket is locked"));
+                }
+                Err(TryLockError::Error(e)) => return Err(e),
+            }
+        }
+    }
+
+    /// Body of [`Trait::set`]; the caller holds the writer lock.
+    fn set_locked(&mut self, key: Vec<u8>, data: Vec<u8>) -> Result<()> {
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        if is_value_unchanged(&mut self.reader, &list_block_data, &key, &data) {
+            // giá trị không đổi: không ghi gì vào file
+            return Ok(());
+        }
+        if self.worm {
+            append_data(
+                &mut self.reader,
+                &mut self.writer,
+                list_block_data,
+                vec![(key, data)],
+                start_list_point,
+                self.max_file_size,
+            )?;
+        } else {
+            set_one_data(
+                &mut self.reader,
+                &mut self.writer,
+                list_block_data,
+                (key, data),
+                start_list_point,
+                self.max_file_size,
+                self.placement,
+            )?;
+        }
+        self.evict_oldest()?;
+        self.refresh_cache()
+    }
+
+    /// Body of [`Trait::delete`]; the caller holds the writer lock.
+    fn delete_locked(&mut self, key: Vec<u8>) -> Result<()> {
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        if self.delete_mode == DeleteMode::Tombstone && !key.is_empty() {
+            tombstone_one_data(
+                &mut self.reader,
+                &mut self.writer,
+                list_block_data,
+                key,
+                start_list_point,
+            )?;
+        } else {
+            delete_one_data(
+                &mut self.reader,
+                &mut self.writer,
+                list_block_data,
+                key,
+                start_list_point,
+            )?;
+        }
+        self.refresh_cache()
+    }
+
+    fn load_list_config(&mut self) -> (usize, Vec<u8>) {
+        match &self.cache {
+            Some(cache) => (cache.start_list_point, cache.list_block_data.clone()),
+            None => get_list_config(&mut self.reader).unwrap_or_else(|_| (FIRST_SIZE, Vec::new())),
+        }
+    }
+
+    /// The reader wrapped in the scan buffer set by `open_with_buffers`.
+    fn scan_reader(&mut self) -> ScanReader<&mut File> {
+        ScanReader::new(self.buffers.read, &mut self.reader)
+    }
+
+    /// [`Trait::get`] through the lookup cache; the caller holds the read lock.
+    fn get_with_lookup_cache(&mut self, key: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
+        let Some(lookup_cache) = &mut self.lookup_cache else {
+            return Default::default();
+        };
+        // key vẫn được so sánh: block có thể đã bị handle khác ghi đè
+        if let Some(block_info) = lookup_cache.get(&key)
+            && let Ok((found_key, found_data)) = pull_data(&mut self.reader, &block_info)
+            && found_key == key
+        {
+            return (found_key, found_data);
+        }
+        let (_, list_block_data) = self.load_list_config();
+        let Some(block_info) = get_one_block(&mut self.reader, &list_block_data, &key) else {
+            return Default::default();
+        };
+        let Ok((found_key, found_data)) = pull_data(&mut self.reader, &block_info) else {
+            return Default::default();
+        };
+        if let Some(lookup_cache) = &mut self.lookup_cache {
+            lookup_cache.insert(key, block_info);
+        }
+        (found_key, found_data)
+    }
+
+    fn refresh_cache(&mut self) -> Result<()> {
+        if let Some(lookup_cache) = &mut self.lookup_cache {
+            lookup_cache.map_key_block.clear();
+        }
+        if self.cache.is_some() {
+            let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+            self.cache = Some(ListCache::new(start_list_point, list_block_data));
+        }
+        Ok(())
+    }
+}
+
+impl Trait for Bucket {
+    fn new(path: String) -> Result<Self> {
+        let reader = match File::open(&path) {
+            Ok(f) => f,
+            Err(_) => {
+                create_bucket_file(&path)?;
+                File::open(&path)?
+            }
+        };
+        let writer = OpenOptions::new().write(true).open(&path)?;
+
+        let mut bucket = Self::from_file(reader, writer);
+        bucket.path = path;
+        Ok(bucket)
+    }
+
+    fn set(&mut self, key: Vec<u8>, data: Vec<u8>) -> Result<()> {
+        let _lock = self.lock_write()?;
+        self.set_locked(key, data)
+    }
+
+    fn get(&mut self, key: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        if self.lookup_cache.is_some() {
+            return self.get_with_lookup_cache(key);
+        }
+        if let Some(cache) = &self.cache {
+            return get_one_data_cached(&mut self.reader, cache, key);
+        }
+        let (_, list_block_data) = self.load_list_config();
+        get_one_data(&mut self.reader, list_block_data, key)
+    }
+
+    fn delete(&mut self, key: Vec<u8>) -> Result<()> {
+        self.check_deletable()?;
+        let _lock = self.lock_write()?;
+        self.delete_locked(key)
+    }
+
+    fn set_many(&mut self, list_data: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        let _lock = self.lock_write()?;
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        if self.worm {
+            append_data(
+                &mut self.reader,
+                &mut self.writer,
+                list_block_data,
+                list_data,
+                start_list_point,
+                self.max_file_size,
+            )?;
+        } else if self.placement.alignment > 1 {
+            // set_many_data xếp các block liền nhau: ghi từng key qua bộ cấp phát căn lề
+            for (key, data) in list_data {
+                let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+                set_one_data(
+                    &mut self.reader,
+                    &mut self.writer,
+                    list_block_data,
+                    (key, data),
+                    start_list_point,
+                    self.max_file_size,
+                    self.placement,
+                )?;
+            }
+        } else {
+            set_many_data(
+                &mut self.reader,
+                &mut self.writer,
+                list_block_data,
+                list_data,
+                start_list_point,
+                self.max_file_size,
+            )?;
+        }
+        self.evict_oldest()?;
+        self.refresh_cache()
+    }
+
+    fn list(&mut self, limit: u8) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_list_data(&mut self.scan_reader(), list_block_data, limit)
+    }
+
+    fn list_next(&mut self, limit: u8, skip: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_list_next_data(&mut self.scan_reader(), list_block_data, limit, skip)
+    }
+
+    fn find_next(
+        &mut self,
+        key: Vec<u8>,
+        limit: u8,
+        only_after_key: bool,
+    ) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_find_next_data(
+            &mut self.scan_reader(),
+            list_block_data,
+            key,
+            limit,
+            only_after_key,
+        )
+    }
+
+    fn delete_to(&mut self, key: Vec<u8>, also_delete_the_found_block: bool) -> Result<()> {
+        self.check_deletable()?;
+        let _lock = self.lock_write()?;
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        delete_to_data(
+            &mut self.reader,
+            &mut self.writer,
+            start_list_point,
+            list_block_data,
+            also_delete_the_found_block,
+            key,
+        )?;
+        self.refresh_cache()
+    }
+
+    fn list_lock_delete(&mut self, limit: u8) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.check_deletable()?;
+        let _lock = self.lock_write()?;
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        let result = get_list_lock_delete_data(
+            &mut self.reader,
+            &mut self.writer,
+            start_list_point,
+            list_block_data,
+            limit,
+        );
+        self.refresh_cache()?;
+        result
+    }
+
+    fn find_suffix(&mut self, suffix: Vec<u8>, limit: u8) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_find_suffix_data(&mut self.reader, list_block_data, suffix, limit)
+    }
+
+    fn get_prefix_first(&mut self, prefix: Vec<u8>) -> Option<(Vec<u8>, Vec<u8>)> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_prefix_first_data(&mut self.reader, list_block_data, prefix)
+    }
+
+    fn compact(&mut self) -> Result<()> {
+        if self.path.is_empty() {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "compact needs a bucket opened from a path",
+            ));
+        }
+        let _lock = self.lock_write()?;
+        let (_, list_block_data) = get_list_config(&mut self.reader)?;
+        let tmp_path = format!("{}.compact", self.path);
+        // file tạm còn sót lại từ lần compact bị ngắt trước
+        let _ = fs::remove_file(&tmp_path);
+        write_compact_file(
+            &mut ScanReader::new(self.buffers.read, &mut self.reader),
+            list_block_data,
+            &tmp_path,
+            self.placement.alignment,
+            self.buffers.write,
+        )?;
+        fs::set_permissions(&tmp_path, self.reader.metadata()?.permissions())?;
+        fs::rename(&tmp_path, &self.path)?;
+        sync_parent_dir(&self.path)?;
+        self.reopen()
+    }
+
+    fn open_cached(path: String) -> Result<Self> {
+        let options = BucketOptions {
+            cached: true,
+            ..Default::default()
+        };
+        Self::open_with(path, options)
+    }
+
+    fn check_overlaps(&mut self) -> Vec<(Block, Block)> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_overlaps_data(list_block_data)
+    }
+
+    fn find_next_iter(&mut self, key: Vec<u8>, only_after_key: bool) -> BlockIter<'_> {
+        let (_, list_block_data) = self.load_list_config();
+        get_find_next_iter(&mut self.reader, list_block_data, key, only_after_key)
+    }
+
+    fn set_group(&mut self, prefix: Vec<u8>, list_data: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        let list_data = list_data
+            .into_iter()
+            .map(|(key, data)| (merge_vec(&[prefix.clone(), key]), data))
+            .collect();
+        self.set_many(list_data)
+    }
+
+    fn list_region(&mut self) -> (usize, usize) {
+        let Ok(_lock) = self.lock_read() else {
+            return (FIRST_SIZE, FIRST_SIZE + 1);
+        };
+        let (start_list_point, list_block_data) = self.load_list_config();
+        (
+            start_list_point,
+            start_list_point + list_block_data.len() + 1,
+        )
+    }
+
+    fn get_value(&mut self, key: Vec<u8>) -> Option<Vec<u8>> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_value_data(&mut self.reader, list_block_data, key)
+    }
+
+    fn get_many(&mut self, list_key: Vec<Vec<u8>>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_many_data(&mut self.reader, list_block_data, list_key)
+    }
+
+    fn compacted_size(&mut self) -> usize {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        let (start_list_point, new_list_block_data) = get_compact_list(
+            &get_list_block_info(&list_block_data),
+            self.placement.alignment,
+        );
+        start_list_point + new_list_block_data.len() + 1
+    }
+
+    fn new_with_access(path: String, access_mode: AccessMode) -> Result<Self> {
+        let options = BucketOptions {
+            access_mode,
+            ..Default::default()
+        };
+        Self::open_with(path, options)
+    }
+
+    fn set_with_retry(
+        &mut self,
+        key: Vec<u8>,
+        data: Vec<u8>,
+        retries: u32,
+        backoff: Duration,
+    ) -> Result<()> {
+        let _lock = self.lock_with_retry(retries, backoff)?;
+        self.set_locked(key, data)
+    }
+
+    fn delete_with_retry(&mut self, key: Vec<u8>, retries: u32, backoff: Duration) -> Result<()> {
+        self.check_deletable()?;
+        let _lock = self.lock_with_retry(retries, backoff)?;
+        self.delete_locked(key)
+    }
+
+    fn compact_step(&mut self, max_blocks: usize) -> Result<bool> {
+        let _lock = self.lock_write()?;
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        let has_more = compact_step_data(
+            &mut self.reader,
+            &mut self.writer,
+            start_list_point,
+            list_block_data,
+            max_blocks,
+            self.placement.alignment,
+        )?;
+        self.refresh_cache()?;
+        Ok(has_more)
+    }
+
+    fn read_raw(&mut self, offset: usize, len: usize) -> Result<Vec<u8>> {
+        let _lock = self.lock_read()?;
+        self.reader.seek(Start(offset as u64))?;
+        // không cấp phát trước `len` byte: `len` có thể lớn hơn cả file
+        let mut buffer = Vec::new();
+        (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut buffer)?;
+        if buffer.len() < len {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "read past the end of the file",
+            ));
+        }
+        Ok(buffer)
+    }
+
+    fn from_file(reader: File, writer: File) -> Self {
+        Self {
+            reader,
+            writer,
+            path: String::new(),
+            cache: None,
+            access_mode: AccessMode::WriteLock,
+            worm: false,
+            max_entries: None,
+            max_file_size: None,
+            auto_reopen: false,
+            lookup_cache: None,
+            placement: Placement::default(),
+            buffers: Buffers::default(),
+            delete_mode: DeleteMode::default(),
+        }
+    }
+
+    fn from_file_single(file: File) -> Result<Self> {
+        let writer = file.try_clone()?;
+        Ok(Self::from_file(file, writer))
+    }
+
+    fn fragment_count(&mut self) -> usize {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (start_list_point, list_block_data) = self.load_list_config();
+        get_list_space(start_list_point, get_list_block_info(&list_block_data)).len()
+    }
+
+    fn bulk_load(&mut self, list_data: impl Iterator<Item = (Vec<u8>, Vec<u8>)>) -> Result<()> {
+        let _lock = self.lock_write()?;
+        let (_, list_block_data) = get_list_config(&mut self.reader)?;
+        if !list_block_data.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "bucket is not empty"));
+        }
+        bulk_load_data(
+            &mut self.writer,
+            list_data,
+            self.max_file_size,
+            (self.placement.alignment, self.buffers.write),
+        )?;
+        self.evict_oldest()?;
+        self.refresh_cache()
+    }
+
+    fn validate_key(&mut self, key: Vec<u8>) -> Result<bool> {
+        let _lock = self.lock_read()?;
+        let (_, list_block_data) = get_list_config(&mut self.reader)?;
+        validate_key_data(&mut self.reader, list_block_data, key)
+    }
+
+    fn clone_to(&mut self, dest: String) -> Result<Self> {
+        let _lock = self.lock_write()?;
+        let (_, list_block_data) = get_list_config(&mut self.reader)?;
+        if let Err(e) = write_compact_file(
+            &mut ScanReader::new(self.buffers.read, &mut self.reader),
+            list_block_data,
+            &dest,
+            self.placement.alignment,
+            self.buffers.write,
+        ) {
+            if e.kind() != ErrorKind::AlreadyExists {
+                let _ = fs::remove_file(&dest);
+            }
+            return Err(e);
+        }
+        Self::new(dest)
+    }
+
+    fn blocks_in_range(&mut self, lo: usize, hi: usize) -> Vec<Block> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_list_block_info(&list_block_data)
+            .into_iter()
+            .filter(|block_info| (lo..hi).contains(&block_info.start))
+            .collect()
+    }
+
+    fn lookup_cost(&mut self, key: Vec<u8>) -> usize {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_lookup_cost_data(&mut self.reader, list_block_data, key)
+    }
+
+    fn touch(&mut self, key: Vec<u8>) -> Result<bool> {
+        let _lock = self.lock_write()?;
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        let (found_key, _) = get_one_data(&mut self.reader, list_block_data.clone(), key.clone());
+        let is_new = found_key.is_empty();
+        if is_new && self.worm {
+            append_data(
+                &mut self.reader,
+                &mut self.writer,
+                list_block_data,
+                vec![(key, Vec::new())],
+                start_list_point,
+                self.max_file_size,
+            )?;
+            self.evict_oldest()?;
+            self.refresh_cache()?;
+        } else if is_new {
+            set_one_data(
+                &mut self.reader,
+                &mut self.writer,
+                list_block_data,
+                (key, Vec::new()),
+                start_list_point,
+                self.max_file_size,
+                self.placement,
+            )?;
+            self.evict_oldest()?;
+            self.refresh_cache()?;
+        }
+        Ok(is_new)
+    }
+
+    fn group_by_prefix(&mut self, sep: u8) -> Vec<PrefixGroup> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_group_by_prefix_data(&mut self.reader, list_block_data, sep)
+    }
+
+    fn open_worm(path: String) -> Result<Self> {
+        let options = BucketOptions {
+            worm: true,
+            ..Default::default()
+        };
+        Self::open_with(path, options)
+    }
+
+    fn snapshot_iter(&mut self) -> BlockIter<'_> {
+        let (_, list_block_data) = self.load_list_config();
+        BlockIter {
+            read: &mut self.reader,
+            list_block_info: get_list_block_info(&list_block_data),
+            index: 0,
+        }
+    }
+
+    fn export_key_hashes(&mut self) -> Vec<u64> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        let mut result: Vec<u64> = Vec::new();
+        for block_info in get_list_block_info(&list_block_data) {
+            let found_key = pull_key(&mut self.reader, &block_info).unwrap_or_else(|_| Vec::new());
+            if is_valid_key(&found_key, &block_info) {
+                result.push(key_hash(&found_key));
+            }
+        }
+        result
+    }
+
+    fn format_version(&mut self) -> Result<u32> {
+        let _lock = self.lock_read()?;
+        get_format_version(&mut self.reader)
+    }
+
+    fn needs_migration(&mut self) -> bool {
+        self.format_version()
+            .is_ok_and(|version| version < FORMAT_VERSION)
+    }
+
+    fn find_value_prefix(&mut self, prefix: Vec<u8>, limit: u8) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_find_value_prefix_data(&mut self.reader, list_block_data, prefix, limit)
+    }
+
+    fn shard_into(
+        &mut self,
+        n: usize,
+        dest_prefix: &str,
+        hash: impl Fn(&[u8]) -> u64,
+    ) -> Result<Vec<Self>> {
+        if n == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "n must be at least 1"));
+        }
+        let _lock = self.lock_write()?;
+        self.write_shards(n, dest_prefix, hash)
+    }
+
+    fn reopen(&mut self) -> Result<()> {
+        if self.path.is_empty() {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "reopen needs a bucket opened from a path",
+            ));
+        }
+        // mở cả hai handle trước khi thay, để lỗi không để lại bucket nửa cũ nửa mới
+        let reader = File::open(&self.path)?;
+        let writer = OpenOptions::new().write(true).open(&self.path)?;
+        self.reader = reader;
+        self.writer = writer;
+        self.refresh_cache()
+    }
+
+    fn set_meta(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        let _lock = self.lock_write()?;
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        set_meta_data(
+            &mut self.reader,
+            &mut self.writer,
+            start_list_point,
+            list_block_data,
+            key.as_bytes(),
+            value,
+        )?;
+        self.refresh_cache()
+    }
+
+    fn get_meta(&mut self, key: &str) -> Option<Vec<u8>> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let meta_data = pull_meta_region(&mut self.reader).ok()?;
+        get_list_meta(&meta_data)
+            .into_iter()
+            .find(|(n, _)| n == key.as_bytes())
+            .map(|(_, v)| v)
+    }
+
+    fn bounds(&mut self) -> Option<(usize, usize)> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        let list_block_info = get_list_block_info(&list_block_data);
+        let min_start = list_block_info.iter().map(|b| b.start).min()?;
+        let max_start = list_block_info.iter().map(|b| b.start).max()?;
+        Some((min_start, max_start))
+    }
+
+    fn open_strict(path: String) -> Result<Self> {
+        let options = BucketOptions {
+            strict: true,
+            ..Default::default()
+        };
+        Self::open_with(path, options)
+    }
+
+    fn get_many_prefix(
+        &mut self,
+        list_prefix: Vec<Vec<u8>>,
+        limit: u8,
+    ) -> Vec<Vec<(Vec<u8>, Vec<u8>)>> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_many_prefix_data(&mut self.reader, list_block_data, list_prefix, limit)
+    }
+
+    fn content_hash(&mut self) -> u64 {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_content_hash_data(&mut self.reader, list_block_data)
+    }
+
+    fn push(&mut self, value: Vec<u8>) -> Result<u64> {
+        let _lock = self.lock_write()?;
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        let seq = get_list_meta(&pull_meta_region(&mut self.reader)?)
+            .into_iter()
+            .find(|(n, _)| n == b"push")
+            .and_then(|(_, v)| decode_u64_key(&v))
+            .unwrap_or(0);
+        let Some(next_seq) = seq.checked_add(1) else {
+            return Err(Error::new(
+                ErrorKind::StorageFull,
+                "push sequence numbers are exhausted",
+            ));
+        };
+        set_meta_data(
+            &mut self.reader,
+            &mut self.writer,
+            start_list_point,
+            list_block_data.clone(),
+            b"push",
+            &encode_u64_key(next_seq),
+        )?;
+        if self.worm {
+            append_data(
+                &mut self.reader,
+                &mut self.writer,
+                list_block_data,
+                vec![(encode_u64_key(seq), value)],
+                start_list_point,
+                self.max_file_size,
+            )?;
+        } else {
+            set_one_data(
+                &mut self.reader,
+                &mut self.writer,
+                list_block_data,
+                (encode_u64_key(seq), value),
+                start_list_point,
+                self.max_file_size,
+                self.placement,
+            )?;
+        }
+        self.evict_oldest()?;
+        self.refresh_cache()?;
+        Ok(seq)
+    }
+
+    fn open_bounded(path: String, max_entries: usize) -> Result<Self> {
+        let options = BucketOptions {
+            max_entries: Some(max_entries),
+            ..Default::default()
+        };
+        Self::open_with(path, options)
+    }
+
+    fn list_sizes(&mut self, limit: u8) -> Vec<(Vec<u8>, usize)> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_list_sizes_data(&mut self.reader, list_block_data, limit)
+    }
+
+    #[cfg(unix)]
+    fn open_with_flags(path: String, custom_flags: i32) -> Result<Self> {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        if fs::metadata(&path).is_err() {
+            create_bucket_file(&path)?;
+        }
+        let reader = OpenOptions::new()
+            .read(true)
+            .custom_flags(custom_flags)
+            .open(&path)?;
+        let writer = OpenOptions::new()
+            .write(true)
+            .custom_flags(custom_flags)
+            .open(&path)?;
+
+        let mut bucket = Self::from_file(reader, writer);
+        bucket.path = path;
+        Ok(bucket)
+    }
+
+    fn repair(&mut self) -> Result<usize> {
+        let _lock = self.lock_write()?;
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        let dropped = repair_data(
+            &mut self.reader,
+            &mut self.writer,
+            start_list_point,
+            list_block_data,
+        )?;
+        self.refresh_cache()?;
+        Ok(dropped)
+    }
+
+    fn move_to(&mut self, other: &mut Self, key: Vec<u8>) -> Result<bool> {
+        let (found_key, found_data) = self.get(key);
+        if found_key.is_empty() {
+            return Ok(false);
+        }
+        other.set(found_key.clone(), found_data)?;
+        self.delete(found_key)?;
+        Ok(true)
+    }
+
+    fn free_list_summary(&mut self) -> FreeListSummary {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (start_list_point, list_block_data) = self.load_list_config();
+        let list_space = get_list_space(start_list_point, get_list_block_info(&list_block_data));
+        let list_size: Vec<usize> = list_space.iter().map(|s| s.size_data).collect();
+        FreeListSummary {
+            holes: list_size.len(),
+            largest: list_size.iter().copied().max().unwrap_or(0),
+            smallest: list_size.iter().copied().min().unwrap_or(0),
+            total_free: list_size.iter().sum(),
+        }
+    }
+
+    fn find_next_many(
+        &mut self,
+        list_key: Vec<Vec<u8>>,
+        limit: u8,
+        only_after_key: bool,
+    ) -> Vec<Vec<(Vec<u8>, Vec<u8>)>> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_find_next_many_data(
+            &mut self.reader,
+            list_block_data,
+            list_key,
+            limit,
+            only_after_key,
+        )
+    }
+
+    fn open_single(path: String) -> Result<Self> {
+        if fs::metadata(&path).is_err() {
+            create_bucket_file(&path)?;
+        }
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        let mut bucket = Self::from_file_single(file)?;
+        bucket.path = path;
+        Ok(bucket)
+    }
+
+    fn find_duplicates(&mut self) -> Vec<(Vec<u8>, usize)> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_duplicates_data(&mut self.reader, list_block_data)
+    }
+
+    fn export_raw<W: Write>(&mut self, mut w: W) -> Result<()> {
+        let _lock = self.lock_read()?;
+        let (_, list_block_data) = get_list_config(&mut self.reader)?;
+        export_raw_data(&mut self.scan_reader(), list_block_data, &mut w)
+    }
+
+    fn import_raw<R: Read>(&mut self, mut r: R) -> Result<()> {
+        read_raw_magic(&mut r)?;
+        while let Some((key, data)) = read_raw_entry(&mut r)? {
+            self.set(key, data)?;
+        }
+        Ok(())
+    }
+
+    fn health(&mut self) -> HealthReport {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        get_health_data(&mut self.reader)
+    }
+
+    fn prefetch_range(&mut self, start: Vec<u8>, end: Vec<u8>) -> Result<()> {
+        let _lock = self.lock_read()?;
+        let (_, list_block_data) = get_list_config(&mut self.reader)?;
+        let read_plan = get_prefetch_plan(&mut self.reader, &list_block_data, &start, &end);
+        prefetch_data(&mut self.reader, &read_plan)
+    }
+
+    fn rotate(&mut self, archive_path: String) -> Result<()> {
+        if self.path.is_empty() {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "rotate needs a bucket opened from a path",
+            ));
+        }
+        let _lock = self.lock_write()?;
+        fs::rename(&self.path, &archive_path)?;
+        create_bucket_file(&self.path)?;
+        self.reopen()
+    }
+
+    fn prefix_reclaimable(&mut self, prefix: Vec<u8>) -> usize {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_prefix_reclaimable_data(&mut self.reader, list_block_data, prefix)
+    }
+
+    fn set_at(&mut self, key: Vec<u8>, offset: usize, patch: Vec<u8>) -> Result<bool> {
+        if self.worm {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "bucket is write-once (WORM): set_at is disabled",
+            ));
+        }
+        let _lock = self.lock_write()?;
+        let (_, list_block_data) = get_list_config(&mut self.reader)?;
+        set_at_data(
+            &mut self.reader,
+            &mut self.writer,
+            list_block_data,
+            key,
+            offset,
+            patch,
+        )
+    }
+
+    fn iter_physical(&mut self) -> BlockIter<'_> {
+        let (_, list_block_data) = self.load_list_config();
+        let mut list_block_info = get_list_block_info(&list_block_data);
+        list_block_info.sort_by_key(|b| b.start);
+        BlockIter {
+            read: &mut self.reader,
+            list_block_info,
+            index: 0,
+        }
+    }
+
+    fn approx_len(&mut self) -> usize {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        get_approx_len_data(&mut self.reader).unwrap_or(0)
+    }
+
+    fn compact_key(&mut self, key: Vec<u8>) -> Result<bool> {
+        if self.worm {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "bucket is write-once (WORM): compact_key is disabled",
+            ));
+        }
+        let _lock = self.lock_write()?;
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        let is_moved = compact_key_data(
+            &mut self.reader,
+            &mut self.writer,
+            list_block_data,
+            key,
+            start_list_point,
+            self.placement.alignment,
+        )?;
+        self.refresh_cache()?;
+        Ok(is_moved)
+    }
+
+    fn freeze(&mut self) -> Result<BucketReader<File>> {
+        if self.path.is_empty() {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "freeze needs a bucket opened from a path",
+            ));
+        }
+        let _lock = self.lock_write()?;
+        let (_, list_block_data) = get_list_config(&mut self.reader)?;
+        let frozen_path = format!("{}.frozen", self.path);
+        let tmp_path = format!("{}.tmp", frozen_path);
+        // một tmp còn sót lại từ lần freeze lỗi trước sẽ chặn create_new
+        let _ = fs::remove_file(&tmp_path);
+        write_compact_file(
+            &mut ScanReader::new(self.buffers.read, &mut self.reader),
+            list_block_data,
+            &tmp_path,
+            self.placement.alignment,
+            self.buffers.write,
+        )?;
+        let mut permissions = fs::metadata(&tmp_path)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&tmp_path, permissions)?;
+        fs::rename(&tmp_path, &frozen_path)?;
+        sync_parent_dir(&frozen_path)?;
+        Ok(BucketReader::new(File::open(&frozen_path)?))
+    }
+
+    fn filter_iter<F: FnMut(&[u8], &[u8]) -> bool>(
+        &mut self,
+        mut pred: F,
+    ) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> {
+        self.snapshot_iter()
+            .filter(move |(key, data)| pred(key, data))
+    }
+
+    fn open_capped(path: String, max_file_size: u64) -> Result<Self> {
+        let options = BucketOptions {
+            max_file_size: Some(max_file_size),
+            ..Default::default()
+        };
+        Self::open_with(path, options)
+    }
+
+    fn debug_blocks(&mut self) -> String {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_debug_blocks_data(&mut self.reader, list_block_data)
+    }
+
+    fn set_at_offset(&mut self, key: Vec<u8>, value: Vec<u8>, start: usize) -> Result<()> {
+        if self.worm {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "bucket is write-once (WORM): set_at_offset is disabled",
+            ));
+        }
+        let _lock = self.lock_write()?;
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        set_at_offset_data(
+            &mut self.reader,
+            &mut self.writer,
+            list_block_data,
+            (key, value),
+            start,
+            start_list_point,
+            self.max_file_size,
+        )?;
+        self.evict_oldest()?;
+        self.refresh_cache()
+    }
+
+    fn count_by_key_len(&mut self, len: usize) -> usize {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_list_block_info(&list_block_data)
+            .iter()
+            .filter(|b| b.size_key == len)
+            .count()
+    }
+
+    fn reserve_value(&mut self, key: Vec<u8>, size: usize) -> Result<ValueHandle> {
+        if self.worm {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "bucket is write-once (WORM): reserve_value is disabled",
+            ));
+        }
+        let _lock = self.lock_write()?;
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        let handle = reserve_value_data(
+            &mut self.writer,
+            list_block_data,
+            key,
+            size,
+            start_list_point,
+            self.max_file_size,
+            self.placement,
+        )?;
+        self.refresh_cache()?;
+        Ok(handle)
+    }
+
+    fn write_value(&mut self, handle: &ValueHandle, offset: usize, bytes: &[u8]) -> Result<()> {
+        if offset.saturating_add(bytes.len()) > handle.size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} bytes at offset {} exceed the reserved size {}",
+                    bytes.len(),
+                    offset,
+                    handle.size
+                ),
+            ));
+        }
+        let _lock = self.lock_write()?;
+        let (_, list_block_data) = get_list_config(&mut self.reader)?;
+        check_reservation(&list_block_data, handle)?;
+        self.writer
+            .seek(Start((handle.start + handle.key.len() + offset) as u64))?;
+        self.writer.write_all(bytes)
+    }
+
+    fn finalize_value(&mut self, handle: ValueHandle) -> Result<()> {
+        let _lock = self.lock_write()?;
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        finalize_value_data(
+            &mut self.reader,
+            &mut self.writer,
+            list_block_data,
+            &handle,
+            start_list_point,
+        )?;
+        self.evict_oldest()?;
+        self.refresh_cache()
+    }
+
+    fn for_each_batch<F: FnMut(&[(Vec<u8>, Vec<u8>)])>(&mut self, batch_size: usize, mut f: F) {
+        let batch_size = batch_size.max(1);
+        let mut batch: Vec<(Vec<u8>, Vec<u8>)> = Vec::with_capacity(batch_size);
+        for item in self.snapshot_iter() {
+            batch.push(item);
+            if batch.len() == batch_size {
+                f(&batch);
+                batch.clear();
+            }
+        }
+        if !batch.is_empty() {
+            f(&batch);
+        }
+    }
+
+    fn rank(&mut self, key: Vec<u8>) -> Option<usize> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_rank_data(&mut self.reader, list_block_data, key)
+    }
+
+    fn get_consistent(&mut self, key: Vec<u8>) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let _lock = self.lock_read()?;
+        get_consistent_data(&mut self.reader, key, CONSISTENT_READ_BACKOFF)
+    }
+
+    fn diff(&mut self, other: &mut Self) -> Vec<DiffEntry> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let Ok(_other_lock) = other.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        let (_, other_list_block_data) = other.load_list_config();
+        get_diff_data(
+            &mut self.reader,
+            list_block_data,
+            &mut other.reader,
+            other_list_block_data,
+        )
+    }
+
+    fn trim_to_last(&mut self, n: usize) -> Result<usize> {
+        self.check_deletable()?;
+        let _lock = self.lock_write()?;
+        let (start_list_point, list_block_data) = get_list_config(&mut self.reader)?;
+        let dropped = trim_list_data(
+            &mut self.reader,
+            &mut self.writer,
+            start_list_point,
+            list_block_data,
+            n,
+        )?;
+        self.refresh_cache()?;
+        Ok(dropped)
+    }
+
+    fn iter_decoded<V, F: Fn(&[u8]) -> V>(
+        &mut self,
+        decode: F,
+    ) -> impl Iterator<Item = (Vec<u8>, V)> {
+        self.snapshot_iter()
+            .map(move |(key, data)| (key, decode(&data)))
+    }
+
+    fn open_auto_reopen(path: String) -> Result<Self> {
+        let options = BucketOptions {
+            auto_reopen: true,
+            ..Default::default()
+        };
+        Self::open_with(path, options)
+    }
+
+    fn open_lookup_cache(path: String, capacity: usize) -> Result<Self> {
+        let options = BucketOptions {
+            lookup_cache: Some(capacity),
+            ..Default::default()
+        };
+        Self::open_with(path, options)
+    }
+
+    fn open_size_classes(path: String) -> Result<Self> {
+        let options = BucketOptions {
+            size_classes: true,
+            ..Default::default()
+        };
+        Self::open_with(path, options)
+    }
+
+    fn open_aligned(path: String, alignment: usize) -> Result<Self> {
+        let options = BucketOptions {
+            alignment: Some(alignment),
+            ..Default::default()
+        };
+        Self::open_with(path, options)
+    }
+
+    fn open_with_buffers(path: String, read_buf: usize, write_buf: usize) -> Result<Self> {
+        let options = BucketOptions {
+            read_buffer: Some(read_buf),
+            write_buffer: Some(write_buf),
+            ..Default::default()
+        };
+        Self::open_with(path, options)
+    }
+
+    fn open_with(path: String, options: BucketOptions) -> Result<Self> {
+        if options.lookup_cache == Some(0) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "capacity must be at least 1",
+            ));
+        }
+        if options.alignment == Some(0) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "alignment must be at least 1",
+            ));
+        }
+        if options.read_buffer == Some(0) || options.write_buffer == Some(0) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "buffer sizes must be at least 1",
+            ));
+        }
+        let mut bucket = Self::new(path)?;
+        bucket.access_mode = options.access_mode;
+        bucket.delete_mode = options.delete_mode;
+        bucket.worm = options.worm;
+        bucket.max_entries = options.max_entries;
+        bucket.max_file_size = options.max_file_size;
+        bucket.auto_reopen = options.auto_reopen;
+        bucket.lookup_cache = options.lookup_cache.map(LookupCache::new);
+        bucket.placement = Placement {
+            size_classes: options.size_classes,
+            alignment: options.alignment.unwrap_or_default(),
+        };
+        let default_buffers = Buffers::default();
+        bucket.buffers = Buffers {
+            read: options.read_buffer.unwrap_or(default_buffers.read),
+            write: options.write_buffer.unwrap_or(default_buffers.write),
+        };
+        if options.strict {
+            let (_, list_block_data) = get_list_config(&mut bucket.reader)?;
+            check_duplicate_key_data(&mut bucket.reader, list_block_data)?;
+        }
+        if options.cached {
+            let (start_list_point, list_block_data) = get_list_config(&mut bucket.reader)?;
+            bucket.cache = Some(ListCache::new(start_list_point, list_block_data));
+        }
+        Ok(bucket)
+    }
+
+    fn list_tombstones(&mut self, limit: u8) -> Vec<Vec<u8>> {
+        let Ok(_lock) = self.lock_read() else {
+            return Default::default();
+        };
+        let (_, list_block_data) = self.load_list_config();
+        get_tombstones_data(&mut self.reader, list_block_data, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        AccessMode, Block, Bucket, BucketOptions, BucketReader, DeleteMode, DiffEntry, END,
+        FreeListSummary, HealthReport, Trait, decode_u64_key, encode_u64_key, get_consistent_data,
+        get_list_block_info, get_list_config, get_many_read_plan, get_prefetch_plan,
+        group_digits_to_vec, is_value_unchanged, key_hash, migrate_v0_to_v1, prefetch_data,
+        push_block_to_data, sync_parent_dir, update_list_block, write_compact_file,
+    };
+    use std::fs::{self, OpenOptions};
+    use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom, SeekFrom::Start, Write};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_all() {
+        set_data();
+        get_data();
+        delete_data();
+        set_many_data();
+        list_data();
+        list_next_data();
+        find_next_data();
+        delete_to_data();
+        get_list_and_delete_list_data();
+        delete_bucket();
+    }
+
+    #[allow(clippy::bool_assert_comparison)]
+    fn set_data() {
+        let file_path = String::from("data.db");
+        let mut bucket = Bucket::new(file_path).unwrap();
+
+        let test_key: Vec<u8> = String::from("test-key-001-99999999999999").into_bytes();
+        let test_value: Vec<u8> = String::from("test data value: 0123456789 abcdefgh").into_bytes();
+        let error = bucket.set(test_key, test_value).is_err();
+
+        assert_eq!(error, false);
+    }
+
+    fn get_data() {
+        let file_path = String::from("data.db");
+        let mut bucket = Bucket::new(file_path).unwrap();
+
+        let test_key: Vec<u8> = String::from("test-key-001-99999999999999").into_bytes();
+        let test_value: Vec<u8> = String::from("test data value: 0123456789 abcdefgh").into_bytes();
+        let (key_block, value_block) = bucket.get(test_key.clone());
+
+        assert_eq!(test_key, key_block);
+        assert_eq!(test_value, value_block);
+    }
+
+    #[allow(clippy::bool_assert_comparison)]
+    fn delete_data() {
+        let file_path = String::from("data.db");
+        let mut bucket = Bucket::new(file_path).unwrap();
+
+        let test_key: Vec<u8> = String::from("test-key-001-99999999999999").into_bytes();
+        let error = bucket.delete(test_key).is_err();
+
+        assert_eq!(error, false);
+    }
+
+    #[allow(clippy::bool_assert_comparison)]
+    fn set_many_data() {
+        let file_path = String::from("data.db");
+        let mut bucket = Bucket::new(file_path).unwrap();
+
+        // let test_key: Vec<u8> = String::from("test-key-001-99999999999999").into_bytes();
+        let test_value: Vec<u8> = String::from("test data value: 0123456789 abcdefgh").into_bytes();
+
+        let mut list_data: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+        for i in 0..10 {
+            let test_key = format!("test-key-00{}-99999999999999", i).into_bytes();
+            list_data.push((test_key, test_value.clone()));
+        }
+
+        let error = bucket.set_many(list_data).is_err();
+
+        assert_eq!(error, false);
+    }
+
+    #[allow(clippy::bool_assert_comparison, clippy::len_zero)]
+    fn list_data() {
+        let file_path = String::from("data.db");
+        let mut bucket = Bucket::new(file_path).unwrap();
+
+        let limit = 10u8;
+        let list_block = bucket.list(limit);
+
+        assert_eq!(list_block.len() > 0, true);
+    }
+
+    #[allow(clippy::bool_assert_comparison, clippy::len_zero)]
+    fn list_next_data() {
+        let file_path = String::from("data.db");
+        let mut bucket = Bucket::new(file_path).unwrap();
+
+        let limit = 10u8;
+        let skip = 0usize;
+        let list_block = bucket.list_next(limit, skip);
+
+        assert_eq!(list_block.len() > 0, true);
+    }
+
+    #[allow(clippy::bool_assert_comparison, clippy::len_zero)]
+    fn find_next_data() {
+        let file_path = String::from("data.db");
+        let mut bucket = Bucket::new(file_path).unwrap();
+
+        let test_key: Vec<u8> = String::from("test-key-001-99999999999999").into_bytes();
+        let limit = 10u8;
+        let only_after_key = false;
+        // let only_after_key = true;
+        let list_block = bucket.find_next(test_key, limit, only_after_key);
+
+        assert_eq!(list_block.len() > 0, true);
+    }
+
+    #[allow(clippy::bool_assert_comparison)]
+    fn delete_to_data() {
+        let file_path = String::from("data.db");
+        let mut bucket = Bucket::new(file_path).unwrap();
+
+        let test_key: Vec<u8> = String::from("test-key-001-99999999999999").into_bytes();
+        let also_delete_the_found_block = true;
+        // let also_delete_the_found_block = false;
+        let error = bucket
+            .delete_to(test_key, also_delete_the_found_block)
+            .is_err();
+
+        assert_eq!(error, false);
+    }
+
+    #[allow(clippy::bool_assert_comparison, clippy::len_zero)]
+    fn get_list_and_delete_list_data() {
+        let file_path = String::from("data.db");
+        let mut bucket = Bucket::new(file_path).unwrap();
+
+        let limit = 10u8;
+        let list_block = bucket.list_lock_delete(limit).unwrap();
+
+        assert_eq!(list_block.len() > 0, true);
+    }
+
+    fn delete_bucket() {
+        let file_path = String::from("data.db");
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_corrupt_block_list() {
+        let file_path = String::from("data_corrupt_block_list.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        bucket
+            .set(b"key-002".to_vec(), b"value-002".to_vec())
+            .unwrap();
+
+        let (start_list_point, _) = get_list_config(&mut bucket.reader).unwrap();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&file_path)
+            .unwrap();
+        let mut byte = [0u8; 1];
+        file.seek(Start(start_list_point as u64)).unwrap();
+        file.read_exact(&mut byte).unwrap();
+        byte[0] = if byte[0] == 1 { 2 } else { 1 };
+        file.seek(Start(start_list_point as u64)).unwrap();
+        file.write_all(&byte).unwrap();
+
+        let error = get_list_config(&mut bucket.reader).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(
+            bucket
+                .set(b"key-003".to_vec(), b"value-003".to_vec())
+                .is_err()
+        );
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_find_suffix() {
+        let file_path = String::from("data_find_suffix.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set_many(vec![
+                (b"a.json".to_vec(), b"1".to_vec()),
+                (b"b.txt".to_vec(), b"2".to_vec()),
+                (b"c.json".to_vec(), b"3".to_vec()),
+                (b"json".to_vec(), b"4".to_vec()),
+            ])
+            .unwrap();
+
+        let list_block = bucket.find_suffix(b".json".to_vec(), 10);
+        assert_eq!(
+            list_block,
+            vec![
+                (b"a.json".to_vec(), b"1".to_vec()),
+                (b"c.json".to_vec(), b"3".to_vec()),
+            ]
+        );
+        assert_eq!(bucket.find_suffix(b".json".to_vec(), 1).len(), 1);
+        assert!(bucket.find_suffix(b".csv".to_vec(), 10).is_empty());
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_get_prefix_first() {
+        let file_path = String::from("data_get_prefix_first.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket.set(b"other/1".to_vec(), b"0".to_vec()).unwrap();
+        bucket.set(b"user/2".to_vec(), b"2".to_vec()).unwrap();
+        bucket.set(b"user/1".to_vec(), b"1".to_vec()).unwrap();
+
+        let first = bucket.get_prefix_first(b"user/".to_vec());
+        assert_eq!(first, Some((b"user/2".to_vec(), b"2".to_vec())));
+        assert_eq!(bucket.get_prefix_first(b"admin/".to_vec()), None);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_compact() {
+        let file_path = String::from("data_compact.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        let mut list_data: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+        for i in 0..20 {
+            list_data.push((format!("key-{:02}", i).into_bytes(), vec![b'v'; 64]));
+        }
+        bucket.set_many(list_data).unwrap();
+        for i in (0..20).step_by(2) {
+            bucket.delete(format!("key-{:02}", i).into_bytes()).unwrap();
+        }
+        let size_before = fs::metadata(&file_path).unwrap().len();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&file_path, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        bucket.compact().unwrap();
+
+        let size_after = fs::metadata(&file_path).unwrap().len();
+        assert!(size_after < size_before);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&file_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+        }
+        assert_eq!(bucket.list(255).len(), 10);
+        let (key_block, value_block) = bucket.get(b"key-01".to_vec());
+        assert_eq!(key_block, b"key-01".to_vec());
+        assert_eq!(value_block, vec![b'v'; 64]);
+        bucket.set(b"key-20".to_vec(), b"new".to_vec()).unwrap();
+        assert_eq!(bucket.get(b"key-20".to_vec()).1, b"new".to_vec());
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_compact_interrupted_before_rename() {
+        let file_path = String::from("data_compact_interrupted.db");
+        let tmp_path = format!("{}.compact", file_path);
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        bucket
+            .set(b"key-002".to_vec(), b"value-002".to_vec())
+            .unwrap();
+        bucket.delete(b"key-001".to_vec()).unwrap();
+        let original = fs::read(&file_path).unwrap();
+
+        // crash giả lập: file tạm đã ghi xong nhưng chưa rename
+        let (_, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        write_compact_file(&mut bucket.reader, list_block_data, &tmp_path, 1, 64).unwrap();
+
+        assert_eq!(fs::read(&file_path).unwrap(), original);
+        let mut reopened = Bucket::new(file_path.clone()).unwrap();
+        assert_eq!(reopened.get(b"key-002".to_vec()).1, b"value-002".to_vec());
+
+        fs::remove_file(tmp_path).unwrap();
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_open_cached() {
+        let file_path = String::from("data_open_cached.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 0..1000 {
+            let key = format!("key-{:04}", i).into_bytes();
+            bucket.set(key, b"value".to_vec()).unwrap();
+        }
+
+        let mut cached = Bucket::open_cached(file_path.clone()).unwrap();
+        assert_eq!(cached.cache.as_ref().unwrap().list_block_info.len(), 1000);
+
+        cached.set(b"key-new".to_vec(), b"new".to_vec()).unwrap();
+        assert_eq!(cached.get(b"key-new".to_vec()).1, b"new".to_vec());
+        cached.delete(b"key-0000".to_vec()).unwrap();
+        assert!(cached.get(b"key-0000".to_vec()).0.is_empty());
+        assert_eq!(cached.list(3)[0].0, b"key-0001".to_vec());
+        assert_eq!(cached.cache.as_ref().unwrap().list_block_info.len(), 1000);
+
+        // hỏng list trên đĩa: bucket thường không đọc được nữa, bucket cached vẫn đọc
+        // từ list trong bộ nhớ
+        let (start_list_point, _) = get_list_config(&mut bucket.reader).unwrap();
+        let mut file = OpenOptions::new().write(true).open(&file_path).unwrap();
+        file.seek(Start(start_list_point as u64)).unwrap();
+        file.write_all(&[END]).unwrap();
+        assert!(bucket.get(b"key-0005".to_vec()).0.is_empty());
+        for i in 1..200 {
+            let key = format!("key-{:04}", i * 5).into_bytes();
+            assert_eq!(cached.get(key).1, b"value".to_vec());
+        }
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_check_overlaps() {
+        let file_path = String::from("data_check_overlaps.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        assert!(bucket.check_overlaps().is_empty());
+
+        let block = |start: usize| Block {
+            start,
+            size_key: 7,
+            sum_key: 600,
+            sum_md5: 2000,
+            size_data: 9,
+        };
+        let mut list_block_data = Vec::new();
+        for start in [128, 140, 200] {
+            list_block_data = push_block_to_data(list_block_data, &block(start));
+        }
+        update_list_block(&mut bucket.writer, 300, list_block_data).unwrap();
+
+        assert_eq!(bucket.check_overlaps(), vec![(block(128), block(140))]);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_find_next_iter() {
+        let file_path = String::from("data_find_next_iter.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket.set(b"anchor".to_vec(), b"a".to_vec()).unwrap();
+        for i in 0..1000 {
+            let key = format!("key-{:04}", i).into_bytes();
+            bucket.set(key, b"value".to_vec()).unwrap();
+        }
+
+        let mut iter = bucket.find_next_iter(b"anchor".to_vec(), true);
+        let list_block: Vec<(Vec<u8>, Vec<u8>)> = iter.by_ref().take(5).collect();
+        assert_eq!(list_block.len(), 5);
+        assert_eq!(list_block[0].0, b"key-0000".to_vec());
+        assert_eq!(list_block[4].0, b"key-0004".to_vec());
+        assert_eq!(iter.index, 6);
+
+        let first = bucket.find_next_iter(b"anchor".to_vec(), false).next();
+        assert_eq!(first, Some((b"anchor".to_vec(), b"a".to_vec())));
+        assert!(
+            bucket
+                .find_next_iter(b"missing".to_vec(), false)
+                .next()
+                .is_none()
+        );
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_set_group() {
+        let file_path = String::from("data_set_group.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"user:2/name".to_vec(), b"bob".to_vec())
+            .unwrap();
+        bucket
+            .set_group(
+                b"user:1/".to_vec(),
+                vec![
+                    (b"name".to_vec(), b"alice".to_vec()),
+                    (b"age".to_vec(), b"30".to_vec()),
+                ],
+            )
+            .unwrap();
+
+        assert_eq!(bucket.get(b"user:1/name".to_vec()).1, b"alice".to_vec());
+        assert_eq!(bucket.get(b"user:1/age".to_vec()).1, b"30".to_vec());
+        let first = bucket.get_prefix_first(b"user:1/".to_vec());
+        assert_eq!(first, Some((b"user:1/name".to_vec(), b"alice".to_vec())));
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_list_region() {
+        let file_path = String::from("data_list_region.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        bucket
+            .set(b"key-002".to_vec(), b"value-002".to_vec())
+            .unwrap();
+
+        let (start, end) = bucket.list_region();
+        let (start_list_point, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        let raw = fs::read(&file_path).unwrap();
+        assert_eq!(start, start_list_point);
+        assert_eq!(&raw[start..end - 1], list_block_data.as_slice());
+        assert_eq!(raw[end - 1], END);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_empty_file() {
+        let file_path = String::from("data_empty_file.db");
+        let _ = fs::remove_file(&file_path);
+        fs::File::create(&file_path).unwrap();
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+
+        let (start_list_point, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        assert_eq!(start_list_point, 128);
+        assert!(list_block_data.is_empty());
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        assert_eq!(bucket.get(b"key-001".to_vec()).1, b"value-001".to_vec());
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_truncated_file() {
+        let file_path = String::from("data_truncated_file.db");
+        let _ = fs::remove_file(&file_path);
+        fs::write(&file_path, [1u8, 2, 3, END, 4, END]).unwrap();
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+
+        let error = get_list_config(&mut bucket.reader).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        let error = bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(fs::read(&file_path).unwrap(), vec![1u8, 2, 3, END, 4, END]);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_get_value() {
+        let file_path = String::from("data_get_value.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        let long_key = vec![b'k'; 300];
+        bucket
+            .set(long_key.clone(), b"value-long".to_vec())
+            .unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        bucket.set(b"key-empty".to_vec(), Vec::new()).unwrap();
+
+        for key in [long_key, b"key-001".to_vec(), b"key-empty".to_vec()] {
+            assert_eq!(bucket.get_value(key.clone()), Some(bucket.get(key).1));
+        }
+        assert_eq!(bucket.get_value(b"key-002".to_vec()), None);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_get_many() {
+        let file_path = String::from("data_get_many.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"key-a".to_vec(), b"value-a-long".to_vec())
+            .unwrap();
+        bucket.set(b"key-b".to_vec(), b"value-b".to_vec()).unwrap();
+        bucket.set(b"key-c".to_vec(), b"value-c".to_vec()).unwrap();
+        bucket.delete(b"key-a".to_vec()).unwrap();
+        // key-d dùng lại chỗ trống của key-a: đứng sau trong list nhưng trước trong file
+        bucket.set(b"key-d".to_vec(), b"value-d".to_vec()).unwrap();
+
+        let list_key = vec![
+            b"key-c".to_vec(),
+            b"key-d".to_vec(),
+            b"key-x".to_vec(),
+            b"key-b".to_vec(),
+            b"key-c".to_vec(),
+        ];
+        let list_block = bucket.get_many(list_key.clone());
+        assert_eq!(list_block.len(), list_key.len());
+        for (key, (key_block, value_block)) in list_key.iter().zip(list_block) {
+            let (key_expected, value_expected) = bucket.get(key.clone());
+            assert_eq!(key_block, key_expected);
+            assert_eq!(value_block, value_expected);
+        }
+
+        let (_, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        let read_plan = get_many_read_plan(&list_block_data, &list_key);
+        let list_start: Vec<usize> = read_plan.iter().map(|(b, _)| b.start).collect();
+        let mut list_sorted = list_start.clone();
+        list_sorted.sort();
+        assert_eq!(list_start, list_sorted);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_compacted_size() {
+        let file_path = String::from("data_compacted_size.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 0..30 {
+            let key = format!("key-{:02}", i).into_bytes();
+            bucket.set(key, vec![b'v'; 20 + i]).unwrap();
+        }
+        for i in (0..30).step_by(3) {
+            bucket.delete(format!("key-{:02}", i).into_bytes()).unwrap();
+        }
+
+        let predicted = bucket.compacted_size();
+        assert!(predicted < fs::metadata(&file_path).unwrap().len() as usize);
+        bucket.compact().unwrap();
+        assert_eq!(predicted, fs::metadata(&file_path).unwrap().len() as usize);
+        assert_eq!(bucket.compacted_size(), predicted);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_single_writer_multi_reader() {
+        let file_path = String::from("data_single_writer_multi_reader.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"stable".to_vec(), b"value-stable".to_vec())
+            .unwrap();
+
+        let mut list_reader = Vec::new();
+        for _ in 0..3 {
+            let path = file_path.clone();
+            list_reader.push(thread::spawn(move || {
+                let mode = AccessMode::SingleWriterMultiReader;
+                let mut reader = Bucket::new_with_access(path, mode).unwrap();
+                for _ in 0..100 {
+                    assert_eq!(reader.get(b"stable".to_vec()).1, b"value-stable".to_vec());
+                    for (key, value) in reader.list(255) {
+                        // value = "value-" + key + một số dấu '.'
+                        let expected = [b"value-".as_slice(), &key].concat();
+                        assert!(value.starts_with(&expected));
+                        assert!(value[expected.len()..].iter().all(|&x| x == b'.'));
                     }
-                    block_info = EMPTY_BLOCK;
-                }
-                END => {
-                    break;
                 }
-                _ => {
-                    tmp_group.push(v);
+            }));
+        }
+        let mode = AccessMode::SingleWriterMultiReader;
+        let mut writer = Bucket::new_with_access(file_path.clone(), mode).unwrap();
+        for i in 0..100 {
+            let key = format!("key-{:02}", i % 20).into_bytes();
+            let value = [b"value-".as_slice(), &key, &vec![b'.'; i % 7]].concat();
+            writer.set(key, value).unwrap();
+        }
+        for reader in list_reader {
+            reader.join().unwrap();
+        }
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_set_with_retry() {
+        let file_path = String::from("data_set_with_retry.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        let backoff = Duration::from_millis(10);
+
+        let holder = fs::File::open(&file_path).unwrap();
+        holder.lock().unwrap();
+        let error = bucket
+            .set_with_retry(b"key-001".to_vec(), b"value-001".to_vec(), 2, backoff)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::WouldBlock);
+
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            holder.unlock().unwrap();
+        });
+        bucket
+            .set_with_retry(b"key-001".to_vec(), b"value-001".to_vec(), 100, backoff)
+            .unwrap();
+        release.join().unwrap();
+        assert_eq!(bucket.get(b"key-001".to_vec()).1, b"value-001".to_vec());
+        bucket
+            .delete_with_retry(b"key-001".to_vec(), 2, backoff)
+            .unwrap();
+        assert!(bucket.get(b"key-001".to_vec()).0.is_empty());
+
+        // lock đã được trả lại sau mỗi lần gọi
+        let other = fs::File::open(&file_path).unwrap();
+        other.try_lock().unwrap();
+        other.unlock().unwrap();
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_compact_step() {
+        let file_path = String::from("data_compact_step.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 0..30 {
+            let key = format!("key-{:02}", i).into_bytes();
+            bucket.set(key, vec![b'v'; 10 + i]).unwrap();
+        }
+        for i in (0..30).step_by(2) {
+            bucket.delete(format!("key-{:02}", i).into_bytes()).unwrap();
+        }
+        let size_before = fs::metadata(&file_path).unwrap().len();
+
+        let mut step = 0;
+        while bucket.compact_step(3).unwrap() {
+            step += 1;
+            for i in (1..30).step_by(2) {
+                let key = format!("key-{:02}", i).into_bytes();
+                assert_eq!(bucket.get(key).1, vec![b'v'; 10 + i]);
+            }
+        }
+        // block không được chép vào chỗ cũ của block vừa dời trong cùng lần gọi
+        assert_eq!(step, 16);
+        assert!(fs::metadata(&file_path).unwrap().len() < size_before);
+        // không còn khoảng trống và list nằm cuối file; block dời ra sau list có thể đổi
+        // thứ tự nên kích thước list có thể khác compacted_size
+        assert_eq!(bucket.fragment_count(), 0);
+        let (start_list_point, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        assert_eq!(
+            fs::metadata(&file_path).unwrap().len() as usize,
+            start_list_point + list_block_data.len() + 1
+        );
+        assert!(bucket.check_overlaps().is_empty());
+        for i in (1..30).step_by(2) {
+            let key = format!("key-{:02}", i).into_bytes();
+            assert_eq!(bucket.get(key).1, vec![b'v'; 10 + i]);
+        }
+        assert!(!bucket.compact_step(3).unwrap());
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_compact_step_crash_before_list() {
+        let file_path = String::from("data_compact_step_crash.db");
+        let crash_path = String::from("data_compact_step_crash_copy.db");
+        let _ = fs::remove_file(&file_path);
+        let _ = fs::remove_file(&crash_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        // a: 128..138, b: 138..143, c: 143..153; b dời về 128 thì c không được chép đè
+        // lên 138..143 khi list trên đĩa còn trỏ tới đó
+        bucket.set(b"a".to_vec(), vec![b'a'; 9]).unwrap();
+        bucket.set(b"b".to_vec(), vec![b'b'; 4]).unwrap();
+        bucket.set(b"c".to_vec(), vec![b'c'; 9]).unwrap();
+        for i in 0..20 {
+            bucket
+                .set(format!("key-{:02}", i).into_bytes(), vec![b'v'; 3 + i])
+                .unwrap();
+        }
+        bucket.delete(b"a".to_vec()).unwrap();
+        for i in (0..20).step_by(3) {
+            bucket.delete(format!("key-{:02}", i).into_bytes()).unwrap();
+        }
+        let mut list_expected = bucket.list(255);
+        list_expected.sort();
+
+        loop {
+            let before = fs::read(&file_path).unwrap();
+            let (start_list_point, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+            let is_more = bucket.compact_step(10).unwrap();
+            let after = fs::read(&file_path).unwrap();
+            // crash trước khi ghi header: header và list cũ, dữ liệu đã chép
+            let mut crash = before.clone();
+            let list_end = start_list_point + list_block_data.len() + 1;
+            for (j, &x) in after.iter().enumerate().skip(128) {
+                if j < start_list_point {
+                    crash[j] = x;
+                } else if j >= list_end {
+                    if j >= crash.len() {
+                        crash.resize(j + 1, 0);
+                    }
+                    crash[j] = x;
                 }
             }
+            fs::write(&crash_path, &crash).unwrap();
+            let mut crashed = Bucket::new(crash_path.clone()).unwrap();
+            let mut list_crashed = crashed.list(255);
+            list_crashed.sort();
+            assert_eq!(list_crashed, list_expected);
+            if !is_more {
+                break;
+            }
+        }
+        let mut list_compacted = bucket.list(255);
+        list_compacted.sort();
+        assert_eq!(list_compacted, list_expected);
+        // block giữ thứ tự trong file, không theo thứ tự list như compacted_size
+        assert_eq!(bucket.fragment_count(), 0);
+        let (start_list_point, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        assert_eq!(
+            fs::metadata(&file_path).unwrap().len() as usize,
+            start_list_point + list_block_data.len() + 1
+        );
+
+        fs::remove_file(crash_path).unwrap();
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_compact_step_keeps_reservation() {
+        let file_path = String::from("data_compact_step_reservation.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket.set(b"key-001".to_vec(), vec![b'a'; 64]).unwrap();
+        let handle = bucket.reserve_value(b"key-002".to_vec(), 16).unwrap();
+        bucket.set(b"key-003".to_vec(), vec![b'c'; 8]).unwrap();
+        bucket.delete(b"key-001".to_vec()).unwrap();
+
+        while bucket.compact_step(1).unwrap() {}
+        bucket.write_value(&handle, 0, &[b'b'; 16]).unwrap();
+        bucket.finalize_value(handle).unwrap();
+        assert_eq!(bucket.get_value(b"key-002".to_vec()), Some(vec![b'b'; 16]));
+        assert_eq!(bucket.get_value(b"key-003".to_vec()), Some(vec![b'c'; 8]));
+        assert!(bucket.check_overlaps().is_empty());
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_read_raw() {
+        let file_path = String::from("data_read_raw.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+
+        let header = bucket.read_raw(0, 128).unwrap();
+        assert_eq!(header.iter().filter(|&&x| x == END).count(), 2);
+        assert_eq!(
+            bucket.read_raw(128, 16).unwrap(),
+            b"key-001value-001".to_vec()
+        );
+        let error = bucket.read_raw(100_000, 1).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_from_file() {
+        let file_path = std::env::temp_dir().join("blockbucket_from_file.db");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&file_path)
+            .unwrap();
+        // giống tempfile(): file đã bị unlink, chỉ còn handle
+        fs::remove_file(&file_path).unwrap();
+
+        let mut bucket = Bucket::from_file_single(file).unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        assert_eq!(bucket.get(b"key-001".to_vec()).1, b"value-001".to_vec());
+        let error = bucket.compact().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+
+        let file_path = String::from("data_from_file.db");
+        let _ = fs::remove_file(&file_path);
+        Bucket::new(file_path.clone()).unwrap();
+        let reader = fs::File::open(&file_path).unwrap();
+        let writer = OpenOptions::new().write(true).open(&file_path).unwrap();
+        let mut bucket = Bucket::from_file(reader, writer);
+        bucket
+            .set(b"key-002".to_vec(), b"value-002".to_vec())
+            .unwrap();
+        let mut reopened = Bucket::new(file_path.clone()).unwrap();
+        assert_eq!(reopened.get(b"key-002".to_vec()).1, b"value-002".to_vec());
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_fragment_count() {
+        let file_path = String::from("data_fragment_count.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 0..10 {
+            let key = format!("key-{:02}", i).into_bytes();
+            bucket.set(key, b"value".to_vec()).unwrap();
+        }
+        assert_eq!(bucket.fragment_count(), 0);
+
+        for i in [2, 4, 6] {
+            bucket.delete(format!("key-{:02}", i).into_bytes()).unwrap();
+        }
+        assert_eq!(bucket.fragment_count(), 3);
+        // xoá block kề bên: hai khoảng trống gộp thành một
+        bucket.delete(b"key-03".to_vec()).unwrap();
+        assert_eq!(bucket.fragment_count(), 2);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_bulk_load() {
+        let file_path = String::from("data_bulk_load.db");
+        let _ = fs::remove_file(&file_path);
+        let list_data = (0..10_000).map(|i| (format!("key-{:05}", i).into_bytes(), vec![b'v'; 32]));
+
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket.bulk_load(list_data.clone()).unwrap();
+
+        assert_eq!(bucket.list(10)[0].0, b"key-00000".to_vec());
+        assert_eq!(bucket.list_next(1, 9_999)[0].0, b"key-09999".to_vec());
+        assert_eq!(bucket.get(b"key-04567".to_vec()).1, vec![b'v'; 32]);
+        assert_eq!(bucket.fragment_count(), 0);
+        // các block nằm liền nhau từ byte 128, list ghi một lần ngay sau block cuối
+        let (start_list_point, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        assert_eq!(start_list_point, 128 + 10_000 * (9 + 32));
+        assert_eq!(
+            fs::metadata(&file_path).unwrap().len() as usize,
+            start_list_point + list_block_data.len() + 1
+        );
+        let error = bucket.bulk_load(list_data.take(1)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_validate_key() {
+        let file_path = String::from("data_validate_key.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        bucket
+            .set(b"key-002".to_vec(), b"value-002".to_vec())
+            .unwrap();
+        assert!(bucket.validate_key(b"key-001".to_vec()).unwrap());
+        assert!(bucket.validate_key(b"key-002".to_vec()).unwrap());
+        assert!(!bucket.validate_key(b"key-003".to_vec()).unwrap());
+
+        let (start_list_point, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        let mut list_block_info = get_list_block_info(&list_block_data);
+        list_block_info[1].sum_key += 1;
+        let list_block_data = list_block_info.iter().fold(Vec::new(), push_block_to_data);
+        update_list_block(&mut bucket.writer, start_list_point, list_block_data).unwrap();
+
+        assert!(bucket.validate_key(b"key-001".to_vec()).unwrap());
+        assert!(!bucket.validate_key(b"key-002".to_vec()).unwrap());
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_clone_to() {
+        let file_path = String::from("data_clone_to.db");
+        let dest_path = String::from("data_clone_to_copy.db");
+        let _ = fs::remove_file(&file_path);
+        let _ = fs::remove_file(&dest_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 0..20 {
+            bucket
+                .set(format!("key-{:02}", i).into_bytes(), vec![b'v'; 64])
+                .unwrap();
+        }
+        for i in (0..20).step_by(2) {
+            bucket.delete(format!("key-{:02}", i).into_bytes()).unwrap();
+        }
+        let size_before = fs::metadata(&file_path).unwrap().len();
+
+        let mut clone = bucket.clone_to(dest_path.clone()).unwrap();
+
+        // bản gốc không thay đổi
+        assert_eq!(fs::metadata(&file_path).unwrap().len(), size_before);
+        assert!(fs::metadata(&dest_path).unwrap().len() < size_before);
+        assert_eq!(clone.list(255), bucket.list(255));
+        for i in (1..20).step_by(2) {
+            let key = format!("key-{:02}", i).into_bytes();
+            assert_eq!(clone.get(key.clone()), bucket.get(key));
+        }
+        bucket.set(b"key-20".to_vec(), b"new".to_vec()).unwrap();
+        assert!(clone.get_value(b"key-20".to_vec()).is_none());
+
+        // không ghi đè file đã có, kể cả file của chính bucket
+        let original = fs::read(&dest_path).unwrap();
+        let error = bucket.clone_to(dest_path.clone()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&dest_path).unwrap(), original);
+        let original = fs::read(&file_path).unwrap();
+        let error = bucket.clone_to(file_path.clone()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&file_path).unwrap(), original);
+        assert_eq!(bucket.get_value(b"key-20".to_vec()), Some(b"new".to_vec()));
+
+        fs::remove_file(file_path).unwrap();
+        fs::remove_file(dest_path).unwrap()
+    }
+
+    #[test]
+    fn test_blocks_in_range() {
+        let file_path = String::from("data_blocks_in_range.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        // mỗi block dài 7 + 9 = 16 byte, bắt đầu tại 128, 144, 160, 176
+        for i in 0..4 {
+            bucket
+                .set(
+                    format!("key-00{}", i).into_bytes(),
+                    format!("value-00{}", i).into_bytes(),
+                )
+                .unwrap();
+        }
+        let starts = |blocks: Vec<Block>| blocks.iter().map(|b| b.start).collect::<Vec<_>>();
+        assert_eq!(
+            starts(bucket.blocks_in_range(0, usize::MAX)),
+            vec![128, 144, 160, 176]
+        );
+        assert_eq!(starts(bucket.blocks_in_range(144, 176)), vec![144, 160]);
+        assert_eq!(
+            starts(bucket.blocks_in_range(145, 160)),
+            Vec::<usize>::new()
+        );
+        assert_eq!(starts(bucket.blocks_in_range(130, 150)), vec![144]);
+        let block = &bucket.blocks_in_range(160, 161)[0];
+        assert_eq!(
+            bucket.read_raw(block.start, block.size_key).unwrap(),
+            b"key-002".to_vec()
+        );
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_lookup_cost() {
+        let file_path = String::from("data_lookup_cost.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        // "ac", "bb", "ca" cùng độ dài và cùng sum_key
+        bucket.set(b"ac".to_vec(), b"1".to_vec()).unwrap();
+        bucket.set(b"xy".to_vec(), b"2".to_vec()).unwrap();
+        bucket.set(b"bb".to_vec(), b"3".to_vec()).unwrap();
+        bucket.set(b"ca".to_vec(), b"4".to_vec()).unwrap();
+        assert_eq!(bucket.lookup_cost(b"ac".to_vec()), 1);
+        assert_eq!(bucket.lookup_cost(b"bb".to_vec()), 2);
+        assert_eq!(bucket.lookup_cost(b"ca".to_vec()), 3);
+        assert_eq!(bucket.lookup_cost(b"xy".to_vec()), 1);
+        // key không tồn tại: kiểm tra hết các block trùng
+        assert_eq!(bucket.lookup_cost(b"d`".to_vec()), 3);
+        assert_eq!(bucket.lookup_cost(b"zz".to_vec()), 0);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_adjacent_holes_are_merged() {
+        let file_path = String::from("data_adjacent_holes.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        // mỗi block dài 16 byte, bắt đầu tại 128, 144, 160, 176
+        for i in 1..5 {
+            bucket
+                .set(
+                    format!("key-00{}", i).into_bytes(),
+                    format!("value-00{}", i).into_bytes(),
+                )
+                .unwrap();
+        }
+        bucket.delete(b"key-002".to_vec()).unwrap();
+        bucket.delete(b"key-003".to_vec()).unwrap();
+        // hai khoảng trống liền nhau được tính là một khoảng 32 byte
+        assert_eq!(bucket.fragment_count(), 1);
+
+        bucket.set(b"key-005".to_vec(), vec![b'v'; 25]).unwrap();
+        let blocks = bucket.blocks_in_range(144, 176);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].start, 144);
+        assert_eq!(blocks[0].size_key + blocks[0].size_data, 32);
+        assert_eq!(bucket.fragment_count(), 0);
+        assert_eq!(bucket.get_value(b"key-005".to_vec()), Some(vec![b'v'; 25]));
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_new_syncs_parent_dir() {
+        let dir_path = String::from("data_new_sync_dir");
+        let file_path = format!("{}/data.db", dir_path);
+        let _ = fs::remove_dir_all(&dir_path);
+        fs::create_dir(&dir_path).unwrap();
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        assert!(fs::metadata(&file_path).unwrap().is_file());
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        assert!(sync_parent_dir(&file_path).is_ok());
+        // đường dẫn không có thư mục cha dùng thư mục hiện tại
+        assert!(sync_parent_dir("data_new_sync.db").is_ok());
+
+        fs::remove_dir_all(dir_path).unwrap()
+    }
+
+    #[test]
+    fn test_u64_key() {
+        let file_path = String::from("data_u64_key.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        let numbers: Vec<u64> = vec![300, 2, 70_000, 10, 0, u64::MAX, 256];
+        for &n in &numbers {
+            bucket
+                .set(encode_u64_key(n), n.to_string().into_bytes())
+                .unwrap();
+        }
+        // sắp xếp theo byte cho cùng thứ tự như sắp xếp theo số
+        let mut list_key: Vec<Vec<u8>> = bucket.list(255).into_iter().map(|(k, _)| k).collect();
+        list_key.sort();
+        let decoded: Vec<u64> = list_key
+            .iter()
+            .map(|k| decode_u64_key(k).unwrap())
+            .collect();
+        let mut sorted = numbers.clone();
+        sorted.sort();
+        assert_eq!(decoded, sorted);
+        assert_eq!(
+            bucket.get_value(encode_u64_key(70_000)),
+            Some(b"70000".to_vec())
+        );
+        assert_eq!(decode_u64_key(b"short"), None);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_touch() {
+        let file_path = String::from("data_touch.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        assert!(bucket.touch(b"key-001".to_vec()).unwrap());
+        assert_eq!(
+            bucket.get(b"key-001".to_vec()),
+            (b"key-001".to_vec(), Vec::new())
+        );
+        assert!(!bucket.touch(b"key-001".to_vec()).unwrap());
+        assert_eq!(bucket.list(255).len(), 1);
+
+        // key đã có giá trị thì touch không ghi đè
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        assert!(!bucket.touch(b"key-001".to_vec()).unwrap());
+        assert_eq!(
+            bucket.get_value(b"key-001".to_vec()),
+            Some(b"value-001".to_vec())
+        );
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_group_by_prefix() {
+        let file_path = String::from("data_group_by_prefix.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket.set(b"a/1".to_vec(), b"v1".to_vec()).unwrap();
+        bucket.set(b"b/1".to_vec(), b"v2".to_vec()).unwrap();
+        bucket.set(b"a/2".to_vec(), b"v3".to_vec()).unwrap();
+        bucket.set(b"a/2/x".to_vec(), b"v4".to_vec()).unwrap();
+
+        let groups = bucket.group_by_prefix(b'/');
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].0, b"a".to_vec());
+        assert_eq!(
+            groups[0].1,
+            vec![
+                (b"a/1".to_vec(), b"v1".to_vec()),
+                (b"a/2".to_vec(), b"v3".to_vec()),
+                (b"a/2/x".to_vec(), b"v4".to_vec()),
+            ]
+        );
+        assert_eq!(
+            groups[1],
+            (b"b".to_vec(), vec![(b"b/1".to_vec(), b"v2".to_vec())])
+        );
+
+        // key không có ký tự phân tách tạo nhóm riêng
+        bucket.set(b"c".to_vec(), b"v5".to_vec()).unwrap();
+        let groups = bucket.group_by_prefix(b'/');
+        assert_eq!(
+            groups[2],
+            (b"c".to_vec(), vec![(b"c".to_vec(), b"v5".to_vec())])
+        );
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_open_worm() {
+        let file_path = String::from("data_open_worm.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::open_worm(file_path.clone()).unwrap();
+        // mỗi block dài 16 byte, ghi liên tiếp từ 128
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        bucket
+            .set_many(vec![
+                (b"key-002".to_vec(), b"value-002".to_vec()),
+                (b"key-003".to_vec(), b"value-003".to_vec()),
+            ])
+            .unwrap();
+        let starts = |blocks: Vec<Block>| blocks.iter().map(|b| b.start).collect::<Vec<_>>();
+        assert_eq!(
+            starts(bucket.blocks_in_range(0, usize::MAX)),
+            vec![128, 144, 160]
+        );
+
+        for result in [
+            bucket.delete(b"key-001".to_vec()),
+            bucket.delete_to(b"key-002".to_vec(), true),
+            bucket.list_lock_delete(1).map(|_| ()),
+            bucket.delete_with_retry(b"key-001".to_vec(), 1, Duration::from_millis(1)),
+        ] {
+            assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+        }
+        assert_eq!(bucket.list(255).len(), 3);
+
+        // cập nhật key: giá trị mới được ghi nối tiếp, không dùng lại chỗ cũ
+        bucket
+            .set(b"key-001".to_vec(), b"value-004".to_vec())
+            .unwrap();
+        assert_eq!(
+            starts(bucket.blocks_in_range(0, usize::MAX)),
+            vec![144, 160, 176]
+        );
+        assert_eq!(
+            bucket.get_value(b"key-001".to_vec()),
+            Some(b"value-004".to_vec())
+        );
+        assert_eq!(
+            bucket.get_value(b"key-002".to_vec()),
+            Some(b"value-002".to_vec())
+        );
+
+        // mở lại ở chế độ thường thì xóa được
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket.delete(b"key-001".to_vec()).unwrap();
+        assert!(bucket.get_value(b"key-001".to_vec()).is_none());
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_snapshot_iter() {
+        let file_path = String::from("data_snapshot_iter.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        let mut other = Bucket::new(file_path.clone()).unwrap();
+        for i in 1..4 {
+            bucket
+                .set(
+                    format!("key-00{}", i).into_bytes(),
+                    format!("value-00{}", i).into_bytes(),
+                )
+                .unwrap();
+        }
+
+        let mut iter = bucket.snapshot_iter();
+        assert_eq!(
+            iter.next(),
+            Some((b"key-001".to_vec(), b"value-001".to_vec()))
+        );
+        // ghi qua handle khác trong lúc đang duyệt
+        other.delete(b"key-002".to_vec()).unwrap();
+        other.set(b"key-004".to_vec(), vec![b'v'; 32]).unwrap();
+        assert_eq!(
+            iter.collect::<Vec<_>>(),
+            vec![
+                (b"key-002".to_vec(), b"value-002".to_vec()),
+                (b"key-003".to_vec(), b"value-003".to_vec()),
+            ]
+        );
+        assert!(bucket.get_value(b"key-002".to_vec()).is_none());
+        assert_eq!(bucket.snapshot_iter().count(), 3);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_export_key_hashes() {
+        let file_path = String::from("data_export_key_hashes.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 0..10 {
+            bucket
+                .set(format!("key-{:03}", i).into_bytes(), vec![b'v'; 64])
+                .unwrap();
+        }
+        bucket.delete(b"key-003".to_vec()).unwrap();
+
+        let list_hash = bucket.export_key_hashes();
+        assert_eq!(list_hash.len(), bucket.list(255).len());
+        assert!(list_hash.contains(&key_hash(b"key-007")));
+        assert!(!list_hash.contains(&key_hash(b"key-003")));
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_format_version() {
+        let file_path = String::from("data_format_version.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        assert_eq!(bucket.format_version().unwrap(), 1);
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        assert_eq!(bucket.format_version().unwrap(), 1);
+        assert!(!bucket.needs_migration());
+
+        // ghi lại header theo dạng cũ (không có checksum)
+        let (start_list_point, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        let mut header = group_digits_to_vec(start_list_point);
+        header.push(END);
+        header.extend(group_digits_to_vec(list_block_data.len()));
+        header.push(END);
+        header.resize(128, 0);
+        bucket.writer.seek(Start(0)).unwrap();
+        bucket.writer.write_all(&header).unwrap();
+
+        assert_eq!(bucket.format_version().unwrap(), 0);
+        assert!(bucket.needs_migration());
+        assert_eq!(
+            bucket.get_value(b"key-001".to_vec()),
+            Some(b"value-001".to_vec())
+        );
+        // lần ghi tiếp theo nâng cấp header
+        bucket
+            .set(b"key-002".to_vec(), b"value-002".to_vec())
+            .unwrap();
+        assert_eq!(bucket.format_version().unwrap(), 1);
+        assert!(!bucket.needs_migration());
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_find_value_prefix() {
+        let file_path = String::from("data_find_value_prefix.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"user-1".to_vec(), b"role=admin;name=a".to_vec())
+            .unwrap();
+        bucket
+            .set(b"user-2".to_vec(), b"role=guest;name=b".to_vec())
+            .unwrap();
+        bucket
+            .set(b"user-3".to_vec(), b"role=admin;name=c".to_vec())
+            .unwrap();
+        bucket.set(b"role=admin".to_vec(), b"x".to_vec()).unwrap();
+
+        let found = bucket.find_value_prefix(b"role=admin".to_vec(), 10);
+        assert_eq!(
+            found,
+            vec![
+                (b"user-1".to_vec(), b"role=admin;name=a".to_vec()),
+                (b"user-3".to_vec(), b"role=admin;name=c".to_vec()),
+            ]
+        );
+        assert_eq!(bucket.find_value_prefix(b"role=admin".to_vec(), 1).len(), 1);
+        assert_eq!(bucket.find_value_prefix(b"role=".to_vec(), 10).len(), 3);
+        assert!(bucket.find_value_prefix(b"none".to_vec(), 10).is_empty());
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_list_length_cross_check() {
+        let file_path = String::from("data_list_length_cross_check.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        // offset và kích thước chứa "255" được lưu dạng nhóm chữ số, không chứa byte END
+        let block = Block {
+            start: 255,
+            size_key: 255,
+            sum_key: 25_525,
+            sum_md5: 2_550,
+            size_data: 1_255,
+        };
+        let list_block_data = push_block_to_data(Vec::new(), &block);
+        assert!(!list_block_data.contains(&END));
+        update_list_block(&mut bucket.writer, 2_000, list_block_data.clone()).unwrap();
+        assert_eq!(
+            get_list_config(&mut bucket.reader).unwrap(),
+            (2_000, list_block_data.clone())
+        );
+
+        // header cũ (không có checksum) khai báo độ dài ngắn hơn list thật
+        let mut header = group_digits_to_vec(2_000);
+        header.push(END);
+        header.extend(group_digits_to_vec(list_block_data.len() - 3));
+        header.push(END);
+        header.resize(128, 0);
+        bucket.writer.seek(Start(0)).unwrap();
+        bucket.writer.write_all(&header).unwrap();
+        let error = get_list_config(&mut bucket.reader).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        // độ dài dài hơn vẫn đọc được nhờ END ở cuối list
+        let mut header = group_digits_to_vec(2_000);
+        header.push(END);
+        header.extend(group_digits_to_vec(list_block_data.len() + 1));
+        header.push(END);
+        header.resize(128, 0);
+        bucket.writer.seek(Start(0)).unwrap();
+        bucket.writer.write_all(&header).unwrap();
+        assert_eq!(
+            get_list_config(&mut bucket.reader).unwrap(),
+            (2_000, list_block_data)
+        );
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_shard_into() {
+        let file_path = String::from("data_shard_into.db");
+        let dest_prefix = "data_shard_into_";
+        let _ = fs::remove_file(&file_path);
+        for i in 0..4 {
+            let _ = fs::remove_file(format!("{}{}.db", dest_prefix, i));
+        }
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .bulk_load((0..1000).map(|i| {
+                (
+                    format!("key-{:04}", i).into_bytes(),
+                    format!("value-{}", i).into_bytes(),
+                )
+            }))
+            .unwrap();
+
+        let mut list_shard = bucket.shard_into(4, dest_prefix, key_hash).unwrap();
+        assert_eq!(list_shard.len(), 4);
+        let total: usize = list_shard
+            .iter_mut()
+            .map(|s| s.export_key_hashes().len())
+            .sum();
+        assert_eq!(total, 1000);
+        for i in (0..1000).step_by(37) {
+            let key = format!("key-{:04}", i).into_bytes();
+            let found: Vec<Vec<u8>> = list_shard
+                .iter_mut()
+                .filter_map(|s| s.get_value(key.clone()))
+                .collect();
+            assert_eq!(found, vec![format!("value-{}", i).into_bytes()]);
+            let shard_index = (key_hash(&key) % 4) as usize;
+            assert!(list_shard[shard_index].get_value(key).is_some());
+        }
+
+        // shard đã có dữ liệu thì không ghi đè
+        let error = bucket
+            .shard_into(4, dest_prefix, key_hash)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        bucket
+            .set(b"key-1000".to_vec(), b"value-1000".to_vec())
+            .unwrap();
+
+        fs::remove_file(file_path).unwrap();
+        for i in 0..4 {
+            fs::remove_file(format!("{}{}.db", dest_prefix, i)).unwrap();
         }
     }
-    result
-}
 
-fn get_list_lock_delete_data(
-    read: &mut File,
-    write: &mut File,
-    start_list_point: usize,
-    list_block_data: Vec<u8>,
-    limit: u8,
-) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
-    let mut result: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
-    let mut end_key: Vec<u8> = Vec::new();
-    {
-        let mut block_info = EMPTY_BLOCK;
-        let mut tmp_group: Vec<u8> = Vec::new();
-        let mut current: u8 = 0;
-        for v in list_block_data.clone() {
-            if current >= limit {
-                break;
-            }
-            match v {
-                START => {
-                    block_info.start = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SIZE_KEY => {
-                    block_info.size_key = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SUM_KEY => {
-                    block_info.sum_key = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SUM_MD5 => {
-                    block_info.sum_md5 = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SIZE_DATA => {
-                    block_info.size_data = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                    {
-                        let (found_key, found_data) = pull_data(read, &block_info)
-                            .unwrap_or_else(|_| (Vec::new(), Vec::new()));
-                        let len_found_key = found_key.len();
-                        if len_found_key == block_info.size_key {
-                            let sum_found_key: usize = found_key.iter().map(|&x| x as usize).sum();
-                            if sum_found_key == block_info.sum_key {
-                                let sum_found_md5: usize = md5::compute(&found_key)
-                                    .to_vec()
-                                    .iter()
-                                    .map(|&x| x as usize)
-                                    .sum();
-                                if sum_found_md5 == block_info.sum_md5 {
-                                    // success
-                                    end_key = found_key.clone();
-                                    result.push((found_key, found_data));
-                                    current += 1;
-                                }
-                            }
-                        }
-                    }
-                    block_info = EMPTY_BLOCK;
-                }
-                END => {
-                    break;
-                }
-                _ => {
-                    tmp_group.push(v);
-                }
-            }
+    #[test]
+    fn test_reopen() {
+        let file_path = String::from("data_reopen.db");
+        let other_path = String::from("data_reopen_other.db");
+        let _ = fs::remove_file(&file_path);
+        let _ = fs::remove_file(&other_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket.set(b"key-001".to_vec(), b"old".to_vec()).unwrap();
+
+        // thay file từ bên ngoài
+        let mut other = Bucket::new(other_path.clone()).unwrap();
+        other.set(b"key-001".to_vec(), b"new".to_vec()).unwrap();
+        other.set(b"key-002".to_vec(), b"new".to_vec()).unwrap();
+        fs::rename(&other_path, &file_path).unwrap();
+        assert_eq!(bucket.get_value(b"key-001".to_vec()), Some(b"old".to_vec()));
+
+        bucket.reopen().unwrap();
+        assert_eq!(bucket.get_value(b"key-001".to_vec()), Some(b"new".to_vec()));
+        assert_eq!(bucket.get_value(b"key-002".to_vec()), Some(b"new".to_vec()));
+        bucket.set(b"key-003".to_vec(), b"new".to_vec()).unwrap();
+        assert_eq!(other.get_value(b"key-003".to_vec()), Some(b"new".to_vec()));
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&file_path)
+            .unwrap();
+        let mut bucket = Bucket::from_file_single(file).unwrap();
+        assert_eq!(bucket.reopen().unwrap_err().kind(), ErrorKind::Unsupported);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_meta() {
+        let file_path = String::from("data_meta.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        assert!(bucket.get_meta("schema").is_none());
+        bucket.set_meta("schema", b"v1").unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        bucket.set_meta("label", b"orders").unwrap();
+        bucket.set_meta("schema", b"v2").unwrap();
+
+        assert_eq!(bucket.get_meta("schema"), Some(b"v2".to_vec()));
+        assert_eq!(bucket.get_meta("label"), Some(b"orders".to_vec()));
+        assert_eq!(
+            bucket.list(255),
+            vec![(b"key-001".to_vec(), b"value-001".to_vec())]
+        );
+        assert_eq!(bucket.format_version().unwrap(), 1);
+
+        // meta được giữ lại khi ghi, xóa và compact
+        bucket
+            .set(b"key-002".to_vec(), b"value-002".to_vec())
+            .unwrap();
+        bucket.delete(b"key-001".to_vec()).unwrap();
+        bucket.compact().unwrap();
+        assert_eq!(bucket.get_meta("schema"), Some(b"v2".to_vec()));
+        assert_eq!(bucket.get_meta("label"), Some(b"orders".to_vec()));
+        assert_eq!(
+            bucket.get_value(b"key-002".to_vec()),
+            Some(b"value-002".to_vec())
+        );
+
+        let error = bucket.set_meta("big", &[b'x'; 40]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert_eq!(bucket.get_meta("label"), Some(b"orders".to_vec()));
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_list_contiguous() {
+        let file_path = String::from("data_list_contiguous.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        let list_data: Vec<(Vec<u8>, Vec<u8>)> = (0..1000)
+            .map(|i| {
+                (
+                    format!("key-{:04}", i).into_bytes(),
+                    format!("value-{}", i).into_bytes(),
+                )
+            })
+            .collect();
+        bucket.bulk_load(list_data.clone().into_iter()).unwrap();
+        assert_eq!(bucket.list(255), list_data[..255].to_vec());
+        assert_eq!(bucket.list(3), list_data[..3].to_vec());
+
+        // có khoảng trống: đọc từng block
+        bucket.delete(b"key-0001".to_vec()).unwrap();
+        let mut expected = list_data.clone();
+        expected.remove(1);
+        assert_eq!(bucket.list(255), expected[..255].to_vec());
+
+        // sau compact các block lại liền nhau
+        bucket.compact().unwrap();
+        assert_eq!(bucket.list(255), expected[..255].to_vec());
+
+        // block có metadata sai vẫn bị bỏ qua
+        let (start_list_point, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        let mut list_block_info = get_list_block_info(&list_block_data);
+        list_block_info[0].sum_key += 1;
+        let list_block_data = list_block_info.iter().fold(Vec::new(), push_block_to_data);
+        update_list_block(&mut bucket.writer, start_list_point, list_block_data).unwrap();
+        assert_eq!(bucket.list(3), expected[1..4].to_vec());
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_bounds() {
+        let file_path = String::from("data_bounds.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        assert_eq!(bucket.bounds(), None);
+        // mỗi block dài 16 byte, bắt đầu tại 128, 144, 160, 176
+        for i in 1..5 {
+            bucket
+                .set(
+                    format!("key-00{}", i).into_bytes(),
+                    format!("value-00{}", i).into_bytes(),
+                )
+                .unwrap();
         }
+        assert_eq!(bucket.bounds(), Some((128, 176)));
+        bucket.delete(b"key-001".to_vec()).unwrap();
+        bucket.delete(b"key-004".to_vec()).unwrap();
+        assert_eq!(bucket.bounds(), Some((144, 160)));
+        // block mới lấp vào chỗ trống đầu tiên
+        bucket
+            .set(b"key-005".to_vec(), b"value-005".to_vec())
+            .unwrap();
+        assert_eq!(bucket.bounds(), Some((128, 160)));
+        bucket.delete(b"key-002".to_vec()).unwrap();
+        bucket.delete(b"key-003".to_vec()).unwrap();
+        bucket.delete(b"key-005".to_vec()).unwrap();
+        assert_eq!(bucket.bounds(), None);
+
+        fs::remove_file(file_path).unwrap()
     }
-    delete_to_data(
-        read,
-        write,
-        start_list_point,
-        list_block_data,
-        true,
-        end_key,
-    )?;
-    Ok(result)
-}
 
-fn get_list_next_data(
-    read: &mut File,
-    list_block_data: Vec<u8>,
-    limit: u8,
-    skip: usize,
-) -> Vec<(Vec<u8>, Vec<u8>)> {
-    let mut result: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
-    {
-        let mut block_info = EMPTY_BLOCK;
-        let mut tmp_group: Vec<u8> = Vec::new();
-        let mut current: u8 = 0;
-        let mut current_skip: usize = 0;
-        for v in list_block_data {
-            if current >= limit {
-                break;
-            }
-            match v {
-                START => {
-                    block_info.start = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SIZE_KEY => {
-                    block_info.size_key = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SUM_KEY => {
-                    block_info.sum_key = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SUM_MD5 => {
-                    block_info.sum_md5 = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SIZE_DATA => {
-                    block_info.size_data = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                    {
-                        let (found_key, found_data) = pull_data(read, &block_info)
-                            .unwrap_or_else(|_| (Vec::new(), Vec::new()));
-                        let len_found_key = found_key.len();
-                        if len_found_key == block_info.size_key {
-                            let sum_found_key: usize = found_key.iter().map(|&x| x as usize).sum();
-                            if sum_found_key == block_info.sum_key {
-                                let sum_found_md5: usize = md5::compute(&found_key)
-                                    .to_vec()
-                                    .iter()
-                                    .map(|&x| x as usize)
-                                    .sum();
-                                if sum_found_md5 == block_info.sum_md5 {
-                                    // success
-                                    if current_skip < skip {
-                                        current_skip += 1;
-                                    } else {
-                                        result.push((found_key, found_data));
-                                        current += 1;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    block_info = EMPTY_BLOCK;
-                }
-                END => {
-                    break;
-                }
-                _ => {
-                    tmp_group.push(v);
-                }
-            }
+    #[test]
+    fn test_open_strict() {
+        let file_path = String::from("data_open_strict.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        bucket
+            .set(b"key-002".to_vec(), b"value-002".to_vec())
+            .unwrap();
+        assert!(Bucket::open_strict(file_path.clone()).is_ok());
+
+        // chép block của key-001 ra sau và thêm vào list
+        let (start_list_point, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        let mut block = get_list_block_info(&list_block_data)[0].clone();
+        let raw = bucket.read_raw(block.start, 16).unwrap();
+        bucket.writer.seek(Start(start_list_point as u64)).unwrap();
+        bucket.writer.write_all(&raw).unwrap();
+        block.start = start_list_point;
+        let list_block_data = push_block_to_data(list_block_data, &block);
+        update_list_block(&mut bucket.writer, start_list_point + 16, list_block_data).unwrap();
+
+        assert!(Bucket::new(file_path.clone()).is_ok());
+        let error = Bucket::open_strict(file_path.clone())
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_get_many_prefix() {
+        let file_path = String::from("data_get_many_prefix.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for name in ["a/1", "b/1", "a/2", "c/1", "a/3", "b/2"] {
+            bucket
+                .set(name.as_bytes().to_vec(), name.as_bytes().to_vec())
+                .unwrap();
         }
+        let keys = |group: &Vec<(Vec<u8>, Vec<u8>)>| {
+            group
+                .iter()
+                .map(|(k, _)| String::from_utf8(k.clone()).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let groups =
+            bucket.get_many_prefix(vec![b"a/".to_vec(), b"b/".to_vec(), b"d/".to_vec()], 2);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(keys(&groups[0]), vec!["a/1", "a/2"]);
+        assert_eq!(keys(&groups[1]), vec!["b/1", "b/2"]);
+        assert!(groups[2].is_empty());
+        assert_eq!(groups[0][1], (b"a/2".to_vec(), b"a/2".to_vec()));
+
+        // key khớp nhiều prefix nằm trong từng nhóm
+        let groups = bucket.get_many_prefix(vec![b"a".to_vec(), b"a/3".to_vec(), b"".to_vec()], 10);
+        assert_eq!(keys(&groups[0]), vec!["a/1", "a/2", "a/3"]);
+        assert_eq!(keys(&groups[1]), vec!["a/3"]);
+        assert_eq!(groups[2].len(), 6);
+
+        fs::remove_file(file_path).unwrap()
     }
-    result
-}
 
-fn get_find_next_data(
-    read: &mut File,
-    list_block_data: Vec<u8>,
-    key: Vec<u8>,
-    limit: u8,
-    only_after_key: bool,
-) -> Vec<(Vec<u8>, Vec<u8>)> {
-    let mut result: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
-    {
-        let mut block_info = EMPTY_BLOCK;
-        let mut tmp_group: Vec<u8> = Vec::new();
-        let mut current: u8 = 0;
-        let len_current_key = key.len();
-        let sum_current_key: usize = key.iter().map(|&x| x as usize).sum();
-        let sum_current_md5: usize = md5::compute(&key)
-            .to_vec()
-            .iter()
-            .map(|&x| x as usize)
-            .sum();
-        let mut check_is_begin = false;
-        for v in list_block_data {
-            if current >= limit {
-                break;
-            }
-            match v {
-                START => {
-                    block_info.start = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SIZE_KEY => {
-                    block_info.size_key = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SUM_KEY => {
-                    block_info.sum_key = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SUM_MD5 => {
-                    block_info.sum_md5 = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SIZE_DATA => {
-                    block_info.size_data = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                    {
-                        if !check_is_begin
-                            && block_info.size_key == len_current_key
-                            && block_info.sum_key == sum_current_key
-                            && block_info.sum_md5 == sum_current_md5
-                        {
-                            let found_key =
-                                pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
-                            check_is_begin = found_key == key;
-                        }
-                        if check_is_begin {
-                            // success
-                            let (found_key, found_data) = pull_data(read, &block_info)
-                                .unwrap_or_else(|_| (Vec::new(), Vec::new()));
-                            let len_found_key = found_key.len();
-                            if len_found_key == block_info.size_key {
-                                let sum_found_key: usize =
-                                    found_key.iter().map(|&x| x as usize).sum();
-                                if sum_found_key == block_info.sum_key {
-                                    let sum_found_md5: usize = md5::compute(&found_key)
-                                        .to_vec()
-                                        .iter()
-                                        .map(|&x| x as usize)
-                                        .sum();
-                                    if sum_found_md5 == block_info.sum_md5 {
-                                        // success
-                                        if !only_after_key || current > 0 {
-                                            result.push((found_key, found_data));
-                                        }
-                                        current += 1;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    block_info = EMPTY_BLOCK;
-                }
-                END => {
-                    break;
-                }
-                _ => {
-                    tmp_group.push(v);
-                }
-            }
+    #[test]
+    fn test_content_hash() {
+        let file_path = String::from("data_content_hash.db");
+        let other_path = String::from("data_content_hash_other.db");
+        let clone_path = String::from("data_content_hash_clone.db");
+        let _ = fs::remove_file(&file_path);
+        let _ = fs::remove_file(&other_path);
+        let _ = fs::remove_file(&clone_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        let mut other = Bucket::new(other_path.clone()).unwrap();
+        assert_eq!(bucket.content_hash(), other.content_hash());
+        for i in 0..20 {
+            bucket
+                .set(format!("key-{:02}", i).into_bytes(), vec![b'v'; 64])
+                .unwrap();
         }
+        for i in (0..20).step_by(2) {
+            bucket.delete(format!("key-{:02}", i).into_bytes()).unwrap();
+        }
+        // cùng nội dung, thứ tự ghi ngược lại
+        for i in (1..20).rev().step_by(2) {
+            other
+                .set(format!("key-{:02}", i).into_bytes(), vec![b'v'; 64])
+                .unwrap();
+        }
+        let hash = bucket.content_hash();
+        assert_eq!(other.content_hash(), hash);
+        let mut clone = bucket.clone_to(clone_path.clone()).unwrap();
+        assert_eq!(clone.content_hash(), hash);
+        bucket.compact().unwrap();
+        assert_eq!(bucket.content_hash(), hash);
+
+        other.set(b"key-01".to_vec(), vec![b'w'; 64]).unwrap();
+        assert_ne!(other.content_hash(), hash);
+
+        fs::remove_file(file_path).unwrap();
+        fs::remove_file(other_path).unwrap();
+        fs::remove_file(clone_path).unwrap()
     }
-    result
-}
 
-fn delete_to_data(
-    read: &mut File,
-    write: &mut File,
-    start_list_point: usize,
-    list_block_data: Vec<u8>,
-    also_delete_the_found_block: bool,
-    key: Vec<u8>,
-) -> Result<()> {
-    let mut this_found_index: usize = 0;
-    let mut this_found_finish_index: usize = 0;
-    let mut last_found_block_info: Block = EMPTY_BLOCK;
-    {
-        let mut block_control_index_before: usize = 0;
-        let mut block_info = EMPTY_BLOCK;
-        let mut tmp_group: Vec<u8> = Vec::new();
-        let len_current_key = key.len();
-        let sum_current_key: usize = key.iter().map(|&x| x as usize).sum();
-        let sum_current_md5: usize = md5::compute(&key)
-            .to_vec()
-            .iter()
-            .map(|&x| x as usize)
-            .sum();
-        for i in 0..list_block_data.len() {
-            let v = list_block_data[i];
-            match v {
-                START => {
-                    block_info.start = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SIZE_KEY => {
-                    block_info.size_key = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SUM_KEY => {
-                    block_info.sum_key = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SUM_MD5 => {
-                    block_info.sum_md5 = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                }
-                SIZE_DATA => {
-                    block_info.size_data = digits_to_number(&tmp_group);
-                    tmp_group.clear();
-                    {
-                        if block_info.size_key == len_current_key
-                            && block_info.sum_key == sum_current_key
-                            && block_info.sum_md5 == sum_current_md5
-                        {
-                            let found_key =
-                                pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
-                            if found_key == key {
-                                // success
-                                last_found_block_info = block_info;
-                                this_found_index = block_control_index_before + 1;
-                                this_found_finish_index = i + 1;
-                            }
-                        }
-                    }
-                    block_info = EMPTY_BLOCK;
-                    block_control_index_before = i;
-                }
-                END => {
-                    break;
-                }
-                _ => {
-                    tmp_group.push(v);
-                }
+    #[test]
+    fn test_corrupt_files_do_not_panic() {
+        let file_path = String::from("data_corrupt_files.db");
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next_byte = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as u8
+        };
+        for round in 0..200 {
+            let _ = fs::remove_file(&file_path);
+            let size = 100 + next_byte() as usize * 4;
+            let mut raw: Vec<u8> = Vec::new();
+            if round % 2 == 0 {
+                // toàn bộ là byte ngẫu nhiên
+                raw.extend((0..size).map(|_| next_byte()));
+            } else {
+                // header cũ hợp lệ (không có checksum) trỏ tới một list ngẫu nhiên
+                let start_list_point = 128 + next_byte() as usize;
+                let list_size = next_byte() as usize;
+                raw.extend(group_digits_to_vec(start_list_point));
+                raw.push(END);
+                raw.extend(group_digits_to_vec(list_size));
+                raw.push(END);
+                raw.resize(start_list_point, 0);
+                raw.extend((0..list_size).map(|_| {
+                    let b = next_byte();
+                    if b % 4 == 0 { 250 + b % 6 } else { b % 250 }
+                }));
             }
+            fs::write(&file_path, &raw).unwrap();
+
+            let mut bucket = Bucket::new(file_path.clone()).unwrap();
+            let key = b"key".to_vec();
+            let _ = bucket.get(key.clone());
+            let _ = bucket.get_value(key.clone());
+            let _ = bucket.get_many(vec![key.clone(), b"k".to_vec()]);
+            let _ = bucket.list(255);
+            let _ = bucket.list_next(255, 1);
+            let _ = bucket.find_next(key.clone(), 255, false);
+            let _ = bucket.find_next_iter(key.clone(), true).count();
+            let _ = bucket.find_suffix(key.clone(), 255);
+            let _ = bucket.find_value_prefix(key.clone(), 255);
+            let _ = bucket.get_prefix_first(key.clone());
+            let _ = bucket.get_many_prefix(vec![key.clone(), Vec::new()], 255);
+            let _ = bucket.group_by_prefix(b'/');
+            let _ = bucket.snapshot_iter().count();
+            let _ = bucket.check_overlaps();
+            let _ = bucket.list_region();
+            let _ = bucket.compacted_size();
+            let _ = bucket.fragment_count();
+            let _ = bucket.bounds();
+            let _ = bucket.blocks_in_range(0, usize::MAX);
+            let _ = bucket.validate_key(key.clone());
+            let _ = bucket.lookup_cost(key.clone());
+            let _ = bucket.export_key_hashes();
+            let _ = bucket.content_hash();
+            let _ = bucket.format_version();
+            let _ = bucket.needs_migration();
+            let _ = bucket.get_meta("meta");
+            let _ = bucket.read_raw(0, usize::MAX);
+            let _ = Bucket::open_strict(file_path.clone()).map(|_| ());
+            let _ = Bucket::open_cached(file_path.clone()).map(|mut b| b.get(key.clone()));
+            let _ = bucket.set(key.clone(), b"value".to_vec());
+            let _ = bucket.set_many(vec![(b"a".to_vec(), b"1".to_vec())]);
+            let _ = bucket.touch(b"b".to_vec());
+            let _ = bucket.set_meta("meta", b"1");
+            let _ = bucket.delete(key.clone());
+            let _ = bucket.delete_to(b"a".to_vec(), true);
+            let _ = bucket.list_lock_delete(1);
+            let _ = bucket.compact_step(1);
+            let _ = bucket.compact();
         }
-    }
-    if this_found_index == 0
-        || last_found_block_info.start == 0
-        || last_found_block_info.size_key == 0
-        || last_found_block_info.sum_key == 0
-        || last_found_block_info.sum_md5 == 0
-    {
-        return Ok(());
-    }
-    if also_delete_the_found_block {
-        update_list_block(
-            write,
-            start_list_point,
-            list_block_data[this_found_finish_index..].to_vec(),
-        )
-    } else {
+
+        // list trỏ ra ngoài file và block nằm ngoài vùng dữ liệu bị từ chối
+        fs::write(&file_path, []).unwrap();
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        update_list_block(&mut bucket.writer, 128, Vec::new()).unwrap();
+        let mut header = group_digits_to_vec(128);
+        header.push(END);
+        header.extend(group_digits_to_vec(usize::MAX));
+        header.push(END);
+        header.resize(128, 0);
+        bucket.writer.seek(Start(0)).unwrap();
+        bucket.writer.write_all(&header).unwrap();
+        let error = get_list_config(&mut bucket.reader).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+
+        let block = Block {
+            start: usize::MAX - 1,
+            size_key: 3,
+            sum_key: 0,
+            sum_md5: 0,
+            size_data: usize::MAX,
+        };
         update_list_block(
-            write,
-            start_list_point,
-            list_block_data[this_found_index..].to_vec(),
+            &mut bucket.writer,
+            128,
+            push_block_to_data(Vec::new(), &block),
         )
+        .unwrap();
+        let error = get_list_config(&mut bucket.reader).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(bucket.get(b"key".to_vec()), (Vec::new(), Vec::new()));
+        assert_eq!(
+            bucket.read_raw(0, usize::MAX).unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+        // lệnh ghi lỗi vẫn trả lại lock cho handle khác
+        let error = bucket.set(b"key".to_vec(), b"value".to_vec()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let mut other = Bucket::new(file_path.clone()).unwrap();
+        let error = other
+            .set_with_retry(
+                b"key".to_vec(),
+                b"value".to_vec(),
+                0,
+                Duration::from_millis(1),
+            )
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        fs::remove_file(file_path).unwrap()
     }
-}
 
-fn delete_one_data(
-    read: &mut File,
-    write: &mut File,
-    list_block_data: Vec<u8>,
-    key: Vec<u8>,
-    start_list_point: usize,
-) -> Result<()> {
-    let (new_list_block_data, _) = get_new_list_not_contain_key(read, list_block_data, key, false);
-    update_list_block(write, start_list_point, new_list_block_data)
-}
+    #[test]
+    fn test_push() {
+        let file_path = String::from("data_push.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 0..5u64 {
+            assert_eq!(bucket.push(format!("event-{}", i).into_bytes()).unwrap(), i);
+        }
+        let list_item: Vec<(u64, Vec<u8>)> = bucket
+            .list(255)
+            .into_iter()
+            .map(|(k, v)| (decode_u64_key(&k).unwrap(), v))
+            .collect();
+        assert_eq!(
+            list_item,
+            (0..5u64)
+                .map(|i| (i, format!("event-{}", i).into_bytes()))
+                .collect::<Vec<_>>()
+        );
 
-fn set_one_data(
-    read: &mut File,
-    write: &mut File,
-    list_block_data: Vec<u8>,
-    key: Vec<u8>,
-    data: Vec<u8>,
-    start_list_point: usize,
-) -> Result<()> {
-    let (new_list_block_data, new_list_block_info) =
-        get_new_list_not_contain_key(read, list_block_data, key.clone(), true);
+        // số thứ tự không bị dùng lại sau khi xóa hoặc mở lại file
+        bucket.delete(encode_u64_key(4)).unwrap();
+        assert_eq!(bucket.push(b"event-5".to_vec()).unwrap(), 5);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        assert_eq!(bucket.push(b"event-6".to_vec()).unwrap(), 6);
+        assert_eq!(
+            bucket.get_value(encode_u64_key(6)),
+            Some(b"event-6".to_vec())
+        );
+        assert_eq!(bucket.get_meta("push"), Some(encode_u64_key(7)));
+        assert_eq!(bucket.list(255).len(), 6);
 
-    let size_key = key.len();
-    let size_data = data.len();
-    let block_size = size_key + size_data;
-    let sum_key: usize = key.iter().map(|&x| x as usize).sum();
-    let sum_md5: usize = md5::compute(&key)
-        .to_vec()
-        .iter()
-        .map(|&x| x as usize)
-        .sum();
+        // hết số thứ tự: lỗi thay vì tràn số
+        bucket.set_meta("push", &encode_u64_key(u64::MAX)).unwrap();
+        let error = bucket.push(b"event-max".to_vec()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::StorageFull);
+        assert_eq!(bucket.list(255).len(), 6);
 
-    let list_space = get_list_space(start_list_point, new_list_block_info);
-    let (start_list, start_block) = get_perfect_space(list_space, start_list_point, block_size);
-    let info_data = push_block_to_data(
-        Vec::new(),
-        &Block {
-            start: start_block,
-            size_key,
-            sum_key,
-            sum_md5,
-            size_data,
-        },
-    );
+        fs::remove_file(file_path).unwrap()
+    }
 
-    let list_block_data = merge_vec(&[new_list_block_data, info_data]);
-    update_list_block(write, start_list, list_block_data)?;
+    #[test]
+    fn test_open_bounded() {
+        let file_path = String::from("data_open_bounded.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::open_bounded(file_path.clone(), 3).unwrap();
+        for i in 1..6 {
+            bucket
+                .set(
+                    format!("key-00{}", i).into_bytes(),
+                    format!("value-00{}", i).into_bytes(),
+                )
+                .unwrap();
+        }
+        let keys = |bucket: &mut Bucket| {
+            bucket
+                .list(255)
+                .into_iter()
+                .map(|(k, _)| k)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            keys(&mut bucket),
+            vec![
+                b"key-003".to_vec(),
+                b"key-004".to_vec(),
+                b"key-005".to_vec()
+            ]
+        );
+        assert!(bucket.get_value(b"key-001".to_vec()).is_none());
 
-    write.seek(Start(start_block as u64))?;
-    write.write_all(&merge_vec(&[key, data]))
-}
+        // cập nhật key cũ nhất làm nó thành mới nhất
+        bucket.set(b"key-003".to_vec(), b"new".to_vec()).unwrap();
+        bucket
+            .set_many(vec![(b"key-006".to_vec(), b"value-006".to_vec())])
+            .unwrap();
+        assert_eq!(
+            keys(&mut bucket),
+            vec![
+                b"key-005".to_vec(),
+                b"key-003".to_vec(),
+                b"key-006".to_vec()
+            ]
+        );
+        assert_eq!(bucket.get_value(b"key-003".to_vec()), Some(b"new".to_vec()));
+        assert_eq!(bucket.check_overlaps(), Vec::new());
 
-fn set_many_data(
-    read: &mut File,
-    write: &mut File,
-    list_block_data: Vec<u8>,
-    list_data: Vec<(Vec<u8>, Vec<u8>)>,
-    start_list_point: usize,
-) -> Result<()> {
-    let (new_list_block_data, new_list_block_info) =
-        get_new_list_not_contain_list_key(read, list_block_data, &list_data, true);
+        fs::remove_file(file_path).unwrap()
+    }
 
-    let mut list_block_insert_position: Vec<String> = Vec::new();
-    let mut min_size_block: usize = 0;
-    let mut list_config_insert: Vec<Block> = Vec::new();
-    {
-        let mut max_size_block: usize = 0;
-        for i in 0..list_data.len() {
-            let (key, data) = list_data[i].clone();
-            let size_key = key.len();
-            let size_data = data.len();
-            let block_size = size_key + size_data;
-            if min_size_block == 0 || block_size < min_size_block {
-                min_size_block = block_size;
-            }
-            if block_size > max_size_block {
-                max_size_block = block_size;
-            }
-            let sum_key: usize = key.iter().map(|&x| x as usize).sum();
-            let sum_md5: usize = md5::compute(&key)
-                .to_vec()
-                .iter()
-                .map(|&x| x as usize)
-                .sum();
-            list_config_insert.push(Block {
-                start: i,
-                size_key,
-                sum_key,
-                sum_md5,
-                size_data,
-            });
-            list_block_insert_position
-                .push(format!("{}{}{}{}", size_key, sum_key, sum_md5, size_data));
+    #[test]
+    fn test_set_same_value_skips_write() {
+        let file_path = String::from("data_set_same_value.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        bucket
+            .set(b"key-002".to_vec(), b"value-002".to_vec())
+            .unwrap();
+        let raw_before = fs::read(&file_path).unwrap();
+
+        // ghi lại cùng giá trị: file giữ nguyên từng byte (kể cả thứ tự list)
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), raw_before);
+
+        bucket
+            .set(b"key-001".to_vec(), b"value-003".to_vec())
+            .unwrap();
+        assert_ne!(fs::read(&file_path).unwrap(), raw_before);
+        assert_eq!(
+            bucket.get_value(b"key-001".to_vec()),
+            Some(b"value-003".to_vec())
+        );
+
+        // giá trị rỗng trên key chưa có vẫn được ghi
+        bucket.set(b"key-004".to_vec(), Vec::new()).unwrap();
+        assert_eq!(
+            bucket.get(b"key-004".to_vec()),
+            (b"key-004".to_vec(), Vec::new())
+        );
+
+        // kích thước khác trong list: chỉ đọc key, không đọc giá trị cũ
+        let (_, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        let mut read = CountingReader {
+            inner: Cursor::new(fs::read(&file_path).unwrap()),
+            bytes_read: 0,
+        };
+        assert!(!is_value_unchanged(
+            &mut read,
+            &list_block_data,
+            b"key-001",
+            b"longer value"
+        ));
+        assert_eq!(read.bytes_read, 7);
+        assert!(is_value_unchanged(
+            &mut read,
+            &list_block_data,
+            b"key-001",
+            b"value-003"
+        ));
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    /// Counts the bytes read from `inner`.
+    struct CountingReader {
+        inner: Cursor<Vec<u8>>,
+        bytes_read: usize,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.bytes_read += n;
+            Ok(n)
+        }
+    }
+
+    impl Seek for CountingReader {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_bucket_reader() {
+        let file_path = String::from("data_bucket_reader.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 1..4 {
+            bucket
+                .set(
+                    format!("key-00{}", i).into_bytes(),
+                    format!("value-00{}", i).into_bytes(),
+                )
+                .unwrap();
+        }
+        bucket.delete(b"key-002".to_vec()).unwrap();
+        let image = fs::read(&file_path).unwrap();
+
+        let mut reader = BucketReader::new(std::io::Cursor::new(image.clone()));
+        assert_eq!(reader.list(255), bucket.list(255));
+        assert_eq!(
+            reader.get(b"key-001".to_vec()),
+            bucket.get(b"key-001".to_vec())
+        );
+        assert_eq!(
+            reader.get_value(b"key-003".to_vec()),
+            Some(b"value-003".to_vec())
+        );
+        assert!(reader.get_value(b"key-002".to_vec()).is_none());
+        assert_eq!(reader.find_next(b"key-001".to_vec(), 10, true).len(), 1);
+
+        // ảnh file nằm giữa dữ liệu khác: bọc phần con bằng Cursor riêng
+        let mut embedded = vec![0u8; 64];
+        embedded.extend(&image);
+        let mut reader = BucketReader::new(std::io::Cursor::new(&embedded[64..]));
+        assert_eq!(
+            reader.list_next(10, 1),
+            vec![(b"key-003".to_vec(), b"value-003".to_vec())]
+        );
+
+        // dữ liệu hỏng đọc như bucket rỗng
+        let mut reader = BucketReader::new(std::io::Cursor::new(vec![1u8; 10]));
+        assert!(reader.list(255).is_empty());
+        assert_eq!(reader.into_inner().into_inner(), vec![1u8; 10]);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_list_sizes() {
+        let file_path = String::from("data_list_sizes.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 0..6 {
+            bucket
+                .set(format!("key-00{}", i).into_bytes(), vec![b'v'; i * 100])
+                .unwrap();
+        }
+        let list_size = bucket.list_sizes(255);
+        assert_eq!(list_size.len(), 6);
+        for (key, size) in &list_size {
+            assert_eq!(bucket.get(key.clone()).1.len(), *size);
+        }
+        assert_eq!(list_size[5], (b"key-005".to_vec(), 500));
+        assert_eq!(bucket.list_sizes(2).len(), 2);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_open_with_flags() {
+        // O_NOATIME trên Linux
+        const O_NOATIME: i32 = 0o1000000;
+        let file_path = String::from("data_open_with_flags.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::open_with_flags(file_path.clone(), O_NOATIME).unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        assert_eq!(
+            bucket.get_value(b"key-001".to_vec()),
+            Some(b"value-001".to_vec())
+        );
+
+        let mut bucket = Bucket::open_with_flags(file_path.clone(), O_NOATIME).unwrap();
+        assert_eq!(
+            bucket.get_value(b"key-001".to_vec()),
+            Some(b"value-001".to_vec())
+        );
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_repair() {
+        let file_path = String::from("data_repair.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 0..10 {
+            bucket
+                .set(
+                    format!("key-00{}", i).into_bytes(),
+                    format!("value-00{}", i).into_bytes(),
+                )
+                .unwrap();
+        }
+        // ghi đè byte key của 2 block trên đĩa
+        let list_block = bucket.blocks_in_range(0, usize::MAX);
+        for i in [3, 7] {
+            bucket
+                .writer
+                .seek(Start(list_block[i].start as u64))
+                .unwrap();
+            bucket.writer.write_all(b"XXX").unwrap();
+        }
+
+        assert_eq!(bucket.repair().unwrap(), 2);
+        assert_eq!(bucket.list(255).len(), 8);
+        for i in 0..10 {
+            let key = format!("key-00{}", i).into_bytes();
+            let expected = (![3, 7].contains(&i)).then(|| format!("value-00{}", i).into_bytes());
+            assert_eq!(bucket.get_value(key), expected);
+        }
+        assert_eq!(bucket.repair().unwrap(), 0);
+        // chỗ của 2 block bị loại trở thành khoảng trống
+        assert_eq!(bucket.fragment_count(), 2);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_move_to() {
+        let file_path = String::from("data_move_to.db");
+        let other_path = String::from("data_move_to_other.db");
+        let _ = fs::remove_file(&file_path);
+        let _ = fs::remove_file(&other_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        let mut other = Bucket::new(other_path.clone()).unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        bucket
+            .set(b"key-002".to_vec(), b"value-002".to_vec())
+            .unwrap();
+        other.set(b"key-001".to_vec(), b"old".to_vec()).unwrap();
+
+        assert!(bucket.move_to(&mut other, b"key-001".to_vec()).unwrap());
+        assert!(bucket.get_value(b"key-001".to_vec()).is_none());
+        assert_eq!(
+            other.get_value(b"key-001".to_vec()),
+            Some(b"value-001".to_vec())
+        );
+        assert_eq!(bucket.list(255).len(), 1);
+        assert_eq!(other.list(255).len(), 1);
+
+        assert!(!bucket.move_to(&mut other, b"key-003".to_vec()).unwrap());
+        assert!(other.get_value(b"key-003".to_vec()).is_none());
+
+        fs::remove_file(file_path).unwrap();
+        fs::remove_file(other_path).unwrap()
+    }
+
+    #[test]
+    fn test_free_list_summary() {
+        let file_path = String::from("data_free_list_summary.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        assert_eq!(bucket.free_list_summary(), FreeListSummary::default());
+        // block dài 16, 26, 36, 46, 56 byte bắt đầu từ 128
+        for i in 0..5 {
+            bucket
+                .set(format!("key-00{}", i).into_bytes(), vec![b'v'; 9 + i * 10])
+                .unwrap();
         }
+        bucket.delete(b"key-001".to_vec()).unwrap();
+        bucket.delete(b"key-003".to_vec()).unwrap();
+        assert_eq!(
+            bucket.free_list_summary(),
+            FreeListSummary {
+                holes: 2,
+                largest: 46,
+                smallest: 26,
+                total_free: 72,
+            }
+        );
+        // xóa block cuối: chỗ của key-003 và key-004 gộp thành khoảng trống trước list
+        bucket.delete(b"key-004".to_vec()).unwrap();
+        assert_eq!(
+            bucket.free_list_summary(),
+            FreeListSummary {
+                holes: 2,
+                largest: 102,
+                smallest: 26,
+                total_free: 128,
+            }
+        );
 
-        list_config_insert.sort_by(|a, b| {
-            let ka = a.size_key + a.size_data;
-            let kb = b.size_key + b.size_data;
-            kb.cmp(&ka)
-        }); // sort decrement [ max ---> min ]
+        fs::remove_file(file_path).unwrap()
     }
 
-    let mut start_list_block = start_list_point;
-    let mut list_info_data: Vec<u8> = Vec::new();
-    let mut selected: HashMap<usize, bool> = HashMap::new();
-    let mut list_write_data: Vec<(usize, Vec<u8>, Vec<u8>)> = Vec::new();
-    let mut total_last_space_used: usize = 0;
-    let mut map_block_insert: HashMap<String, Block> = HashMap::new();
-
-    {
-        let list_space = get_list_space(start_list_point, new_list_block_info);
-        for s in list_space {
-            let mut this_space_used = 0;
-            if s.size_data < min_size_block && s.sum_key == 0 {
-                continue;
-            }
-            for c in list_config_insert.clone() {
-                if selected.get(&c.start) == Some(&true) {
-                    continue;
-                }
-                let block_size = c.size_key + c.size_data;
-                if s.size_data >= block_size + this_space_used || s.sum_key == 1 {
-                    selected.insert(c.start, true);
-                    {
-                        let start_block = s.start + this_space_used;
-                        let (key, data) = list_data[c.start].clone();
-                        add_to_map_sort(&mut map_block_insert, c, start_block);
-                        list_write_data.push((start_block, key, data));
-                        this_space_used += block_size;
-                    }
-                    if s.sum_key == 1 {
-                        start_list_block = s.start;
-                        total_last_space_used += block_size;
-                    }
-                }
+    #[test]
+    fn test_find_next_many() {
+        let file_path = String::from("data_find_next_many.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 0..10 {
+            bucket
+                .set(
+                    format!("key-00{}", i).into_bytes(),
+                    format!("value-00{}", i).into_bytes(),
+                )
+                .unwrap();
+        }
+        let list_key = vec![
+            b"key-002".to_vec(),
+            b"key-008".to_vec(),
+            b"missing".to_vec(),
+            b"key-003".to_vec(),
+        ];
+        for only_after_key in [false, true] {
+            let windows = bucket.find_next_many(list_key.clone(), 3, only_after_key);
+            assert_eq!(windows.len(), 4);
+            for (key, window) in list_key.iter().zip(&windows) {
+                assert_eq!(window, &bucket.find_next(key.clone(), 3, only_after_key));
             }
         }
+        let windows = bucket.find_next_many(list_key, 3, false);
+        let keys = |window: &Vec<(Vec<u8>, Vec<u8>)>| {
+            window.iter().map(|(k, _)| k.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            keys(&windows[0]),
+            vec![
+                b"key-002".to_vec(),
+                b"key-003".to_vec(),
+                b"key-004".to_vec()
+            ]
+        );
+        assert_eq!(
+            keys(&windows[1]),
+            vec![b"key-008".to_vec(), b"key-009".to_vec()]
+        );
+        assert!(windows[2].is_empty());
+
+        fs::remove_file(file_path).unwrap()
     }
 
-    // nếu những block còn lại không đủ khoảng trống thì xử lý thêm vào cuối
-    for c in list_config_insert.clone() {
-        if selected.get(&c.start) == Some(&true) {
-            continue;
+    #[test]
+    fn test_open_single() {
+        let file_path = String::from("data_open_single.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::open_single(file_path.clone()).unwrap();
+        for i in 0..5 {
+            let key = format!("key-00{}", i).into_bytes();
+            bucket.set(key.clone(), vec![b'v'; i * 10]).unwrap();
+            // ghi xong đọc lại ngay qua cùng một handle
+            assert_eq!(bucket.get_value(key), Some(vec![b'v'; i * 10]));
         }
-        let block_size = c.size_key + c.size_data;
-        let start_block = start_list_block + total_last_space_used;
-        selected.insert(c.start, true);
-        let (key, data) = list_data[c.start].clone();
-        add_to_map_sort(&mut map_block_insert, c, start_block);
-        list_write_data.push((start_block, key, data));
-        total_last_space_used += block_size;
+        bucket.delete(b"key-002".to_vec()).unwrap();
+        bucket.set(b"key-005".to_vec(), vec![b'w'; 15]).unwrap();
+        assert_eq!(bucket.list(255).len(), 5);
+        assert_eq!(bucket.check_overlaps(), Vec::new());
+
+        bucket.compact().unwrap();
+        assert_eq!(bucket.get_value(b"key-005".to_vec()), Some(vec![b'w'; 15]));
+        let mut other = Bucket::new(file_path.clone()).unwrap();
+        assert_eq!(other.list(255), bucket.list(255));
+
+        fs::remove_file(file_path).unwrap()
     }
 
-    for key_sort in list_block_insert_position {
-        match map_block_insert.get(&key_sort) {
-            None => {
-                continue;
-            }
-            Some(block) => {
-                let info_data = push_block_to_data(Vec::new(), &block);
-                list_info_data = merge_vec(&[list_info_data, info_data]);
-            }
+    #[test]
+    fn test_find_duplicates() {
+        let file_path = String::from("data_find_duplicates.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 1..=3 {
+            let key = format!("key-00{}", i).into_bytes();
+            bucket.set(key, b"value-001".to_vec()).unwrap();
         }
-    }
+        assert_eq!(bucket.find_duplicates(), Vec::new());
 
-    let new_list_block_data = merge_vec(&[new_list_block_data, list_info_data]);
-    update_list_block(
-        write,
-        start_list_block + total_last_space_used,
-        new_list_block_data,
-    )?;
+        // chép block của key-003 hai lần, key-001 một lần ra sau và thêm vào list
+        let (mut start_list_point, mut list_block_data) =
+            get_list_config(&mut bucket.reader).unwrap();
+        let list_block = get_list_block_info(&list_block_data);
+        for index in [2, 0, 2] {
+            let mut block = list_block[index].clone();
+            let raw = bucket.read_raw(block.start, 16).unwrap();
+            bucket.writer.seek(Start(start_list_point as u64)).unwrap();
+            bucket.writer.write_all(&raw).unwrap();
+            block.start = start_list_point;
+            list_block_data = push_block_to_data(list_block_data, &block);
+            start_list_point += 16;
+        }
+        update_list_block(&mut bucket.writer, start_list_point, list_block_data).unwrap();
 
-    for (start_block, key, data) in list_write_data {
-        write.seek(Start(start_block as u64))?;
-        write.write_all(&merge_vec(&[key, data]))?;
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        assert_eq!(
+            bucket.find_duplicates(),
+            vec![(b"key-003".to_vec(), 3), (b"key-001".to_vec(), 2)]
+        );
+
+        fs::remove_file(file_path).unwrap()
     }
-    Ok(())
-}
 
-fn add_to_map_sort(map_block_sort: &mut HashMap<String, Block>, c: Block, start_block: usize) {
-    map_block_sort.insert(
-        format!("{}{}{}{}", c.size_key, c.sum_key, c.sum_md5, c.size_data),
-        Block {
-            start: start_block,
-            size_key: c.size_key,
-            sum_key: c.sum_key,
-            sum_md5: c.sum_md5,
-            size_data: c.size_data,
-        },
-    );
-}
+    #[test]
+    fn test_export_import_raw() {
+        let file_path = String::from("data_export_raw.db");
+        let dest_path = String::from("data_import_raw.db");
+        let _ = fs::remove_file(&file_path);
+        let _ = fs::remove_file(&dest_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 0..500 {
+            let key = format!("key-{:03}", i).into_bytes();
+            bucket.set(key, vec![b'v'; i % 37]).unwrap();
+        }
 
-fn get_new_list_not_contain_key(
-    read: &mut File,
-    list_block_data: Vec<u8>,
-    key: Vec<u8>,
-    is_return_list_info: bool,
-) -> (Vec<u8>, Vec<Block>) {
-    let mut new_list_block_data: Vec<u8> = Vec::new();
-    let mut new_list_block_info: Vec<Block> = Vec::new();
+        let mut pipe: Vec<u8> = Vec::new();
+        bucket.export_raw(&mut pipe).unwrap();
+        let mut dest = Bucket::new(dest_path.clone()).unwrap();
+        dest.import_raw(pipe.as_slice()).unwrap();
+        for i in 0..500 {
+            let key = format!("key-{:03}", i).into_bytes();
+            assert_eq!(dest.get_value(key), Some(vec![b'v'; i % 37]));
+        }
+        assert_eq!(dest.content_hash(), bucket.content_hash());
 
-    let len_key = key.len();
-    let sum_key: usize = key.iter().map(|&x| x as usize).sum();
-    let sum_md5: usize = md5::compute(&key)
-        .to_vec()
-        .iter()
-        .map(|&x| x as usize)
-        .sum();
+        // stream bị cắt giữa chừng hoặc sai magic
+        let error = dest.import_raw(&pipe[..pipe.len() - 10]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        let error = dest.import_raw(&b"BBKV\x02"[..]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
 
-    let mut block_info = EMPTY_BLOCK;
-    let mut tmp_group: Vec<u8> = Vec::new();
-    for v in list_block_data {
-        match v {
-            START => {
-                block_info.start = digits_to_number(&tmp_group);
-                tmp_group.clear();
-            }
-            SIZE_KEY => {
-                block_info.size_key = digits_to_number(&tmp_group);
-                tmp_group.clear();
-            }
-            SUM_KEY => {
-                block_info.sum_key = digits_to_number(&tmp_group);
-                tmp_group.clear();
-            }
-            SUM_MD5 => {
-                block_info.sum_md5 = digits_to_number(&tmp_group);
-                tmp_group.clear();
-            }
-            SIZE_DATA => {
-                block_info.size_data = digits_to_number(&tmp_group);
-                tmp_group.clear();
-                {
-                    {
-                        if block_info.size_key == len_key
-                            && block_info.sum_key == sum_key
-                            && block_info.sum_md5 == sum_md5
-                        {
-                            let found_key =
-                                pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
-                            if found_key == key {
-                                // success
-                                continue;
-                            }
-                        }
-                    }
-                    new_list_block_data = push_block_to_data(new_list_block_data, &block_info);
-                    if is_return_list_info {
-                        new_list_block_info.push(block_info);
-                    }
-                }
-                block_info = EMPTY_BLOCK;
-            }
-            END => {
-                break;
-            }
-            _ => {
-                tmp_group.push(v);
-            }
-        }
+        fs::remove_file(file_path).unwrap();
+        fs::remove_file(dest_path).unwrap()
     }
-    (new_list_block_data, new_list_block_info)
-}
 
-fn get_new_list_not_contain_list_key(
-    read: &mut File,
-    list_block_data: Vec<u8>,
-    list_data: &Vec<(Vec<u8>, Vec<u8>)>,
-    is_return_list_info: bool,
-) -> (Vec<u8>, Vec<Block>) {
-    let mut list_skip_check: HashMap<Vec<u8>, bool> = HashMap::new();
+    #[test]
+    fn test_health() {
+        let file_path = String::from("data_health.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        assert!(bucket.health().is_healthy());
+        for i in 1..=4 {
+            let key = format!("key-00{}", i).into_bytes();
+            bucket.set(key, b"value-001".to_vec()).unwrap();
+        }
+        let report = bucket.health();
+        assert!(report.is_healthy());
+        assert_eq!(report.checksum_ok, Some(true));
+        assert_eq!(report.fragmentation, 0.0);
+        assert!(report.header_headroom > 0 && report.header_headroom < 80);
+        bucket.delete(b"key-002".to_vec()).unwrap();
+        assert_eq!(bucket.health().fragmentation, 0.25);
 
-    let mut new_list_block_data: Vec<u8> = Vec::new();
-    let mut new_list_block_info: Vec<Block> = Vec::new();
+        // chép block của key-001 ra sau: trùng key nhưng checksum vẫn đúng
+        let (start_list_point, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        let mut block = get_list_block_info(&list_block_data)[0].clone();
+        let raw = bucket.read_raw(block.start, 16).unwrap();
+        bucket.writer.seek(Start(start_list_point as u64)).unwrap();
+        bucket.writer.write_all(&raw).unwrap();
+        block.start = start_list_point;
+        let list_block_data = push_block_to_data(list_block_data, &block);
+        update_list_block(&mut bucket.writer, start_list_point + 16, list_block_data).unwrap();
+        let report = bucket.health();
+        assert!(report.header_ok);
+        assert_eq!(report.duplicate_keys, 1);
+        assert!(!report.is_healthy());
 
-    let mut block_info = EMPTY_BLOCK;
-    let mut tmp_group: Vec<u8> = Vec::new();
-    for v in list_block_data {
-        match v {
-            START => {
-                block_info.start = digits_to_number(&tmp_group);
-                tmp_group.clear();
-            }
-            SIZE_KEY => {
-                block_info.size_key = digits_to_number(&tmp_group);
-                tmp_group.clear();
-            }
-            SUM_KEY => {
-                block_info.sum_key = digits_to_number(&tmp_group);
-                tmp_group.clear();
-            }
-            SUM_MD5 => {
-                block_info.sum_md5 = digits_to_number(&tmp_group);
-                tmp_group.clear();
-            }
-            SIZE_DATA => {
-                block_info.size_data = digits_to_number(&tmp_group);
-                tmp_group.clear();
-                {
-                    {
-                        let mut is_found_key = false;
-                        for (cur_key, _) in list_data {
-                            if list_skip_check.get(cur_key) == Some(&true) {
-                                continue;
-                            }
-                            let key = cur_key.clone();
-                            let len_key = key.len();
-                            let sum_key: usize = key.iter().map(|&x| x as usize).sum();
-                            let sum_md5: usize = md5::compute(&key)
-                                .to_vec()
-                                .iter()
-                                .map(|&x| x as usize)
-                                .sum();
-                            {
-                                if block_info.size_key == len_key
-                                    && block_info.sum_key == sum_key
-                                    && block_info.sum_md5 == sum_md5
-                                {
-                                    let found_key =
-                                        pull_key(read, &block_info).unwrap_or_else(|_| Vec::new());
-                                    if found_key == key {
-                                        // success
-                                        is_found_key = true;
-                                        list_skip_check.insert(key, true);
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                        if is_found_key {
-                            continue;
-                        }
-                    }
-                    new_list_block_data = push_block_to_data(new_list_block_data, &block_info);
-                    if is_return_list_info {
-                        new_list_block_info.push(block_info);
-                    }
-                }
-                block_info = EMPTY_BLOCK;
+        // sửa một byte trong list: checksum sai, list không tải được
+        let (start_list_point, _) = get_list_config(&mut bucket.reader).unwrap();
+        bucket
+            .writer
+            .seek(Start(start_list_point as u64 + 1))
+            .unwrap();
+        bucket.writer.write_all(&[7]).unwrap();
+        let report = bucket.health();
+        assert_eq!(
+            report,
+            HealthReport {
+                header_ok: false,
+                checksum_ok: Some(false),
+                header_headroom: report.header_headroom,
+                ..HealthReport::default()
             }
-            END => {
-                break;
+        );
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_prefetch_range() {
+        struct ReadLog {
+            inner: std::io::Cursor<Vec<u8>>,
+            list_read: Vec<(u64, usize)>,
+        }
+        impl Read for ReadLog {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let position = self.inner.position();
+                let n = self.inner.read(buf)?;
+                self.list_read.push((position, n));
+                Ok(n)
             }
-            _ => {
-                tmp_group.push(v);
+        }
+        impl Seek for ReadLog {
+            fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+                self.inner.seek(pos)
             }
         }
+
+        let file_path = String::from("data_prefetch_range.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for name in ["b/2", "a/1", "b/1", "c/1", "b/3"] {
+            bucket
+                .set(name.as_bytes().to_vec(), vec![b'v'; 13])
+                .unwrap();
+        }
+        bucket
+            .prefetch_range(b"b/".to_vec(), b"c/".to_vec())
+            .unwrap();
+
+        // block 16 byte: b/2 ở 128, b/1 ở 160, b/3 ở 192
+        let (_, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        let read_plan = get_prefetch_plan(&mut bucket.reader, &list_block_data, b"b/", b"b/3");
+        let list_start: Vec<usize> = read_plan.iter().map(|b| b.start).collect();
+        assert_eq!(list_start, vec![128, 160]);
+
+        let mut read = ReadLog {
+            inner: std::io::Cursor::new(fs::read(&file_path).unwrap()),
+            list_read: Vec::new(),
+        };
+        prefetch_data(&mut read, &read_plan).unwrap();
+        assert_eq!(read.list_read, vec![(128, 16), (160, 16)]);
+
+        fs::remove_file(file_path).unwrap()
     }
-    (new_list_block_data, new_list_block_info)
-}
 
-fn push_block_to_data(list_block_data: Vec<u8>, block_info: &Block) -> Vec<u8> {
-    merge_vec(&[
-        list_block_data,
-        group_digits_to_vec(block_info.start),
-        vec![START],
-        group_digits_to_vec(block_info.size_key),
-        vec![SIZE_KEY],
-        group_digits_to_vec(block_info.sum_key),
-        vec![SUM_KEY],
-        group_digits_to_vec(block_info.sum_md5),
-        vec![SUM_MD5],
-        group_digits_to_vec(block_info.size_data),
-        vec![SIZE_DATA],
-    ])
-}
+    #[test]
+    fn test_rotate() {
+        let file_path = String::from("data_rotate.db");
+        let archive_path = String::from("data_rotate.db.1");
+        let _ = fs::remove_file(&file_path);
+        let _ = fs::remove_file(&archive_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        bucket
+            .set(b"key-002".to_vec(), b"value-002".to_vec())
+            .unwrap();
 
-fn get_list_space(start_list_point: usize, list_block_info: Vec<Block>) -> Vec<Block> {
-    let mut list_start_block: Vec<usize> = Vec::new();
-    let mut map_start_block: HashMap<usize, usize> = HashMap::new();
-    for b in list_block_info {
-        list_start_block.push(b.start);
-        map_start_block.insert(b.start, b.size_key + b.size_data);
+        bucket.rotate(archive_path.clone()).unwrap();
+        assert_eq!(fs::metadata(&file_path).unwrap().len(), 0);
+        assert_eq!(bucket.list(255), Vec::new());
+        let mut archive = Bucket::new(archive_path.clone()).unwrap();
+        assert_eq!(archive.list(255).len(), 2);
+
+        // ghi mới vào file mới, file lưu trữ không đổi
+        bucket
+            .set(b"key-003".to_vec(), b"value-003".to_vec())
+            .unwrap();
+        assert_eq!(
+            Bucket::new(file_path.clone()).unwrap().list(255),
+            vec![(b"key-003".to_vec(), b"value-003".to_vec())]
+        );
+        assert_eq!(archive.get_value(b"key-003".to_vec()), None);
+        assert_eq!(
+            archive.get_value(b"key-001".to_vec()),
+            Some(b"value-001".to_vec())
+        );
+
+        fs::remove_file(file_path).unwrap();
+        fs::remove_file(archive_path).unwrap()
     }
-    list_start_block.sort();
 
-    let mut current_point = FIRST_SIZE;
-    let mut list_space: Vec<Block> = Vec::new();
-    for start in list_start_block {
-        if current_point < start {
-            list_space.push(Block {
-                start: current_point,
-                size_key: 0,
-                sum_key: 0,
-                sum_md5: 0,
-                size_data: start - current_point,
-            });
-        }
-        current_point = start + map_start_block[&start];
+    #[test]
+    fn test_prefix_reclaimable() {
+        let file_path = String::from("data_prefix_reclaimable.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket.set(b"a/1".to_vec(), vec![b'v'; 10]).unwrap();
+        bucket.set(b"b/1".to_vec(), vec![b'v'; 20]).unwrap();
+        bucket.set(b"a/22".to_vec(), vec![b'v'; 30]).unwrap();
+        bucket.set(b"a".to_vec(), vec![b'v'; 40]).unwrap();
+        assert_eq!(
+            bucket.prefix_reclaimable(b"a/".to_vec()),
+            (3 + 10) + (4 + 30)
+        );
+        assert_eq!(bucket.prefix_reclaimable(b"a".to_vec()), 47 + 41);
+        assert_eq!(bucket.prefix_reclaimable(b"c".to_vec()), 0);
+
+        // xoá các key a/ và so với dung lượng trống thực tế
+        let free_before = bucket.free_list_summary().total_free;
+        bucket.delete(b"a/1".to_vec()).unwrap();
+        bucket.delete(b"a/22".to_vec()).unwrap();
+        assert_eq!(bucket.free_list_summary().total_free - free_before, 47);
+
+        fs::remove_file(file_path).unwrap()
     }
-    let last_space_size = start_list_point.saturating_sub(current_point);
-    if last_space_size > 0 {
-        list_space.push(Block {
-            start: current_point,
-            size_key: 0,
-            sum_key: 1, // space này là vị trí còn trống cuối cùng trước list
-            sum_md5: 0,
-            size_data: last_space_size,
-        });
+
+    #[test]
+    fn test_set_at() {
+        let file_path = String::from("data_set_at.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"0123456789".to_vec())
+            .unwrap();
+        bucket
+            .set(b"key-002".to_vec(), b"abcdefghij".to_vec())
+            .unwrap();
+        let before = fs::read(&file_path).unwrap();
+
+        assert!(
+            bucket
+                .set_at(b"key-001".to_vec(), 3, b"xyz".to_vec())
+                .unwrap()
+        );
+        assert_eq!(
+            bucket.get_value(b"key-001".to_vec()),
+            Some(b"012xyz6789".to_vec())
+        );
+        assert_eq!(
+            bucket.get_value(b"key-002".to_vec()),
+            Some(b"abcdefghij".to_vec())
+        );
+        // chỉ 3 byte trong file thay đổi
+        let after = fs::read(&file_path).unwrap();
+        assert_eq!(after.len(), before.len());
+        let list_changed: Vec<usize> = (0..after.len())
+            .filter(|&i| after[i] != before[i])
+            .collect();
+        assert_eq!(list_changed, vec![128 + 7 + 3, 128 + 7 + 4, 128 + 7 + 5]);
+
+        assert!(
+            !bucket
+                .set_at(b"key-003".to_vec(), 0, b"x".to_vec())
+                .unwrap()
+        );
+        let error = bucket
+            .set_at(b"key-001".to_vec(), 8, b"xyz".to_vec())
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(
+            bucket
+                .set_at(b"key-001".to_vec(), 7, b"xyz".to_vec())
+                .unwrap()
+        );
+        assert_eq!(
+            bucket.get_value(b"key-001".to_vec()),
+            Some(b"012xyz6xyz".to_vec())
+        );
+
+        fs::remove_file(file_path).unwrap()
     }
-    list_space
-}
 
-fn get_perfect_space(
-    list_space: Vec<Block>,
-    start_list_point: usize,
-    block_size: usize,
-) -> (usize, usize) {
-    let mut perfect_block_size: usize = block_size;
-    let mut perfect_free_size: usize = 0;
-    let mut start_block = start_list_point;
-    let mut is_last_space: bool = false;
-    for s in list_space {
-        if s.size_data >= block_size || s.sum_key == 1 {
-            let new_size = s.size_data;
-            if perfect_free_size == 0
-                || (perfect_free_size > new_size && start_list_point > s.start + s.size_data)
-            {
-                perfect_free_size = new_size;
-                start_block = s.start;
-                is_last_space = s.sum_key == 1;
+    #[test]
+    fn test_read_your_writes() {
+        let file_path = String::from("data_read_your_writes.db");
+        let open: [fn(String) -> Bucket; 4] = [
+            |path| Bucket::new(path).unwrap(),
+            |path| Bucket::open_cached(path).unwrap(),
+            |path| Bucket::open_single(path).unwrap(),
+            |path| Bucket::new_with_access(path, AccessMode::SingleWriterMultiReader).unwrap(),
+        ];
+        for open_bucket in open {
+            let _ = fs::remove_file(&file_path);
+            let mut bucket = open_bucket(file_path.clone());
+            for i in 0..20 {
+                let key = format!("key-{:03}", i).into_bytes();
+                // set -> get
+                bucket.set(key.clone(), vec![b'v'; i]).unwrap();
+                assert_eq!(bucket.get_value(key.clone()), Some(vec![b'v'; i]));
+                // set -> list
+                assert_eq!(bucket.list(255).len(), i + 1);
+                // ghi đè -> get
+                bucket.set(key.clone(), vec![b'w'; i + 3]).unwrap();
+                assert_eq!(bucket.get(key.clone()), (key, vec![b'w'; i + 3]));
+            }
+            for i in (0..20).step_by(2) {
+                let key = format!("key-{:03}", i).into_bytes();
+                // delete -> get
+                bucket.delete(key.clone()).unwrap();
+                assert_eq!(bucket.get_value(key), None);
             }
+            assert_eq!(bucket.list(255).len(), 10);
+            // set_many -> get_many
+            let list_data: Vec<(Vec<u8>, Vec<u8>)> = (20..25)
+                .map(|i| (format!("key-{:03}", i).into_bytes(), vec![b'x'; i]))
+                .collect();
+            bucket.set_many(list_data.clone()).unwrap();
+            let list_key: Vec<Vec<u8>> = list_data.iter().map(|(k, _)| k.clone()).collect();
+            assert_eq!(bucket.get_many(list_key), list_data);
         }
+
+        fs::remove_file(file_path).unwrap()
     }
-    if perfect_free_size >= perfect_block_size {
-        perfect_block_size = 0
+
+    #[test]
+    fn test_iter_physical() {
+        let file_path = String::from("data_iter_physical.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for name in ["key-001", "key-002", "key-003", "key-004"] {
+            bucket
+                .set(name.as_bytes().to_vec(), b"value-001".to_vec())
+                .unwrap();
+        }
+        // key-005 dùng lại chỗ trống của key-002 nhưng nằm cuối list
+        bucket.delete(b"key-002".to_vec()).unwrap();
+        bucket
+            .set(b"key-005".to_vec(), b"value-001".to_vec())
+            .unwrap();
+
+        let keys = |list: Vec<(Vec<u8>, Vec<u8>)>| {
+            list.into_iter()
+                .map(|(k, _)| String::from_utf8(k).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let list_physical = keys(bucket.iter_physical().collect());
+        assert_eq!(list_physical, ["key-001", "key-005", "key-003", "key-004"]);
+        let mut list_snapshot = keys(bucket.snapshot_iter().collect());
+        assert_eq!(list_snapshot, ["key-001", "key-003", "key-004", "key-005"]);
+
+        // cùng tập key, offset tăng dần
+        list_snapshot.sort();
+        let mut list_sorted = list_physical.clone();
+        list_sorted.sort();
+        assert_eq!(list_sorted, list_snapshot);
+        let (_, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        let mut list_start: Vec<usize> = get_list_block_info(&list_block_data)
+            .iter()
+            .map(|b| b.start)
+            .collect();
+        list_start.sort();
+        assert_eq!(list_start, vec![128, 144, 160, 176]);
+
+        fs::remove_file(file_path).unwrap()
     }
-    let start_list: usize;
-    if is_last_space {
-        start_list = start_block + block_size;
-    } else {
-        start_list = start_list_point + perfect_block_size;
+
+    #[test]
+    fn test_approx_len() {
+        let file_path = String::from("data_approx_len.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        assert_eq!(bucket.approx_len(), 0);
+        bucket.set(b"key-0001".to_vec(), vec![b'v'; 3]).unwrap();
+        bucket.set(b"key-0002".to_vec(), vec![b'v'; 300]).unwrap();
+        // list nhỏ hơn mẫu: kết quả chính xác
+        assert_eq!(bucket.approx_len(), 2);
+
+        for i in 3..1000 {
+            let key = format!("key-{:04}", i).into_bytes();
+            bucket.set(key, vec![b'v'; i % 97]).unwrap();
+        }
+        let (_, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        let exact = get_list_block_info(&list_block_data).len();
+        assert_eq!(exact, 999);
+        let approx = bucket.approx_len();
+        assert!(
+            approx * 10 >= exact * 9 && approx * 10 <= exact * 11,
+            "{}",
+            approx
+        );
+
+        fs::remove_file(file_path).unwrap()
     }
-    (start_list, start_block)
-}
 
-fn get_list_config(read: &mut File) -> Result<(usize, Vec<u8>)> {
-    read.seek(Start(0))?;
-    let mut buffer = vec![0u8; FIRST_SIZE];
-    read.read_exact(&mut buffer)?;
+    #[test]
+    fn test_empty_bucket_reads() {
+        let file_path = String::from("data_empty_bucket_reads.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        let key = b"key-001".to_vec();
 
-    let mut start_list_data = Vec::new();
-    let mut size_list_data = Vec::new();
-    {
-        let mut position_list_check: u8 = 0;
-        for v in buffer {
-            if v == END {
-                position_list_check += 1;
-                continue;
-            }
-            if position_list_check == 0 {
-                start_list_data.push(v)
-            } else if position_list_check == 1 {
-                size_list_data.push(v)
-            } else {
-                break;
-            }
+        // bucket mới, chưa ghi gì: mọi thao tác đọc trả về rỗng / 0
+        assert_eq!(bucket.list(10), Vec::new());
+        assert_eq!(bucket.list_next(10, 3), Vec::new());
+        assert_eq!(bucket.find_next(key.clone(), 10, false), Vec::new());
+        assert_eq!(bucket.find_next(key.clone(), 10, true), Vec::new());
+        assert_eq!(bucket.find_next_iter(key.clone(), false).count(), 0);
+        assert_eq!(
+            bucket.find_next_many(vec![key.clone()], 10, false),
+            vec![Vec::new()]
+        );
+        assert_eq!(bucket.snapshot_iter().count(), 0);
+        assert_eq!(bucket.iter_physical().count(), 0);
+        assert_eq!(bucket.get(key.clone()), (Vec::new(), Vec::new()));
+        assert_eq!(bucket.get_value(key.clone()), None);
+        assert_eq!(
+            bucket.get_many(vec![key.clone()]),
+            vec![(Vec::new(), Vec::new())]
+        );
+        assert_eq!(
+            bucket.get_many_prefix(vec![b"key".to_vec()], 10),
+            vec![Vec::new()]
+        );
+        assert_eq!(bucket.find_value_prefix(b"v".to_vec(), 10), Vec::new());
+        assert_eq!(bucket.group_by_prefix(b'-'), Vec::new());
+        assert_eq!(bucket.list_sizes(10), Vec::new());
+        assert_eq!(bucket.blocks_in_range(0, usize::MAX), Vec::new());
+        assert_eq!(bucket.lookup_cost(key.clone()), 0);
+        assert_eq!(bucket.approx_len(), 0);
+        assert_eq!(bucket.bounds(), None);
+        assert_eq!(bucket.compacted_size(), 129);
+        assert_eq!(bucket.fragment_count(), 0);
+        assert_eq!(bucket.free_list_summary(), FreeListSummary::default());
+        assert_eq!(bucket.check_overlaps(), Vec::new());
+        assert_eq!(bucket.find_duplicates(), Vec::new());
+        assert_eq!(bucket.export_key_hashes(), Vec::<u64>::new());
+        assert_eq!(bucket.prefix_reclaimable(Vec::new()), 0);
+        assert!(bucket.health().is_healthy());
+        assert_eq!(bucket.format_version().unwrap(), 1);
+        assert!(!bucket.needs_migration());
+        // đọc không được ghi gì vào file
+        assert_eq!(fs::metadata(&file_path).unwrap().len(), 0);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_compact_key() {
+        let file_path = String::from("data_compact_key.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for name in ["key-001", "key-002", "key-003", "key-004"] {
+            bucket
+                .set(name.as_bytes().to_vec(), b"value-001".to_vec())
+                .unwrap();
         }
+        bucket.set(b"key-005".to_vec(), b"v5".to_vec()).unwrap();
+        // lỗ 16 byte ở 128 (key-001) và 160 (key-003)
+        bucket.delete(b"key-001".to_vec()).unwrap();
+        bucket.delete(b"key-003".to_vec()).unwrap();
+        assert_eq!(bucket.fragment_count(), 2);
+
+        assert!(bucket.compact_key(b"key-005".to_vec()).unwrap());
+        let (_, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        let block = get_list_block_info(&list_block_data)
+            .into_iter()
+            .find(|b| b.size_data == 2)
+            .unwrap();
+        assert_eq!(block.start, 128);
+        assert_eq!(bucket.read_raw(128, 9).unwrap(), b"key-005v5".to_vec());
+        assert_eq!(bucket.get_value(b"key-005".to_vec()), Some(b"v5".to_vec()));
+        assert_eq!(bucket.check_overlaps(), Vec::new());
+
+        // key-004 chỉ còn lỗ 16 byte ở 160 phía trước
+        assert!(bucket.compact_key(b"key-004".to_vec()).unwrap());
+        assert_eq!(bucket.bounds(), Some((128, 160)));
+        assert_eq!(
+            bucket.get_value(b"key-004".to_vec()),
+            Some(b"value-001".to_vec())
+        );
+        assert!(!bucket.compact_key(b"key-002".to_vec()).unwrap());
+        assert!(!bucket.compact_key(b"key-009".to_vec()).unwrap());
+        assert_eq!(bucket.list(255).len(), 3);
+
+        fs::remove_file(file_path).unwrap()
     }
-    // println!("0 === {:?} {:?}", start_list_data, size_list_data);
 
-    let mut start_list_point = digits_to_number(&start_list_data);
-    let mut list_block_data: Vec<u8>;
-    {
-        if start_list_point < FIRST_SIZE {
-            start_list_point = FIRST_SIZE;
-            list_block_data = Vec::new();
-        } else {
-            read.seek(Start(start_list_point as u64))?;
-            let size_list = digits_to_number(&size_list_data);
-            list_block_data = vec![0u8; size_list];
-            read.read_exact(&mut list_block_data)?;
-            if let Some(pos) = list_block_data.iter().position(|&x| x == END) {
-                list_block_data.truncate(pos);
-            }
+    #[test]
+    fn test_freeze() {
+        let file_path = String::from("data_freeze.db");
+        let frozen_path = String::from("data_freeze.db.frozen");
+        let _ = fs::remove_file(&file_path);
+        let _ = fs::remove_file(&frozen_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        bucket
+            .set(b"key-002".to_vec(), b"value-002".to_vec())
+            .unwrap();
+
+        let mut frozen = bucket.freeze().unwrap();
+        assert!(fs::metadata(&frozen_path).unwrap().permissions().readonly());
+        // sửa bucket gốc sau khi freeze
+        bucket.delete(b"key-001".to_vec()).unwrap();
+        bucket
+            .set(b"key-002".to_vec(), b"value-new".to_vec())
+            .unwrap();
+        bucket
+            .set(b"key-003".to_vec(), b"value-003".to_vec())
+            .unwrap();
+        assert_eq!(
+            frozen.list(255),
+            vec![
+                (b"key-001".to_vec(), b"value-001".to_vec()),
+                (b"key-002".to_vec(), b"value-002".to_vec()),
+            ]
+        );
+
+        // freeze lần nữa thay snapshot cũ, reader cũ vẫn đọc bản cũ
+        let mut frozen_new = bucket.freeze().unwrap();
+        assert_eq!(frozen_new.list(255), bucket.list(255));
+        assert_eq!(frozen.get_value(b"key-003".to_vec()), None);
+        assert_eq!(
+            frozen.get_value(b"key-001".to_vec()),
+            Some(b"value-001".to_vec())
+        );
+
+        fs::remove_file(file_path).unwrap();
+        fs::remove_file(frozen_path).unwrap()
+    }
+
+    #[test]
+    fn test_filter_iter() {
+        let file_path = String::from("data_filter_iter.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 0..20 {
+            let key = format!("key-{:03}", i).into_bytes();
+            bucket.set(key, vec![b'0' + (i % 2) as u8; 5]).unwrap();
         }
+
+        let mut count_read = 0;
+        let list_found: Vec<(Vec<u8>, Vec<u8>)> = bucket
+            .filter_iter(|_, data| {
+                count_read += 1;
+                data[0] == b'1'
+            })
+            .take(3)
+            .collect();
+        let keys: Vec<Vec<u8>> = list_found.into_iter().map(|(k, _)| k).collect();
+        assert_eq!(
+            keys,
+            vec![
+                b"key-001".to_vec(),
+                b"key-003".to_vec(),
+                b"key-005".to_vec()
+            ]
+        );
+        // dừng sau block thứ 6, không đọc hết 20 block
+        assert_eq!(count_read, 6);
+        assert_eq!(bucket.filter_iter(|key, _| key.ends_with(b"9")).count(), 2);
+
+        fs::remove_file(file_path).unwrap()
     }
-    // println!("1 === {:?} {:?}", start_list_point, list_block_data);
 
-    Ok((start_list_point, list_block_data))
-}
+    #[test]
+    fn test_open_capped() {
+        let file_path = String::from("data_open_capped.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for name in ["key-001", "key-002", "key-003"] {
+            bucket
+                .set(name.as_bytes().to_vec(), b"value-001".to_vec())
+                .unwrap();
+        }
+        let max_file_size = fs::metadata(&file_path).unwrap().len();
 
-fn update_list_block(write: &mut File, start: usize, list_block_data: Vec<u8>) -> Result<()> {
-    let first_block_data = merge_vec(&[
-        group_digits_to_vec(start),
-        vec![END],
-        group_digits_to_vec(list_block_data.len()),
-        vec![END],
-    ]);
-    write.seek(Start(start as u64))?;
-    write.write_all(&merge_vec(&[list_block_data, vec![END]]))?;
-    write.seek(Start(0))?;
-    write.write_all(&first_block_data)
-}
+        // file đã đầy: thêm key mới sẽ làm file lớn hơn
+        let mut bucket = Bucket::open_capped(file_path.clone(), max_file_size).unwrap();
+        let raw = fs::read(&file_path).unwrap();
+        let error = bucket
+            .set(b"key-004".to_vec(), b"value-001".to_vec())
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::StorageFull);
+        let error = bucket
+            .set_many(vec![(b"key-004".to_vec(), b"v".to_vec())])
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::StorageFull);
+        assert_eq!(fs::read(&file_path).unwrap(), raw);
+        // push chỉ tăng bộ đếm trong metadata, không ghi block
+        let error = bucket.push(b"v".to_vec()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::StorageFull);
+        assert_eq!(fs::read(&file_path).unwrap()[128..], raw[128..]);
+
+        // ghi đè cùng kích thước và ghi vào chỗ trống vẫn được
+        bucket
+            .set(b"key-001".to_vec(), b"value-new".to_vec())
+            .unwrap();
+        bucket.delete(b"key-002".to_vec()).unwrap();
+        bucket
+            .set(b"key-004".to_vec(), b"value-004".to_vec())
+            .unwrap();
+        assert_eq!(
+            bucket.get_value(b"key-004".to_vec()),
+            Some(b"value-004".to_vec())
+        );
+        assert_eq!(bucket.list(255).len(), 3);
+        assert!(fs::metadata(&file_path).unwrap().len() <= max_file_size);
+
+        fs::remove_file(file_path).unwrap()
+    }
 
-#[cfg(test)]
-mod test_md5 {
     #[test]
-    fn test_md5() {
-        let digest = md5::compute(b"abcdefghijklmnopqrstuvwxyz");
-        assert_eq!(format!("{:x}", digest), "c3fcd3d76192e4007dfb496cca67e13b");
+    fn test_debug_blocks() {
+        let file_path = String::from("data_debug_blocks.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        assert_eq!(bucket.debug_blocks(), "");
+        bucket.set(b"ab".to_vec(), b"value".to_vec()).unwrap();
+        bucket.set(b"\x00\xff".to_vec(), b"v".to_vec()).unwrap();
+        bucket.set(b"xyz".to_vec(), Vec::new()).unwrap();
+
+        let output = bucket.debug_blocks();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            format!(
+                "0: start=128 size_key=2 sum_key=195 sum_md5={} size_data=5 key=6162",
+                md5::compute(b"ab")
+                    .0
+                    .iter()
+                    .map(|&x| x as usize)
+                    .sum::<usize>()
+            )
+        );
+        assert!(lines[1].starts_with("1: start=135 size_key=2 sum_key=255 "));
+        assert!(lines[1].ends_with(" size_data=1 key=00ff"));
+        assert!(lines[2].starts_with("2: start=138 "));
+        assert!(lines[2].ends_with(" size_data=0 key=78797a"));
+
+        // sửa byte key trên đĩa: dòng được đánh dấu
+        bucket.writer.seek(Start(128)).unwrap();
+        bucket.writer.write_all(b"b").unwrap();
+        assert!(
+            bucket
+                .debug_blocks()
+                .lines()
+                .next()
+                .unwrap()
+                .ends_with("key=6262 (does not match sums)")
+        );
+
+        fs::remove_file(file_path).unwrap()
     }
-}
 
-impl Trait for Bucket {
-    fn new(path: String) -> Result<Self> {
-        let reader = match File::open(&path) {
-            Ok(f) => f,
-            Err(_) => {
-                File::create(&path)?;
-                File::open(&path)?
-            }
-        };
-        let writer = OpenOptions::new().write(true).open(&path)?;
+    #[test]
+    fn test_set_at_offset() {
+        let file_path = String::from("data_set_at_offset.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set_at_offset(b"key-001".to_vec(), b"value-001".to_vec(), 200)
+            .unwrap();
+        bucket
+            .set_at_offset(b"key-002".to_vec(), b"value-002".to_vec(), 140)
+            .unwrap();
+        assert_eq!(bucket.bounds(), Some((140, 200)));
+        assert_eq!(
+            bucket.read_raw(200, 16).unwrap(),
+            b"key-001value-001".to_vec()
+        );
+        assert_eq!(
+            bucket.get_value(b"key-002".to_vec()),
+            Some(b"value-002".to_vec())
+        );
+        assert_eq!(bucket.list_region().0, 216);
+
+        // chồng lên block khác hoặc header: lỗi, file không đổi
+        let raw = fs::read(&file_path).unwrap();
+        let error = bucket
+            .set_at_offset(b"key-003".to_vec(), b"value-003".to_vec(), 150)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let error = bucket
+            .set_at_offset(b"key-003".to_vec(), b"value-003".to_vec(), 185)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let error = bucket
+            .set_at_offset(b"key-003".to_vec(), b"v".to_vec(), 100)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert_eq!(fs::read(&file_path).unwrap(), raw);
+
+        // ghi lại key-001 vào chỗ cũ của chính nó, và vào khe 156..200
+        bucket
+            .set_at_offset(b"key-001".to_vec(), b"value-new".to_vec(), 200)
+            .unwrap();
+        bucket
+            .set_at_offset(b"key-003".to_vec(), b"value-003".to_vec(), 184)
+            .unwrap();
+        assert_eq!(bucket.check_overlaps(), Vec::new());
+        assert_eq!(bucket.list(255).len(), 3);
+        assert_eq!(
+            bucket.get_value(b"key-001".to_vec()),
+            Some(b"value-new".to_vec())
+        );
+
+        // vị trí làm tràn số: lỗi thay vì panic
+        let error = bucket
+            .set_at_offset(b"key-004".to_vec(), b"v".to_vec(), usize::MAX - 4)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+        // chồng lấn có sẵn giữa hai block khác không chặn việc ghi ở chỗ khác
+        let (start_list_point, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        let mut list_block_info = get_list_block_info(&list_block_data);
+        list_block_info[0].start = list_block_info[1].start;
+        let list_block_data = list_block_info.iter().fold(Vec::new(), push_block_to_data);
+        update_list_block(&mut bucket.writer, start_list_point, list_block_data).unwrap();
+        assert!(!bucket.check_overlaps().is_empty());
+        bucket
+            .set_at_offset(b"key-004".to_vec(), b"value-004".to_vec(), 1000)
+            .unwrap();
+        assert_eq!(
+            bucket.get_value(b"key-004".to_vec()),
+            Some(b"value-004".to_vec())
+        );
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_count_by_key_len() {
+        let file_path = String::from("data_count_by_key_len.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for name in ["a", "bb", "cc", "ddd", "eee", "fff", "gg"] {
+            bucket
+                .set(name.as_bytes().to_vec(), b"value".to_vec())
+                .unwrap();
+        }
+        bucket.delete(b"eee".to_vec()).unwrap();
+        let counts: Vec<usize> = (0..5).map(|len| bucket.count_by_key_len(len)).collect();
+        assert_eq!(counts, vec![0, 1, 3, 2, 0]);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_set_many_same_key() {
+        let file_path = String::from("data_set_many_same_key.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set_many(vec![
+                (b"key-001".to_vec(), b"v1".to_vec()),
+                (b"key-002".to_vec(), b"value-002".to_vec()),
+                (b"key-001".to_vec(), b"v2".to_vec()),
+            ])
+            .unwrap();
+        // mỗi key đúng một block, key-001 giữ giá trị cuối
+        assert_eq!(bucket.count_by_key_len(7), 2);
+        assert_eq!(bucket.get_value(b"key-001".to_vec()), Some(b"v2".to_vec()));
+        assert_eq!(bucket.find_duplicates(), Vec::new());
+        assert_eq!(
+            bucket.compacted_size(),
+            fs::metadata(&file_path).unwrap().len() as usize
+        );
+
+        // "aje" và "jae" có cùng size_key, sum_key và sum_md5
+        bucket
+            .set_many(vec![
+                (b"aje".to_vec(), b"value-1".to_vec()),
+                (b"jae".to_vec(), b"value-2".to_vec()),
+            ])
+            .unwrap();
+        assert_eq!(bucket.get_value(b"aje".to_vec()), Some(b"value-1".to_vec()));
+        assert_eq!(bucket.get_value(b"jae".to_vec()), Some(b"value-2".to_vec()));
+        assert_eq!(bucket.check_overlaps(), Vec::new());
+        assert_eq!(bucket.list(255).len(), 4);
+
+        fs::remove_file(file_path).unwrap()
+    }
+
+    #[test]
+    fn test_reserve_value() {
+        let file_path = String::from("data_reserve_value.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket.set(b"key-big".to_vec(), b"old".to_vec()).unwrap();
+        let size = 1 << 20;
+        let handle = bucket.reserve_value(b"key-big".to_vec(), size).unwrap();
+        assert_eq!(handle.size(), size);
+
+        // ghi xen kẽ với set khác: vùng đã đặt không bị cấp lại
+        for (i, offset) in (0..size).step_by(64 * 1024).enumerate() {
+            let chunk = vec![i as u8; 64 * 1024];
+            bucket.write_value(&handle, offset, &chunk).unwrap();
+            bucket
+                .set(format!("key-{:03}", i).into_bytes(), vec![b'x'; 100])
+                .unwrap();
+        }
+        let error = bucket.write_value(&handle, size - 1, b"xy").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        // chưa finalize: vẫn đọc giá trị cũ
+        assert_eq!(bucket.get_value(b"key-big".to_vec()), Some(b"old".to_vec()));
+        assert_eq!(bucket.list(255).len(), 17);
 
-        Ok(Self { reader, writer })
-    }
+        bucket.finalize_value(handle.clone()).unwrap();
+        let value = bucket.get_value(b"key-big".to_vec()).unwrap();
+        assert_eq!(value.len(), size);
+        for (i, chunk) in value.chunks(64 * 1024).enumerate() {
+            assert!(chunk.iter().all(|&x| x == i as u8));
+        }
+        assert_eq!(bucket.list(255).len(), 17);
+        assert_eq!(bucket.check_overlaps(), Vec::new());
+        assert_eq!(bucket.find_duplicates(), Vec::new());
+        let error = bucket.finalize_value(handle).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
 
-    fn set(&mut self, key: Vec<u8>, data: Vec<u8>) -> Result<()> {
-        self.writer.lock()?;
-        let (start_list_point, list_block_data) =
-            get_list_config(&mut self.reader).unwrap_or_else(|_| (FIRST_SIZE, Vec::new()));
-        set_one_data(
-            &mut self.reader,
-            &mut self.writer,
-            list_block_data,
-            key,
-            data,
-            start_list_point,
-        )?;
-        self.writer.unlock()
-    }
+        // trim_to_last không đếm và không bỏ chỗ giữ
+        let handle = bucket.reserve_value(b"key-new".to_vec(), 4).unwrap();
+        bucket.write_value(&handle, 0, b"data").unwrap();
+        assert_eq!(bucket.trim_to_last(2).unwrap(), 15);
+        assert_eq!(bucket.list(255).len(), 2);
+        bucket.finalize_value(handle).unwrap();
+        assert_eq!(
+            bucket.get_value(b"key-new".to_vec()),
+            Some(b"data".to_vec())
+        );
+        // delete_to bỏ cả chỗ giữ
+        let handle = bucket.reserve_value(b"key-next".to_vec(), 4).unwrap();
+        bucket.set(b"key-last".to_vec(), b"last".to_vec()).unwrap();
+        bucket.delete_to(b"key-last".to_vec(), false).unwrap();
+        let error = bucket.finalize_value(handle).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
 
-    fn get(&mut self, key: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
-        let (_, list_block_data) =
-            get_list_config(&mut self.reader).unwrap_or_else(|_| (FIRST_SIZE, Vec::new()));
-        get_one_data(&mut self.reader, list_block_data, key)
+        fs::remove_file(file_path).unwrap()
     }
 
-    fn delete(&mut self, key: Vec<u8>) -> Result<()> {
-        self.writer.lock()?;
-        let (start_list_point, list_block_data) =
-            get_list_config(&mut self.reader).unwrap_or_else(|_| (FIRST_SIZE, Vec::new()));
-        delete_one_data(
-            &mut self.reader,
-            &mut self.writer,
-            list_block_data,
-            key,
-            start_list_point,
-        )?;
-        self.writer.unlock()
+    #[test]
+    fn test_for_each_batch() {
+        let file_path = String::from("data_for_each_batch.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .bulk_load(
+                (0..10_000).map(|i| (format!("key-{:05}", i).into_bytes(), vec![b'v'; i % 50])),
+            )
+            .unwrap();
+
+        let mut list_batch_len: Vec<usize> = Vec::new();
+        let mut total = 0;
+        bucket.for_each_batch(100, |batch| {
+            list_batch_len.push(batch.len());
+            total += batch.iter().map(|(_, v)| v.len()).sum::<usize>();
+        });
+        assert_eq!(list_batch_len, vec![100; 100]);
+        let expected: usize = bucket.snapshot_iter().map(|(_, v)| v.len()).sum();
+        assert_eq!(total, expected);
+
+        // batch cuối không đủ kích thước
+        let mut list_batch_len: Vec<usize> = Vec::new();
+        bucket.for_each_batch(3_000, |batch| list_batch_len.push(batch.len()));
+        assert_eq!(list_batch_len, vec![3_000, 3_000, 3_000, 1_000]);
+
+        fs::remove_file(file_path).unwrap()
     }
 
-    fn set_many(&mut self, list_data: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
-        self.writer.lock()?;
-        let (start_list_point, list_block_data) =
-            get_list_config(&mut self.reader).unwrap_or_else(|_| (FIRST_SIZE, Vec::new()));
-        set_many_data(
-            &mut self.reader,
-            &mut self.writer,
-            list_block_data,
-            list_data,
-            start_list_point,
-        )?;
-        self.writer.unlock()
+    #[test]
+    fn test_rank() {
+        let file_path = String::from("data_rank.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 0..10 {
+            let key = format!("key-{:03}", i).into_bytes();
+            bucket.set(key, b"value".to_vec()).unwrap();
+        }
+        for i in 0..10 {
+            let key = format!("key-{:03}", i).into_bytes();
+            assert_eq!(bucket.rank(key.clone()), Some(i));
+            assert_eq!(bucket.list_next(1, i)[0].0, key);
+        }
+        assert_eq!(bucket.rank(b"key-999".to_vec()), None);
+
+        // xoá key-002: các key sau lùi một vị trí; cập nhật key-000 đưa nó về cuối list
+        bucket.delete(b"key-002".to_vec()).unwrap();
+        bucket
+            .set(b"key-000".to_vec(), b"value-new".to_vec())
+            .unwrap();
+        assert_eq!(bucket.rank(b"key-001".to_vec()), Some(0));
+        assert_eq!(bucket.rank(b"key-005".to_vec()), Some(3));
+        assert_eq!(bucket.rank(b"key-000".to_vec()), Some(8));
+        assert_eq!(bucket.rank(b"key-002".to_vec()), None);
+
+        fs::remove_file(file_path).unwrap()
     }
 
-    fn list(&mut self, limit: u8) -> Vec<(Vec<u8>, Vec<u8>)> {
-        let (_, list_block_data) =
-            get_list_config(&mut self.reader).unwrap_or_else(|_| (FIRST_SIZE, Vec::new()));
-        get_list_data(&mut self.reader, list_block_data, limit)
+    /// Serves `bad` for the first `bad_loads` block list loads, then `good`.
+    struct FlakyReader {
+        good: Cursor<Vec<u8>>,
+        bad: Cursor<Vec<u8>>,
+        bad_loads: u32,
+        loads: u32,
     }
 
-    fn list_next(&mut self, limit: u8, skip: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
-        let (_, list_block_data) =
-            get_list_config(&mut self.reader).unwrap_or_else(|_| (FIRST_SIZE, Vec::new()));
-        get_list_next_data(&mut self.reader, list_block_data, limit, skip)
+    impl FlakyReader {
+        fn current(&mut self) -> &mut Cursor<Vec<u8>> {
+            if self.loads <= self.bad_loads {
+                &mut self.bad
+            } else {
+                &mut self.good
+            }
+        }
     }
 
-    fn find_next(
-        &mut self,
-        key: Vec<u8>,
-        limit: u8,
-        only_after_key: bool,
-    ) -> Vec<(Vec<u8>, Vec<u8>)> {
-        let (_, list_block_data) =
-            get_list_config(&mut self.reader).unwrap_or_else(|_| (FIRST_SIZE, Vec::new()));
-        get_find_next_data(
-            &mut self.reader,
-            list_block_data,
-            key,
-            limit,
-            only_after_key,
-        )
+    impl Read for FlakyReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.current().read(buf)
+        }
     }
 
-    fn delete_to(&mut self, key: Vec<u8>, also_delete_the_found_block: bool) -> Result<()> {
-        self.writer.lock()?;
-        let (start_list_point, list_block_data) =
-            get_list_config(&mut self.reader).unwrap_or_else(|_| (FIRST_SIZE, Vec::new()));
-        delete_to_data(
-            &mut self.reader,
-            &mut self.writer,
-            start_list_point,
-            list_block_data,
-            also_delete_the_found_block,
-            key,
-        )?;
-        self.writer.unlock()
+    impl Seek for FlakyReader {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            // mỗi lần tải list bắt đầu bằng seek tới cuối file
+            if pos == SeekFrom::End(0) {
+                self.loads += 1;
+            }
+            self.current().seek(pos)
+        }
     }
 
-    fn list_lock_delete(&mut self, limit: u8) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
-        self.reader.lock()?;
-        let (start_list_point, list_block_data) =
-            get_list_config(&mut self.reader).unwrap_or_else(|_| (FIRST_SIZE, Vec::new()));
-        let result = get_list_lock_delete_data(
-            &mut self.reader,
-            &mut self.writer,
-            start_list_point,
-            list_block_data,
-            limit,
+    #[test]
+    fn test_get_consistent() {
+        let file_path = String::from("data_get_consistent.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .set(b"key-001".to_vec(), b"value-001".to_vec())
+            .unwrap();
+        bucket
+            .set(b"key-002".to_vec(), b"value-002".to_vec())
+            .unwrap();
+        assert_eq!(
+            bucket.get_consistent(b"key-002".to_vec()).unwrap(),
+            Some((b"key-002".to_vec(), b"value-002".to_vec()))
         );
-        self.reader.unlock()?;
-        result
-    }
-}
+        assert_eq!(bucket.get_consistent(b"key-003".to_vec()).unwrap(), None);
 
-#[cfg(test)]
-mod tests {
-    use crate::{Bucket, Trait};
-    use std::fs;
+        // giả lập list đang ghi dở: sửa một byte trong list
+        let good = fs::read(&file_path).unwrap();
+        let (start_list_point, _) = get_list_config(&mut bucket.reader).unwrap();
+        let position = start_list_point
+            + good[start_list_point..]
+                .iter()
+                .position(|&x| x < 200)
+                .unwrap();
+        let mut bad = good.clone();
+        bad[position] ^= 1;
+        let flaky = |bad: &Vec<u8>, bad_loads: u32| FlakyReader {
+            good: Cursor::new(good.clone()),
+            bad: Cursor::new(bad.clone()),
+            bad_loads,
+            loads: 0,
+        };
 
-    #[test]
-    fn test_all() {
-        set_data();
-        get_data();
-        delete_data();
-        set_many_data();
-        list_data();
-        list_next_data();
-        find_next_data();
-        delete_to_data();
-        get_list_and_delete_list_data();
-        delete_bucket();
+        // list được ghi xong sau 3 lần đọc
+        let mut read = flaky(&bad, 3);
+        assert_eq!(
+            get_consistent_data(&mut read, b"key-002".to_vec(), Duration::ZERO).unwrap(),
+            Some((b"key-002".to_vec(), b"value-002".to_vec()))
+        );
+        assert_eq!(read.loads, 4);
+
+        // list sai checksum mãi: bỏ cuộc sau số lần thử và trả lỗi
+        let mut read = flaky(&bad, u32::MAX);
+        let error =
+            get_consistent_data(&mut read, b"key-002".to_vec(), Duration::ZERO).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(read.loads, 11);
+
+        // lỗi khác checksum (file cụt) không được thử lại
+        let mut read = flaky(&good[..64].to_vec(), u32::MAX);
+        let error =
+            get_consistent_data(&mut read, b"key-002".to_vec(), Duration::ZERO).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(read.loads, 1);
+
+        fs::remove_file(file_path).unwrap()
     }
 
-    fn set_data() {
-        let file_path = String::from("data.db");
-        let mut bucket = Bucket::new(file_path).unwrap();
+    #[test]
+    fn test_diff() {
+        let file_path = String::from("data_diff.db");
+        let other_path = String::from("data_diff_other.db");
+        let _ = fs::remove_file(&file_path);
+        let _ = fs::remove_file(&other_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        let mut other = Bucket::new(other_path.clone()).unwrap();
+        for (key, value) in [
+            ("same", "v1"),
+            ("only-a", "v2"),
+            ("size", "v3"),
+            ("bytes", "v4"),
+        ] {
+            bucket
+                .set(key.as_bytes().to_vec(), value.as_bytes().to_vec())
+                .unwrap();
+        }
+        for (key, value) in [
+            ("bytes", "v5"),
+            ("only-b", "v6"),
+            ("size", "v3-long"),
+            ("same", "v1"),
+        ] {
+            other
+                .set(key.as_bytes().to_vec(), value.as_bytes().to_vec())
+                .unwrap();
+        }
 
-        let test_key: Vec<u8> = String::from("test-key-001-99999999999999").into_bytes();
-        let test_value: Vec<u8> = String::from("test data value: 0123456789 abcdefgh").into_bytes();
-        let error = bucket.set(test_key, test_value).is_err();
+        assert_eq!(
+            bucket.diff(&mut other),
+            vec![
+                DiffEntry::OnlyInSelf(b"only-a".to_vec()),
+                DiffEntry::ValueDiffers(b"size".to_vec()),
+                DiffEntry::ValueDiffers(b"bytes".to_vec()),
+                DiffEntry::OnlyInOther(b"only-b".to_vec()),
+            ]
+        );
+        assert_eq!(
+            other.diff(&mut bucket),
+            vec![
+                DiffEntry::ValueDiffers(b"bytes".to_vec()),
+                DiffEntry::OnlyInSelf(b"only-b".to_vec()),
+                DiffEntry::ValueDiffers(b"size".to_vec()),
+                DiffEntry::OnlyInOther(b"only-a".to_vec()),
+            ]
+        );
+        let mut copy = bucket.clone_to(String::from("data_diff_copy.db")).unwrap();
+        assert_eq!(bucket.diff(&mut copy), Vec::new());
 
-        assert_eq!(error, false);
+        fs::remove_file(file_path).unwrap();
+        fs::remove_file(other_path).unwrap();
+        fs::remove_file("data_diff_copy.db").unwrap()
     }
 
-    fn get_data() {
-        let file_path = String::from("data.db");
-        let mut bucket = Bucket::new(file_path).unwrap();
+    #[test]
+    fn test_trim_to_last() {
+        let file_path = String::from("data_trim_to_last.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 0..100u64 {
+            assert_eq!(bucket.push(i.to_le_bytes().to_vec()).unwrap(), i);
+        }
+        assert_eq!(bucket.trim_to_last(10).unwrap(), 90);
+        let list_seq: Vec<u64> = bucket
+            .list(255)
+            .iter()
+            .map(|(k, _)| decode_u64_key(k).unwrap())
+            .collect();
+        assert_eq!(list_seq, (90..100).collect::<Vec<u64>>());
+        assert_eq!(bucket.trim_to_last(10).unwrap(), 0);
+        // số thứ tự không bị dùng lại sau khi trim
+        assert_eq!(bucket.push(Vec::new()).unwrap(), 100);
+        assert_eq!(bucket.trim_to_last(0).unwrap(), 11);
+        assert_eq!(bucket.list(255), Vec::new());
 
-        let test_key: Vec<u8> = String::from("test-key-001-99999999999999").into_bytes();
-        let test_value: Vec<u8> = String::from("test data value: 0123456789 abcdefgh").into_bytes();
-        let (key_block, value_block) = bucket.get(test_key.clone());
+        // block có key hỏng không được tính vào n
+        for i in 101..106u64 {
+            assert_eq!(bucket.push(Vec::new()).unwrap(), i);
+        }
+        let (_, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        let newest = get_list_block_info(&list_block_data).pop().unwrap();
+        bucket.writer.seek(Start(newest.start as u64)).unwrap();
+        bucket.writer.write_all(&[0xFF]).unwrap();
+        assert_eq!(bucket.trim_to_last(2).unwrap(), 2);
+        let list_seq: Vec<u64> = bucket
+            .list(255)
+            .iter()
+            .map(|(k, _)| decode_u64_key(k).unwrap())
+            .collect();
+        assert_eq!(list_seq, vec![103, 104]);
+        assert_eq!(bucket.trim_to_last(2).unwrap(), 0);
 
-        assert_eq!(test_key, key_block);
-        assert_eq!(test_value, value_block);
+        fs::remove_file(file_path).unwrap()
     }
 
-    fn delete_data() {
-        let file_path = String::from("data.db");
-        let mut bucket = Bucket::new(file_path).unwrap();
+    #[test]
+    fn test_iter_decoded() {
+        let file_path = String::from("data_iter_decoded.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 1..=10u32 {
+            let key = format!("key-{:03}", i).into_bytes();
+            bucket.set(key, i.to_le_bytes().to_vec()).unwrap();
+        }
+        bucket.set(b"bad".to_vec(), b"x".to_vec()).unwrap();
 
-        let test_key: Vec<u8> = String::from("test-key-001-99999999999999").into_bytes();
-        let error = bucket.delete(test_key).is_err();
+        let decode = |data: &[u8]| data.try_into().map(u32::from_le_bytes).ok();
+        let total: u32 = bucket.iter_decoded(decode).filter_map(|(_, v)| v).sum();
+        assert_eq!(total, 55);
+        let list_bad: Vec<Vec<u8>> = bucket
+            .iter_decoded(decode)
+            .filter(|(_, v)| v.is_none())
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(list_bad, vec![b"bad".to_vec()]);
 
-        assert_eq!(error, false);
+        fs::remove_file(file_path).unwrap()
     }
 
-    fn set_many_data() {
-        let file_path = String::from("data.db");
-        let mut bucket = Bucket::new(file_path).unwrap();
+    #[test]
+    fn test_open_auto_reopen() {
+        let file_path = String::from("data_open_auto_reopen.db");
+        let other_path = String::from("data_open_auto_reopen_other.db");
+        let _ = fs::remove_file(&file_path);
+        let _ = fs::remove_file(&other_path);
+        let mut bucket = Bucket::open_auto_reopen(file_path.clone()).unwrap();
+        bucket.set(b"key-001".to_vec(), b"old".to_vec()).unwrap();
 
-        // let test_key: Vec<u8> = String::from("test-key-001-99999999999999").into_bytes();
-        let test_value: Vec<u8> = String::from("test data value: 0123456789 abcdefgh").into_bytes();
+        let mut other = Bucket::new(other_path.clone()).unwrap();
+        other.set(b"key-001".to_vec(), b"new".to_vec()).unwrap();
+        fs::rename(&other_path, &file_path).unwrap();
+        #[cfg(unix)]
+        {
+            assert_eq!(bucket.get_value(b"key-001".to_vec()), Some(b"new".to_vec()));
+            bucket.set(b"key-002".to_vec(), b"new".to_vec()).unwrap();
+            assert_eq!(other.get_value(b"key-002".to_vec()), Some(b"new".to_vec()));
 
-        let mut list_data: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
-        for i in 0..10 {
-            let test_key = format!("test-key-00{}-99999999999999", i).into_bytes();
-            list_data.push((test_key, test_value.clone()));
+            // list_lock_delete cũng phải làm việc trên file mới
+            let mut newest = Bucket::new(other_path.clone()).unwrap();
+            newest.set(b"key-003".to_vec(), b"newest".to_vec()).unwrap();
+            fs::rename(&other_path, &file_path).unwrap();
+            assert_eq!(
+                bucket.list_lock_delete(10).unwrap(),
+                vec![(b"key-003".to_vec(), b"newest".to_vec())]
+            );
+            assert!(newest.list(10).is_empty());
         }
 
-        let error = bucket.set_many(list_data).is_err();
+        fs::remove_file(file_path).unwrap()
+    }
 
-        assert_eq!(error, false);
+    #[test]
+    fn test_open_lookup_cache() {
+        let file_path = String::from("data_open_lookup_cache.db");
+        let _ = fs::remove_file(&file_path);
+        let error = Bucket::open_lookup_cache(file_path.clone(), 0)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let mut bucket = Bucket::open_lookup_cache(file_path.clone(), 2).unwrap();
+        for i in 0..5 {
+            let key = format!("key-{:03}", i).into_bytes();
+            bucket.set(key, b"value".to_vec()).unwrap();
+        }
+        assert_eq!(bucket.get(b"key-001".to_vec()).1, b"value".to_vec());
+        assert_eq!(bucket.get(b"key-002".to_vec()).1, b"value".to_vec());
+        assert_eq!(bucket.get(b"key-001".to_vec()).1, b"value".to_vec());
+        assert_eq!(bucket.get(b"key-003".to_vec()).1, b"value".to_vec());
+        // key-002 ít dùng nhất nên bị bỏ
+        let lookup_cache = bucket.lookup_cache.as_ref().unwrap();
+        assert!(
+            lookup_cache
+                .map_key_block
+                .contains_key(b"key-001".as_slice())
+        );
+        assert!(
+            !lookup_cache
+                .map_key_block
+                .contains_key(b"key-002".as_slice())
+        );
+
+        // ghi qua bucket này làm rỗng cache
+        bucket.set(b"key-001".to_vec(), b"new".to_vec()).unwrap();
+        assert!(
+            bucket
+                .lookup_cache
+                .as_ref()
+                .unwrap()
+                .map_key_block
+                .is_empty()
+        );
+        assert_eq!(bucket.get(b"key-001".to_vec()).1, b"new".to_vec());
+        bucket.delete(b"key-003".to_vec()).unwrap();
+        assert!(bucket.get(b"key-003".to_vec()).0.is_empty());
+
+        // hỏng list trên đĩa: key trong cache vẫn đọc được mà không cần đọc list
+        assert_eq!(bucket.get(b"key-004".to_vec()).1, b"value".to_vec());
+        assert_eq!(bucket.get(b"key-001".to_vec()).1, b"new".to_vec());
+        let (start_list_point, _) = get_list_config(&mut bucket.reader).unwrap();
+        let mut file = OpenOptions::new().write(true).open(&file_path).unwrap();
+        file.seek(Start(start_list_point as u64)).unwrap();
+        file.write_all(&[END]).unwrap();
+        assert_eq!(bucket.get(b"key-004".to_vec()).1, b"value".to_vec());
+        assert_eq!(bucket.get(b"key-001".to_vec()).1, b"new".to_vec());
+        assert!(bucket.get(b"key-000".to_vec()).0.is_empty());
+
+        fs::remove_file(file_path).unwrap()
     }
 
-    fn list_data() {
-        let file_path = String::from("data.db");
-        let mut bucket = Bucket::new(file_path).unwrap();
+    #[test]
+    fn test_open_size_classes() {
+        let file_path = String::from("data_open_size_classes.db");
+        let _ = fs::remove_file(&file_path);
+        let layout = |bucket: &mut Bucket| {
+            // lỗ 60 byte ở 128 và lỗ 40 byte ở 198, cùng lớp 32..64
+            bucket.set(b"key-a".to_vec(), vec![b'a'; 55]).unwrap();
+            bucket.set(b"key-b".to_vec(), vec![b'b'; 5]).unwrap();
+            bucket.set(b"key-c".to_vec(), vec![b'c'; 35]).unwrap();
+            bucket.set(b"key-d".to_vec(), vec![b'd'; 5]).unwrap();
+            bucket.delete(b"key-a".to_vec()).unwrap();
+            bucket.delete(b"key-c".to_vec()).unwrap();
+            bucket.set(b"key-e".to_vec(), vec![b'e'; 30]).unwrap();
+            let (_, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+            get_list_block_info(&list_block_data)
+                .into_iter()
+                .find(|b| b.size_data == 30)
+                .unwrap()
+                .start
+        };
 
-        let limit = 10u8;
-        let list_block = bucket.list(limit);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        assert_eq!(layout(&mut bucket), 198);
+        fs::remove_file(&file_path).unwrap();
 
-        assert_eq!(list_block.len() > 0, true);
+        let mut bucket = Bucket::open_size_classes(file_path.clone()).unwrap();
+        assert_eq!(layout(&mut bucket), 128);
+        assert_eq!(bucket.get_value(b"key-e".to_vec()), Some(vec![b'e'; 30]));
+        assert_eq!(bucket.get_value(b"key-d".to_vec()), Some(vec![b'd'; 5]));
+        assert!(bucket.check_overlaps().is_empty());
+
+        // không lỗ nào vừa: ghi vào khoảng trống trước list
+        bucket.set(b"key-f".to_vec(), vec![b'f'; 100]).unwrap();
+        assert_eq!(bucket.get_value(b"key-f".to_vec()), Some(vec![b'f'; 100]));
+        assert!(bucket.check_overlaps().is_empty());
+
+        fs::remove_file(file_path).unwrap()
     }
 
-    fn list_next_data() {
-        let file_path = String::from("data.db");
-        let mut bucket = Bucket::new(file_path).unwrap();
+    #[test]
+    fn test_open_aligned() {
+        let file_path = String::from("data_open_aligned.db");
+        let _ = fs::remove_file(&file_path);
+        let error = Bucket::open_aligned(file_path.clone(), 0).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let mut bucket = Bucket::open_aligned(file_path.clone(), 64).unwrap();
+        let assert_aligned = |bucket: &mut Bucket| {
+            let (_, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+            for block_info in get_list_block_info(&list_block_data) {
+                assert_eq!(block_info.start % 64, 0, "{:?}", block_info);
+            }
+            assert!(bucket.check_overlaps().is_empty());
+        };
 
-        let limit = 10u8;
-        let skip = 0usize;
-        let list_block = bucket.list_next(limit, skip);
+        for i in 0..20 {
+            let key = format!("key-{:02}", i).into_bytes();
+            bucket.set(key, vec![b'v'; 5 + i * 7]).unwrap();
+        }
+        for i in (0..20).step_by(3) {
+            bucket.delete(format!("key-{:02}", i).into_bytes()).unwrap();
+        }
+        bucket.set(b"key-20".to_vec(), vec![b'v'; 10]).unwrap();
+        bucket
+            .set_many(vec![
+                (b"key-21".to_vec(), vec![b'v'; 30]),
+                (b"key-22".to_vec(), vec![b'v'; 100]),
+            ])
+            .unwrap();
+        bucket.push(b"event".to_vec()).unwrap();
+        assert_aligned(&mut bucket);
 
-        assert_eq!(list_block.len() > 0, true);
+        bucket.compact_key(b"key-19".to_vec()).unwrap();
+        assert_aligned(&mut bucket);
+        while bucket.compact_step(2).unwrap() {
+            assert_aligned(&mut bucket);
+        }
+        assert_aligned(&mut bucket);
+        bucket.compact().unwrap();
+        assert_aligned(&mut bucket);
+        assert_eq!(
+            fs::metadata(&file_path).unwrap().len() as usize,
+            bucket.compacted_size()
+        );
+        for i in (1..20).filter(|i| i % 3 != 0) {
+            let key = format!("key-{:02}", i).into_bytes();
+            assert_eq!(bucket.get_value(key), Some(vec![b'v'; 5 + i * 7]));
+        }
+        assert_eq!(bucket.get_value(b"key-22".to_vec()), Some(vec![b'v'; 100]));
+        fs::remove_file(&file_path).unwrap();
+
+        let mut bucket = Bucket::open_aligned(file_path.clone(), 64).unwrap();
+        bucket
+            .bulk_load((0..10).map(|i| (format!("key-{:02}", i).into_bytes(), vec![b'v'; i])))
+            .unwrap();
+        assert_aligned(&mut bucket);
+        assert_eq!(bucket.get_value(b"key-09".to_vec()), Some(vec![b'v'; 9]));
+
+        fs::remove_file(file_path).unwrap()
     }
 
-    fn find_next_data() {
-        let file_path = String::from("data.db");
-        let mut bucket = Bucket::new(file_path).unwrap();
+    #[test]
+    fn test_open_with_buffers() {
+        let file_path = String::from("data_open_with_buffers.db");
+        let clone_path = String::from("data_open_with_buffers_clone.db");
+        let _ = fs::remove_file(&file_path);
+        let _ = fs::remove_file(&clone_path);
+        let error = Bucket::open_with_buffers(file_path.clone(), 0, 8)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let error = Bucket::open_with_buffers(file_path.clone(), 8, 0)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
 
-        let test_key: Vec<u8> = String::from("test-key-001-99999999999999").into_bytes();
-        let limit = 10u8;
-        let only_after_key = false;
-        // let only_after_key = true;
-        let list_block = bucket.find_next(test_key, limit, only_after_key);
+        // buffer nhỏ hơn một block: mỗi block cần nhiều lần đọc và flush
+        let mut bucket = Bucket::open_with_buffers(file_path.clone(), 7, 3).unwrap();
+        let mut plain = Bucket::new(file_path.clone()).unwrap();
+        bucket
+            .bulk_load((0..30).map(|i| (format!("key-{:02}", i).into_bytes(), vec![b'v'; i])))
+            .unwrap();
+        for i in (0..30).step_by(4) {
+            bucket.delete(format!("key-{:02}", i).into_bytes()).unwrap();
+        }
+        bucket.set(b"key-30".to_vec(), vec![b'w'; 40]).unwrap();
+        let assert_same = |bucket: &mut Bucket, plain: &mut Bucket| {
+            assert_eq!(bucket.list(100), plain.list(100));
+            assert_eq!(bucket.list_next(5, 3), plain.list_next(5, 3));
+            assert_eq!(
+                bucket.find_next(b"key-10".to_vec(), 6, true),
+                plain.find_next(b"key-10".to_vec(), 6, true)
+            );
+            let mut raw = Vec::new();
+            bucket.export_raw(&mut raw).unwrap();
+            let mut plain_raw = Vec::new();
+            plain.export_raw(&mut plain_raw).unwrap();
+            assert_eq!(raw, plain_raw);
+        };
+        assert_eq!(bucket.list(100).len(), 23);
+        assert_same(&mut bucket, &mut plain);
 
-        assert_eq!(list_block.len() > 0, true);
+        bucket.compact().unwrap();
+        plain.reopen().unwrap();
+        assert_eq!(
+            fs::metadata(&file_path).unwrap().len() as usize,
+            bucket.compacted_size()
+        );
+        assert_same(&mut bucket, &mut plain);
+        for i in (0..30).filter(|i| i % 4 != 0) {
+            let key = format!("key-{:02}", i).into_bytes();
+            assert_eq!(bucket.get_value(key), Some(vec![b'v'; i]));
+        }
+
+        let mut clone = bucket.clone_to(clone_path.clone()).unwrap();
+        assert_eq!(clone.list(100), plain.list(100));
+        let mut frozen = bucket.freeze().unwrap();
+        assert_eq!(frozen.list(100), plain.list(100));
+
+        fs::remove_file(format!("{}.frozen", file_path)).unwrap();
+        fs::remove_file(clone_path).unwrap();
+        fs::remove_file(file_path).unwrap()
     }
 
-    fn delete_to_data() {
-        let file_path = String::from("data.db");
-        let mut bucket = Bucket::new(file_path).unwrap();
+    #[test]
+    fn test_migrate_v0_to_v1() {
+        let file_path = String::from("data_migrate_v0_to_v1.db");
+        let _ = fs::remove_file(&file_path);
+        let mut bucket = Bucket::new(file_path.clone()).unwrap();
+        for i in 0..20 {
+            let key = format!("key-{:03}", i).into_bytes();
+            bucket.set(key, vec![b'v'; 100 + i]).unwrap();
+        }
+        bucket.set_meta("tag", b"meta").unwrap();
 
-        let test_key: Vec<u8> = String::from("test-key-001-99999999999999").into_bytes();
-        let also_delete_the_found_block = true;
-        // let also_delete_the_found_block = false;
-        let error = bucket
-            .delete_to(test_key, also_delete_the_found_block)
-            .is_err();
+        // ghi lại header theo dạng cũ (không có checksum), giữ vùng meta
+        let (start_list_point, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        let mut header = group_digits_to_vec(start_list_point);
+        header.push(END);
+        header.extend(group_digits_to_vec(list_block_data.len()));
+        header.push(END);
+        header.resize(80, 0);
+        bucket.writer.seek(Start(0)).unwrap();
+        bucket.writer.write_all(&header).unwrap();
+        assert!(bucket.needs_migration());
 
-        assert_eq!(error, false);
+        migrate_v0_to_v1(&file_path).unwrap();
+        assert_eq!(bucket.format_version().unwrap(), 1);
+        assert!(!bucket.needs_migration());
+        assert_eq!(
+            get_list_config(&mut bucket.reader).unwrap().1,
+            list_block_data
+        );
+        assert_eq!(bucket.get_meta("tag"), Some(b"meta".to_vec()));
+        for i in 0..20 {
+            let key = format!("key-{:03}", i).into_bytes();
+            assert_eq!(bucket.get_value(key), Some(vec![b'v'; 100 + i]));
+        }
+        // file đã ở dạng mới: không đổi gì
+        let before = fs::read(&file_path).unwrap();
+        migrate_v0_to_v1(&file_path).unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), before);
+
+        let error = migrate_v0_to_v1("data_migrate_v0_to_v1_missing.db")
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+
+        fs::remove_file(file_path).unwrap()
     }
 
-    fn get_list_and_delete_list_data() {
-        let file_path = String::from("data.db");
-        let mut bucket = Bucket::new(file_path).unwrap();
+    #[test]
+    fn test_open_with() {
+        let file_path = String::from("data_open_with.db");
+        let other_path = String::from("data_open_with_other.db");
+        let _ = fs::remove_file(&file_path);
+        let _ = fs::remove_file(&other_path);
+        for options in [
+            BucketOptions {
+                lookup_cache: Some(0),
+                ..Default::default()
+            },
+            BucketOptions {
+                alignment: Some(0),
+                ..Default::default()
+            },
+            BucketOptions {
+                write_buffer: Some(0),
+                ..Default::default()
+            },
+        ] {
+            let error = Bucket::open_with(file_path.clone(), options).err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        }
+        assert!(fs::metadata(&file_path).is_err());
 
-        let limit = 10u8;
-        let list_block = bucket.list_lock_delete(limit).unwrap();
+        // các option kết hợp được với nhau
+        let options = BucketOptions {
+            max_entries: Some(5),
+            auto_reopen: true,
+            size_classes: true,
+            alignment: Some(64),
+            read_buffer: Some(7),
+            write_buffer: Some(3),
+            ..Default::default()
+        };
+        let mut bucket = Bucket::open_with(file_path.clone(), options).unwrap();
+        for i in 0..10 {
+            let key = format!("key-{:02}", i).into_bytes();
+            bucket.set(key, vec![b'v'; 5 + i]).unwrap();
+        }
+        let list_key: Vec<Vec<u8>> = bucket.list(255).into_iter().map(|(k, _)| k).collect();
+        assert_eq!(
+            list_key,
+            (5..10)
+                .map(|i| format!("key-{:02}", i).into_bytes())
+                .collect::<Vec<_>>()
+        );
+        let (_, list_block_data) = get_list_config(&mut bucket.reader).unwrap();
+        for block_info in get_list_block_info(&list_block_data) {
+            assert_eq!(block_info.start % 64, 0, "{:?}", block_info);
+        }
 
-        assert_eq!(list_block.len() > 0, true);
+        let mut other = Bucket::new(other_path.clone()).unwrap();
+        other.set(b"key-new".to_vec(), b"new".to_vec()).unwrap();
+        fs::rename(&other_path, &file_path).unwrap();
+        #[cfg(unix)]
+        assert_eq!(bucket.get_value(b"key-new".to_vec()), Some(b"new".to_vec()));
+
+        fs::remove_file(file_path).unwrap()
     }
 
-    fn delete_bucket() {
-        let file_path = String::from("data.db");
+    #[test]
+    fn test_delete_mode_tombstone() {
+        let file_path = String::from("data_delete_mode_tombstone.db");
+        let _ = fs::remove_file(&file_path);
+        let options = BucketOptions {
+            delete_mode: DeleteMode::Tombstone,
+            ..Default::default()
+        };
+        let mut bucket = Bucket::open_with(file_path.clone(), options).unwrap();
+        for i in 1..=3 {
+            bucket
+                .set(format!("key-00{}", i).into_bytes(), vec![b'v'; 20])
+                .unwrap();
+        }
+        bucket.delete(b"key-002".to_vec()).unwrap();
+        assert!(bucket.get(b"key-002".to_vec()).0.is_empty());
+        assert_eq!(bucket.get_value(b"key-002".to_vec()), None);
+        assert_eq!(bucket.list(255).len(), 2);
+        assert_eq!(bucket.list_tombstones(255), vec![b"key-002".to_vec()]);
+        // vùng value của key đã xoá được trả lại
+        assert_eq!(bucket.free_list_summary().holes, 1);
+
+        // xoá key không có: không đổi gì
+        bucket.delete(b"key-009".to_vec()).unwrap();
+        assert_eq!(bucket.list_tombstones(255), vec![b"key-002".to_vec()]);
+
+        // set lại rồi xoá lại: chỉ còn một tombstone, ở cuối list
+        bucket
+            .set(b"key-002".to_vec(), b"value-002".to_vec())
+            .unwrap();
+        assert_eq!(
+            bucket.get_value(b"key-002".to_vec()),
+            Some(b"value-002".to_vec())
+        );
+        bucket.delete(b"key-001".to_vec()).unwrap();
+        bucket.delete(b"key-002".to_vec()).unwrap();
+        assert_eq!(
+            bucket.list_tombstones(255),
+            vec![b"key-001".to_vec(), b"key-002".to_vec()]
+        );
+        assert_eq!(bucket.list_tombstones(1), vec![b"key-001".to_vec()]);
+
+        // repair và compact giữ tombstone
+        assert_eq!(bucket.repair().unwrap(), 0);
+        bucket.compact().unwrap();
+        assert_eq!(
+            bucket.list_tombstones(255),
+            vec![b"key-001".to_vec(), b"key-002".to_vec()]
+        );
+        assert_eq!(bucket.get_value(b"key-001".to_vec()), None);
+        assert_eq!(
+            bucket.list(255),
+            vec![(b"key-003".to_vec(), vec![b'v'; 20])]
+        );
+        assert!(bucket.check_overlaps().is_empty());
+
+        // DeleteMode::Remove (mặc định) không để lại tombstone
+        let mut plain = Bucket::new(file_path.clone()).unwrap();
+        plain.delete(b"key-003".to_vec()).unwrap();
+        assert_eq!(
+            plain.list_tombstones(255),
+            vec![b"key-001".to_vec(), b"key-002".to_vec()]
+        );
+        assert!(plain.list(255).is_empty());
+
         fs::remove_file(file_path).unwrap()
     }
 }